@@ -1,53 +1,339 @@
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use brickadia::{
-    save::{BrickOwner, SaveData},
+    read::SaveReader,
+    save::SaveData,
     write::SaveWriter,
 };
-use brickadia::save::{User};
-use create_vox::{VoxFile};
-use clap::{Parser};
-use vox2brs::{vox2brs, BrickOutputMode};
+use brickadia::save::{BrickColor, Color, User};
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use vox2brs::{brick_bounds, convert_source_with_progress, BrickAsset, Axis, BrickCollision, BrickMaterial, BrickOutputMode, ConversionStats, ProgressEvent, RampifyOptions, Vox2BrsError, Vox2BrsOptions, DEFAULT_BRICK_ASSETS};
+use vox2brs::palette::{self, parse_hex_palette};
+use vox2brs::schematic::{parse_block_colors, BlockColors, SchematicFile};
+use vox2brs::simplify::{FillStrategy, HollowMode};
+use vox2brs::source::{load_source, CombinedSource, VoxelSource, SUPPORTED_EXTENSIONS};
+
+#[cfg(feature = "server")]
+mod server;
+
+fn is_supported_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| SUPPORTED_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
 
 fn valid_brs_path(string: &str) -> Result<PathBuf, &'static str> {
-    if !string.ends_with(".brs") {
+    if string != "-" && !string.ends_with(".brs") && !Path::new(string).is_dir() {
         return Err("Invalid path to brs.");
     }
+
+    if string.ends_with(".brs") {
+        // A bare file name has an empty parent, meaning the current directory.
+        let parent = Path::new(string).parent().unwrap_or_else(|| Path::new(""));
+        if !parent.as_os_str().is_empty() && !parent.is_dir() {
+            return Err("Output directory doesn't exist.");
+        }
+    }
+
     Ok(string.into())
 }
 
 fn valid_vox_path(string: &str) -> Result<PathBuf, &'static str> {
-    if !string.ends_with(".vox") {
+    let path: PathBuf = string.into();
+    if !path.is_dir() && !is_supported_file(&path) {
         return Err("Invalid path to vox.");
     }
-    let path: PathBuf = string.into();
     if path.exists() {
         return Ok(path);
     }
     Err("Input file doesn't exist.")
 }
 
-/// Convert MagicaVoxel models into a BRS file.
+fn valid_author_id(string: &str) -> Result<Uuid, String> {
+    string.parse().map_err(|_| format!("Invalid author id {}, expected a UUID like a8033bee-6c37-4118-b4a6-cecc1d966133.", string))
+}
+
+fn palette_out_path(string: &str) -> Result<PathBuf, &'static str> {
+    let path = PathBuf::from(string);
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png" | "txt" | "json") => Ok(path),
+        _ => Err("The palette must be written to a .png, .txt or .json file."),
+    }
+}
+
+/// Parses `name=uuid` into a brick owner.
+fn brick_owner(string: &str) -> Result<User, String> {
+    let (name, id) = string
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid owner {}, expected name=uuid.", string))?;
+
+    Ok(User { name: name.trim().into(), id: valid_author_id(id.trim())? })
+}
+
+/// Parses `WxL` into a width and length.
+fn baseplate_size(string: &str) -> Result<(u32, u32), String> {
+    string
+        .split_once('x')
+        .and_then(|(w, l)| Some((w.trim().parse().ok()?, l.trim().parse().ok()?)))
+        .ok_or_else(|| format!("Invalid baseplate size {}, expected WxL.", string))
+}
+
+/// Parses `index=material` into a palette index and brick material.
+fn color_material(string: &str) -> Result<(u32, BrickMaterial), String> {
+    let (index, material) = string
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid material {}, expected index=material.", string))?;
+
+    let index = index.trim().parse()
+        .map_err(|_| format!("Invalid palette index {}.", index))?;
+    let material = BrickMaterial::from_str(material.trim(), true)?;
+
+    Ok((index, material))
+}
+
+/// Parses a rotation in degrees into quarter turns.
+fn quarter_turns(string: &str) -> Result<u8, String> {
+    match string.trim() {
+        "0" => Ok(0),
+        "90" => Ok(1),
+        "180" => Ok(2),
+        "270" => Ok(3),
+        _ => Err(format!("Invalid rotation {}, expected 0, 90, 180 or 270.", string)),
+    }
+}
+
+/// Parses `N` or `WxLxH` into the simplifier's largest brick size, in voxels.
+fn max_box_size(string: &str) -> Result<(usize, usize, usize), String> {
+    let parts: Vec<usize> = string
+        .split('x')
+        .map(|part| part.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid size {}, expected N or WxLxH.", string))?;
+
+    let size = match parts[..] {
+        [n] => (n, n, n),
+        [w, l, h] => (w, l, h),
+        _ => return Err(format!("Invalid size {}, expected N or WxLxH.", string)),
+    };
+
+    if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+        return Err("Simplified bricks must be at least 1 voxel in every direction.".into());
+    }
+
+    Ok(size)
+}
+
+/// Placeholders an output name template can use.
+const OUTPUT_NAME_PLACEHOLDERS: &[&str] = &["{stem}", "{mode}", "{width}", "{height}"];
+
+/// Checks that an output name template makes `.brs` paths inside the output directory.
+fn output_name_template(string: &str) -> Result<String, String> {
+    if !string.ends_with(".brs") {
+        return Err(format!("Invalid output name {}, it must end in .brs.", string));
+    }
+
+    let mut rest = string.to_string();
+    for placeholder in OUTPUT_NAME_PLACEHOLDERS {
+        rest = rest.replace(placeholder, "");
+    }
+
+    if rest.contains('{') || rest.contains('}') {
+        return Err(format!("Invalid output name {}, the placeholders are {}.", string, OUTPUT_NAME_PLACEHOLDERS.join(", ")));
+    }
+
+    let stays_inside = Path::new(string)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if !stays_inside {
+        return Err(format!("Invalid output name {}, it must stay inside the output directory.", string));
+    }
+
+    Ok(string.into())
+}
+
+/// Parses an `x,y,z` voxel offset.
+fn voxel_offset(string: &str) -> Result<(i32, i32, i32), String> {
+    let parts: Vec<i32> = string
+        .split(',')
+        .map(|part| part.trim().parse::<i32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid offset {}, expected x,y,z.", string))?;
+
+    match parts[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(format!("Invalid offset {}, expected x,y,z.", string)),
+    }
+}
+
+/// Parses `start..end` or `start..=end` into a start layer and the layer after the last.
+fn layer_range(string: &str) -> Result<(u32, u32), String> {
+    let error = || format!("Invalid layers {}, expected start..end or start..=end.", string);
+
+    let (start, end) = string.split_once("..").ok_or_else(error)?;
+    let (end, inclusive) = match end.strip_prefix('=') {
+        Some(end) => (end, true),
+        None => (end, false),
+    };
+
+    let start: u32 = start.trim().parse().map_err(|_| error())?;
+    let end: u32 = end.trim().parse().map_err(|_| error())?;
+    let end = if inclusive { end.checked_add(1).ok_or_else(error)? } else { end };
+
+    if start >= end {
+        return Err(format!("Invalid layers {}, the range is empty.", string));
+    }
+
+    Ok((start, end))
+}
+
+/// Parses `path` or `path@x,y,z` into a path and voxel offset.
+fn merge_input(string: &str) -> Result<(PathBuf, (i32, i32, i32)), String> {
+    let (path, offset) = match string.rsplit_once('@') {
+        Some((path, offset)) => (path, voxel_offset(offset)?),
+        None => (string, (0, 0, 0)),
+    };
+
+    Ok((valid_vox_path(path)?, offset))
+}
+
+fn valid_existing_brs_path(string: &str) -> Result<PathBuf, &'static str> {
+    let path: PathBuf = string.into();
+    if !string.ends_with(".brs") {
+        return Err("Invalid path to brs.");
+    }
+    if path.is_file() {
+        return Ok(path);
+    }
+    Err("Save file doesn't exist.")
+}
+
+/// Convert voxel models into BRS files, or look inside them.
 #[derive(Parser, Debug)]
-struct Args {
-    /// Input path to .vox file.
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Names that pick a subcommand. Anything else is read as the arguments of `convert`.
+const SUBCOMMANDS: [&str; 7] = ["convert", "info", "inspect", "serve", "help", "-h", "--help"];
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a voxel file into a BRS file. Used when no subcommand is given.
+    Convert(ConvertArgs),
+
+    /// Print the models, dimensions and palette of a voxel file without converting it.
+    Info(InfoArgs),
+
+    /// Print the brick and color counts of an existing BRS file.
+    Inspect(InspectArgs),
+
+    /// Convert voxel files sent over TCP, answering with the .brs bytes.
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+}
+
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// Input path to .vox, .qb, .vxm or Minecraft .schematic file.
+    #[clap(parse(try_from_str = valid_vox_path))]
+    input: PathBuf,
+
+    /// Colors of Minecraft blocks in .schematic files, replacing the built-in table.
+    #[clap(long)]
+    block_colors: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// Path to the .brs file.
+    #[clap(parse(try_from_str = valid_existing_brs_path))]
+    input: PathBuf,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on, like 127.0.0.1:7070.
+    addr: String,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// Input path to .vox, .qb, .vxm or Minecraft .schematic file, or a directory to convert every file inside.
     #[clap(required = true, parse(try_from_str = valid_vox_path))]
     input: PathBuf,
 
-    /// Output directory of the converted .brs file.
+    /// Output path of the converted .brs file, - to write to stdout, or a directory in batch mode.
     #[clap(required = true, parse(try_from_str = valid_brs_path))]
     output: PathBuf,
 
+    /// Name of each save written into an output directory, which can contain subfolders.
+    /// {stem}, {mode}, {width} and {height} are replaced for every file.
+    #[clap(long, default_value = "{stem}.brs", parse(try_from_str = output_name_template))]
+    output_name: String,
+
     /// How voxels are interpreted.
     #[clap(arg_enum, default_value_t = BrickOutputMode::Brick)]
     mode: BrickOutputMode,
 
-    /// Width of the output brick.
+    /// Width of the output brick, in studs or microbricks.
     width: Option<u32>,
 
-    /// Height of the output brick.
+    /// Height of the output brick, in bricks, plates or microbricks depending on the mode.
     height: Option<u32>,
 
+    /// Depth of the output brick along Y, in studs or microbricks. Defaults to the width.
+    #[clap(long)]
+    depth: Option<u32>,
+
+    /// Axis pointing up in the input. MagicaVoxel is Z-up.
+    #[clap(long, arg_enum, default_value_t = Axis::Z)]
+    up_axis: Axis,
+
+    /// Mirror each model along its X axis.
+    #[clap(long)]
+    mirror_x: bool,
+
+    /// Mirror each model along its Y axis.
+    #[clap(long)]
+    mirror_y: bool,
+
+    /// Mirror each model along its Z axis.
+    #[clap(long)]
+    mirror_z: bool,
+
+    /// Turn each voxel into a block of this many bricks per side.
+    #[clap(long, default_value_t = 1)]
+    scale: u32,
+
+    /// Only convert voxels with an uncovered side, leaving out the model's inside.
+    #[clap(long)]
+    surface_only: bool,
+
+    /// Put studs only on bricks with nothing on top of them, using the brick asset for them
+    /// and the tile asset for the rest. Microbricks have no studs, so it does nothing in micro mode.
+    #[clap(long)]
+    studs: bool,
+
+    /// Project the build onto a single layer across this axis (z for the top view), keeping
+    /// the first voxel seen from that side. Good for pixel art plates.
+    #[clap(long, arg_enum)]
+    flatten: Option<Axis>,
+
     /// Should we run the simplifier?
     #[clap(short, long)]
     simplify: bool,
@@ -55,65 +341,1069 @@ struct Args {
     /// Run rampifier?
     #[clap(short, long)]
     rampify: bool,
+
+    /// Also put ramps on stepped walls, not just floors and ceilings. Needs --rampify.
+    #[clap(long, requires = "rampify")]
+    ramp_walls: bool,
+
+    /// Put wedges on steps one voxel tall and keep the rest as bricks, a lighter alternative to --rampify.
+    #[clap(long, conflicts_with = "rampify")]
+    smooth_edges: bool,
+
+    /// Refill the bricks of each color in whichever order makes the fewest once the model is
+    /// filled, like the ones left apart around ramps. Only used when simplifying or rampifying.
+    #[clap(long)]
+    weld: bool,
+
+    /// How the simplifier merges voxels into bricks.
+    #[clap(long, arg_enum, default_value_t = FillStrategy::HeightFirst)]
+    fill_strategy: FillStrategy,
+
+    /// Try every order of growing bricks on a sample of each model and use the one making the
+    /// fewest bricks, in place of --fill-strategy. Use --verbose to see the chosen orders.
+    #[clap(long)]
+    optimize_axis_order: bool,
+
+    /// Largest brick the simplifier makes, in voxels (N or WxLxH).
+    #[clap(long, default_value = "64", parse(try_from_str = max_box_size))]
+    simplify_max_size: (usize, usize, usize),
+
+    /// Simplify in cubes of this many voxels at a time, using less memory on large models.
+    /// Bricks can't cross cube edges unless they line up.
+    #[clap(long)]
+    chunk_size: Option<usize>,
+
+    /// Leave out simplified bricks made from fewer than this many voxels. Implies --simplify.
+    #[clap(long, default_value = "1")]
+    min_brick_volume: usize,
+
+    /// Recolor bricks below --min-brick-volume to match their neighbors instead of leaving them out.
+    #[clap(long)]
+    merge_small_bricks: bool,
+
+    /// Merge voxels into bricks regardless of their color, for builds painted in-game. Implies --simplify.
+    #[clap(long)]
+    no_simplify_colors: bool,
+
+    /// Palette index of the color every brick gets with --no-simplify-colors. Defaults to the most common color.
+    #[clap(long, requires = "no-simplify-colors")]
+    simplify_color: Option<u32>,
+
+    /// Simplify colors within this RGB distance (0-441) of each other as one, so gradients merge
+    /// into fewer bricks. Each brick takes the color most of its voxels had. Implies --simplify.
+    #[clap(long)]
+    color_merge_tolerance: Option<f32>,
+
+    /// Give each model its own brick owner, so models stay separate groups in-game.
+    #[clap(long)]
+    preserve_model_grouping: bool,
+
+    /// Remove voxels that are enclosed on every side. Implies --simplify.
+    #[clap(long, conflicts_with = "fill-interior")]
+    hollow: bool,
+
+    /// Fill enclosed empty pockets inside the model. Implies --simplify.
+    #[clap(long)]
+    fill_interior: bool,
+
+    /// Keep palette alpha, using glass for colors with alpha below this value.
+    #[clap(long)]
+    alpha_threshold: Option<u8>,
+
+    /// Leave out voxels whose color is less opaque than this, counting glass transparency.
+    /// For marker colors that aren't meant to be built.
+    #[clap(long)]
+    palette_alpha_threshold: Option<u8>,
+
+    /// Convert colors into linear space. Makes colors darker in-game.
+    #[clap(long)]
+    gamma_correct: bool,
+
+    /// Leave colors of this palette index as they are with --gamma-correct, to match an in-game
+    /// color exactly. Can be repeated.
+    #[clap(long = "no-gamma", multiple_occurrences = true, requires = "gamma-correct")]
+    no_gamma_for_indices: Vec<u32>,
+
+    /// Make emissive colors glow, scaling their emission by this value.
+    #[clap(long)]
+    emissive: Option<f32>,
+
+    /// Snap every color to the closest color in this palette, which becomes the save's palette.
+    /// Either a voxel file or a text file with one hex color per line.
+    #[clap(long)]
+    match_palette: Option<PathBuf>,
+
+    /// Reduce the palette to at most this many colors, merging colors that look alike.
+    #[clap(long, conflicts_with = "match-palette")]
+    quantize: Option<usize>,
+
+    /// Mix colors with Floyd–Steinberg dithering across each layer when reducing the palette
+    /// with --quantize or --match-palette, so gradients don't turn into flat bands.
+    #[clap(long)]
+    dither: bool,
+
+    /// Give each brick its own color instead of using the save's palette.
+    #[clap(long)]
+    unique_colors: bool,
+
+    /// Write a save per palette color instead of one save, named <stem>_color<index>.brs
+    /// next to the output. Bricks with their own color, like ones from merged saves, go in
+    /// <stem>_unique.brs. The saves line up when loaded together.
+    #[clap(long, conflicts_with = "unique-colors")]
+    split_by_color: bool,
+
+    /// Move the whole build by this many voxels (x,y,z), on top of each model's own position.
+    /// In batch mode every file is moved by it. Use path@x,y,z with --merge to move a single file.
+    #[clap(long, allow_hyphen_values = true, conflicts_with = "center", parse(try_from_str = voxel_offset))]
+    offset: Option<(i32, i32, i32)>,
+
+    /// Colors of Minecraft blocks in .schematic files, one `id` or `id:data` and hex color per line
+    /// (e.g. 35:14 #A02722). Replaces the built-in table.
+    #[clap(long)]
+    block_colors: Option<PathBuf>,
+
+    /// Merge another voxel file into the save, optionally moved by an offset in voxels (path@x,y,z). Can be repeated.
+    #[clap(long, multiple_occurrences = true, parse(try_from_str = merge_input))]
+    merge: Vec<(PathBuf, (i32, i32, i32))>,
+
+    /// Leave out voxels of this palette index. Can be repeated.
+    #[clap(long = "skip-color", multiple_occurrences = true)]
+    skip_colors: Vec<u32>,
+
+    /// Only convert these Z layers of each model, as start..end (end left out) or start..=end.
+    /// Voxels keep their places, so saves of neighboring bands line up.
+    #[clap(long, alias = "layer-range", parse(try_from_str = layer_range))]
+    layers: Option<(u32, u32)>,
+
+    /// Description of the save. Defaults to the input file's name.
+    #[clap(long)]
+    description: Option<String>,
+
+    /// Name of the save's author and brick owner.
+    #[clap(long, default_value = "vox2brs")]
+    author_name: String,
+
+    /// UUID of the save's author and brick owner.
+    #[clap(long, default_value = "a8033bee-6c37-4118-b4a6-cecc1d966133", parse(try_from_str = valid_author_id))]
+    author_id: Uuid,
+
+    /// Share the bricks with another owner (name=uuid), splitting them evenly with the author. Can be repeated.
+    #[clap(long = "owner", multiple_occurrences = true, conflicts_with = "preserve-model-grouping", parse(try_from_str = brick_owner))]
+    owners: Vec<User>,
+
+    /// Use a material for bricks of a palette index (index=material, e.g. 3=metallic). Can be repeated.
+    #[clap(long = "material", multiple_occurrences = true, parse(try_from_str = color_material))]
+    materials: Vec<(u32, BrickMaterial)>,
+
+    /// Pick brick materials from the file's materials: metal, glass and emit become metallic, glass and glow.
+    #[clap(long)]
+    auto_materials: bool,
+
+    /// What the bricks collide with: all, pass-through (still usable with tools), no-weapons or none.
+    #[clap(long, arg_enum, default_value_t = BrickCollision::All)]
+    collision: BrickCollision,
+
+    /// Don't print progress, only errors.
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// Also print how long each stage of the conversion took.
+    #[clap(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Convert without writing, only reporting what would be written.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Write the simplifier's grid into this directory as a PNG per Z layer, for debugging.
+    #[cfg(feature = "debug-slices")]
+    #[clap(long)]
+    debug_slices: Option<PathBuf>,
+
+    /// Render a front and an isometric view of the converted save into this PNG.
+    #[cfg(feature = "thumbnail")]
+    #[clap(long)]
+    thumbnail: Option<PathBuf>,
+
+    /// Overwrite saves that already exist at the output path.
+    #[clap(short, long)]
+    force: bool,
+
+    /// Write a JSON file describing the conversion, for tools checking the output.
+    /// In batch mode it holds a list with an entry for every file.
+    #[clap(long)]
+    emit_json: Option<PathBuf>,
+
+    /// Write the save's final colors to a .png strip, or a .txt or .json list with each color's index.
+    #[clap(long, parse(try_from_str = palette_out_path))]
+    palette_out: Option<PathBuf>,
+
+    /// Center the build on the origin, with its base at Z 0.
+    #[clap(long)]
+    center: bool,
+
+    /// Snap the build's corner to Brickadia's stud grid.
+    #[clap(long)]
+    grid_align: bool,
+
+    /// Place a plate of this many bricks (WxL) under the build. It always extends past the build.
+    #[clap(long, parse(try_from_str = baseplate_size))]
+    baseplate: Option<(u32, u32)>,
+
+    /// Palette index of the baseplate's color.
+    #[clap(long, default_value_t = 0)]
+    baseplate_color: u32,
+
+    /// Rotate the whole build around the Z axis by 0, 90, 180 or 270 degrees.
+    #[clap(long, default_value = "0", parse(try_from_str = quarter_turns))]
+    rotate: u8,
+
+    /// Fail instead of writing a save with more bricks than this.
+    #[clap(long)]
+    max_bricks: Option<usize>,
+
+    /// Fail if a model is more voxels across than this, so huge models don't hang the conversion.
+    #[clap(long)]
+    max_dimension: Option<u32>,
+
+    /// Shrink models larger than --max-dimension until they fit instead of failing, giving each
+    /// block of voxels the color most of them have.
+    #[clap(long, requires = "max-dimension")]
+    downsample: bool,
+
+    /// BRS save version to write. Older versions can't store glowing colors (9) or components (8).
+    #[clap(long)]
+    save_version: Option<u16>,
+
+    /// Brick asset list of the save, replacing the default assets. Can be repeated.
+    /// Asset names given to the other asset options must be in it.
+    #[clap(long = "brick-asset-list", multiple_occurrences = true)]
+    brick_assets: Vec<String>,
+
+    /// Use the brick asset list of this existing save, so bricks reference the same assets.
+    /// Assets not picked with the options below are looked up by their default names in it.
+    #[clap(long, parse(try_from_str = valid_existing_brs_path))]
+    assets_from_save: Option<PathBuf>,
+
+    /// Also take the author from the --assets-from-save save, who owns the bricks. The save's
+    /// other brick owners aren't copied, as none of the bricks would be theirs.
+    #[clap(long, requires = "assets-from-save")]
+    author_from_save: bool,
+
+    /// Asset for bricks and plates, as an index into the asset list or an asset name.
+    #[clap(long)]
+    brick_asset: Option<String>,
+
+    /// Asset for microbricks, as an index into the asset list or an asset name.
+    #[clap(long)]
+    microbrick_asset: Option<String>,
+
+    /// Asset for tiles, as an index into the asset list or an asset name.
+    #[clap(long)]
+    tile_asset: Option<String>,
+
+    /// Asset for ramps, as an index into the asset list or an asset name.
+    #[clap(long)]
+    ramp_asset: Option<String>,
+
+    /// Asset for wedges, as an index into the asset list or an asset name.
+    #[clap(long)]
+    wedge_asset: Option<String>,
+}
+
+impl ConvertArgs {
+    /// Prints progress, keeping stdout clean when the save is written to it.
+    fn info(&self, message: impl Display) {
+        if self.quiet {
+            return;
+        }
+
+        if self.output.as_os_str() == "-" {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+/// Reads an asset flag as an index into the asset list if it's a number, or an asset name otherwise.
+fn asset_flag(asset: &Option<String>, default: u32) -> BrickAsset {
+    match asset {
+        Some(asset) => asset.parse().map(BrickAsset::Index).unwrap_or_else(|_| BrickAsset::Name(asset.clone())),
+        None => BrickAsset::Index(default),
+    }
+}
+
+/// Loads a voxel file, coloring schematics with `block_colors` when it's given.
+fn load(input: &Path, block_colors: Option<&BlockColors>) -> Result<Box<dyn VoxelSource>, String> {
+    let is_schematic = input
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("schematic"));
+
+    let source = match block_colors {
+        Some(colors) if is_schematic => fs::read(input)
+            .and_then(|bytes| SchematicFile::read_with_colors(&bytes, colors))
+            .map(|schematic| Box::new(schematic) as Box<dyn VoxelSource>),
+        _ => load_source(input),
+    };
+
+    source.map_err(|error| format!("Could not read {}: {}", input.display(), error))
 }
 
-fn main() -> Result<(), &'static str> {
-    let args = Args::parse();
+/// Reads a table of Minecraft block colors.
+fn load_block_colors(path: &Path) -> Result<BlockColors, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
 
-    let public = User {
-        name: "vox2brs".into(),
-        id: "a8033bee-6c37-4118-b4a6-cecc1d966133".parse().unwrap(),
+    parse_block_colors(&text)
+        .map_err(|error| format!("Could not read {}: {}", path.display(), error))
+}
+
+/// Reads the palette of a voxel file, or a text file of hex colors.
+fn load_palette(path: &Path) -> Result<Vec<Color>, String> {
+    let palette = if is_supported_file(path) {
+        load(path, None)?.palette()
+    } else {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        parse_hex_palette(&text)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?
     };
 
-    let mut save = SaveData::default();
+    if palette.is_empty() {
+        return Err(format!("Palette {} has no colors.", path.display()));
+    }
+
+    Ok(palette)
+}
+
+fn convert(args: &ConvertArgs, input: &Path, cancel: &Arc<AtomicBool>) -> Result<(SaveData, ConversionStats), String> {
+    let block_colors = args.block_colors.as_deref().map(load_block_colors).transpose()?;
+
+    let vox_data = if args.merge.is_empty() {
+        load(input, block_colors.as_ref())?
+    } else {
+        let mut combined = CombinedSource::new();
+        combined.push(load(input, block_colors.as_ref())?, (0, 0, 0));
+
+        for (path, offset) in &args.merge {
+            combined.push(load(path, block_colors.as_ref())?, *offset);
+        }
 
-    // set the first header
-    save.header1.author = public.clone();
-    save.header1.host = Some(public.clone());
-    save.header1.description = "Converted .vox file.".into();
+        Box::new(combined)
+    };
 
-    // set the second header
-    save.header2
-        .brick_owners
-        .push(BrickOwner::from_user_bricks(public.clone(), 100));
+    convert_source(args, vox_data, input, cancel)
+}
 
-    save.header2.brick_assets =
-        vec![
-            "PB_DefaultBrick".into(),
-            "PB_DefaultMicroBrick".into(),
-            "PB_DefaultRamp".into(),
-            "PB_DefaultWedge".into(),
-        ];
+/// Converts voxels that are already loaded. `input` names the file in the default description.
+fn convert_source(args: &ConvertArgs, vox_data: Box<dyn VoxelSource>, input: &Path, cancel: &Arc<AtomicBool>) -> Result<(SaveData, ConversionStats), String> {
+    // Warnings are shown even when quiet.
+    let mut progress = |event: ProgressEvent| match event {
+        ProgressEvent::Warning(_) => eprintln!("{}", event),
+        ProgressEvent::GrowOrderChosen { .. } if !args.verbose => {},
+        _ => args.info(event),
+    };
 
-    // In case this changes in the future... it should already be empty.
-    save.header2.colors.clear();
+    let template = args.assets_from_save.as_deref().map(read_save).transpose()?;
 
-    let vox_data = VoxFile::load(&args.input.into_os_string().into_string().unwrap());
+    if template.is_some() && !args.brick_assets.is_empty() {
+        return Err("--assets-from-save and --brick-asset-list can't be used together.".into());
+    }
 
-    let result = vox2brs(vox_data, save, args.mode, args.width, args.height, args.simplify, args.rampify, 0, 1, 2, 3);
+    let author = match &template {
+        Some(template) if args.author_from_save => template.header1.author.clone(),
+        _ => User {
+            name: args.author_name.clone(),
+            id: args.author_id,
+        },
+    };
 
-    match result {
-        Ok(out_save) => {
-            println!("\nWriting save file...");
-            let file = File::create(&args.output);
+    // Assets that aren't picked are found by name in a template, whose list can be in any order.
+    let asset = |flag: &Option<String>, default: usize| match (flag, &template) {
+        (None, Some(_)) => BrickAsset::Name(DEFAULT_BRICK_ASSETS[default].into()),
+        _ => asset_flag(flag, default as u32),
+    };
 
-            match file {
-                Ok(file) => {
-                    SaveWriter::new(file, out_save)
-                        .write()
-                        .unwrap();
+    let brick_assets = if let Some(template) = &template {
+        template.header2.brick_assets.clone()
+    } else if args.brick_assets.is_empty() {
+        // Named assets that aren't in the default list are added to it.
+        let mut assets: Vec<String> = DEFAULT_BRICK_ASSETS.iter().map(|&asset| asset.into()).collect();
 
-                    println!("Save written to {}", args.output.into_os_string().into_string().unwrap());
-                },
-                Err(error) => {
-                    println!("Could not write to {}, {}", args.output.into_os_string().into_string().unwrap(), error.to_string())
+        for flag in [&args.brick_asset, &args.microbrick_asset, &args.tile_asset, &args.ramp_asset, &args.wedge_asset] {
+            if let BrickAsset::Name(name) = asset_flag(flag, 0) {
+                if !assets.contains(&name) {
+                    assets.push(name);
                 }
             }
+        }
+
+        assets
+    } else {
+        args.brick_assets.clone()
+    };
+
+    let reference_palette = args.match_palette.as_deref().map(load_palette).transpose()?;
+
+    let hollow_mode = if args.hollow {
+        HollowMode::RemoveInterior
+    } else if args.fill_interior {
+        HollowMode::FillInterior
+    } else {
+        HollowMode::None
+    };
+
+    let options = Vox2BrsOptions::default()
+        .author(author)
+        .description(args.description.clone())
+        .mode(args.mode)
+        .width(args.width)
+        .depth(args.depth)
+        .height(args.height)
+        .up_axis(args.up_axis)
+        .mirror(args.mirror_x, args.mirror_y, args.mirror_z)
+        .voxel_scale(args.scale)
+        .surface_only(args.surface_only)
+        .studs(args.studs)
+        .flatten(args.flatten)
+        .simplify(args.simplify || hollow_mode != HollowMode::None || args.min_brick_volume > 1 || args.no_simplify_colors || args.color_merge_tolerance.is_some())
+        .rampify(args.rampify)
+        .rampify_options(RampifyOptions { walls_x: args.ramp_walls, walls_y: args.ramp_walls, ..Default::default() })
+        .smooth_edges(args.smooth_edges)
+        .weld(args.weld)
+        .fill_strategy(args.fill_strategy)
+        .optimize_axis_order(args.optimize_axis_order)
+        .hollow(hollow_mode)
+        .max_box_size(args.simplify_max_size)
+        .chunk_size(args.chunk_size)
+        .min_brick_volume(args.min_brick_volume)
+        .merge_small_bricks(args.merge_small_bricks)
+        .merge_colors(args.no_simplify_colors)
+        .merged_color(args.simplify_color)
+        .color_merge_tolerance(args.color_merge_tolerance)
+        .preserve_model_grouping(args.preserve_model_grouping)
+        .extra_owners(args.owners.clone())
+        .alpha_threshold(args.alpha_threshold)
+        .palette_alpha_threshold(args.palette_alpha_threshold)
+        .gamma_correct(args.gamma_correct)
+        .no_gamma_for_indices(args.no_gamma_for_indices.clone())
+        .emissive_intensity(args.emissive)
+        .reference_palette(reference_palette)
+        .quantize(args.quantize)
+        .dither(args.dither)
+        .use_unique_colors(args.unique_colors)
+        .skip_color_indices(args.skip_colors.clone())
+        .z_range(args.layers)
+        .color_materials(args.materials.iter().copied().collect())
+        .auto_materials(args.auto_materials)
+        .collision(args.collision)
+        .global_offset(args.offset.unwrap_or((0, 0, 0)))
+        .center(args.center)
+        .grid_align(args.grid_align)
+        .baseplate(args.baseplate, args.baseplate_color)
+        .world_rotation(args.rotate)
+        .max_bricks(args.max_bricks)
+        .max_dimension(args.max_dimension, args.downsample)
+        .save_version(args.save_version)
+        .cancel(cancel.clone())
+        .brick_assets(brick_assets)
+        .assets(
+            asset(&args.brick_asset, 0),
+            asset(&args.microbrick_asset, 1),
+            asset(&args.tile_asset, 2),
+            asset(&args.ramp_asset, 3),
+            asset(&args.wedge_asset, 4),
+        );
+
+    #[cfg(feature = "debug-slices")]
+    let options = options.debug_slices(args.debug_slices.clone());
+
+    let (save, stats) = convert_source_with_progress(vox_data, input, &options, &mut progress)
+        .map_err(|error| match error {
+            Vox2BrsError::TooManyBricks { .. } if !args.simplify => {
+                format!("Could not convert vox to brs: {}. Try --simplify to merge voxels into fewer bricks.", error)
+            },
+            Vox2BrsError::UnknownAsset(_) | Vox2BrsError::InvalidAssetIndex(_) if template.is_some() => {
+                format!("Could not convert vox to brs: {}. Pick an asset the --assets-from-save save has with the asset options.", error)
+            },
+            _ => format!("Could not convert vox to brs: {}", error),
+        })?;
+
+    if args.no_simplify_colors {
+        args.info(format!("Ignoring colors saved {} bricks.", stats.merged_color_savings));
+    }
+
+    if args.weld {
+        args.info(format!("Welding took the bricks from {} down to {}.", stats.brick_count + stats.weld_savings, stats.brick_count));
+    }
+
+    if let Some(factor) = stats.downsample_factor {
+        args.info(format!("Downsampled models by a factor of {} to fit --max-dimension.", factor));
+    }
+
+    if args.verbose {
+        args.info("Time per stage:");
+
+        for (stage, time) in stage_timings(&stats) {
+            args.info(format!(" - {}: {:.3}s", stage, time.as_secs_f64()));
+        }
+    }
+
+    Ok((save, stats))
+}
+
+/// Each stage of a conversion with the time it took, in the order they run.
+fn stage_timings(stats: &ConversionStats) -> [(&'static str, Duration); 6] {
+    let timings = &stats.timings;
+
+    [
+        ("Colors", timings.colors),
+        ("Models", timings.models),
+        ("Simplify", timings.simplify),
+        ("Rampify", timings.rampify),
+        ("Offset", timings.offset),
+        ("Finish", timings.finish),
+    ]
+}
 
-            Ok(())
+/// Prints what a save contains, for dry runs.
+fn report(save: &SaveData, stats: &ConversionStats) {
+    println!("\nDry run, nothing was written.");
+    println!(" - Voxel bricks: {}", stats.voxel_brick_count);
+
+    if stats.interior_voxel_count > 0 {
+        println!(" - Interior voxels left out: {}", stats.interior_voxel_count);
+    }
+
+    println!(" - Bricks: {}", stats.brick_count);
+    println!(" - Ramps: {}", stats.ramp_count);
+
+    if stats.wedge_count > 0 {
+        println!(" - Smoothing wedges: {} ({} bricks without them)", stats.wedge_count, stats.brick_count - stats.wedge_count);
+    }
+
+    if stats.merged_voxel_count > 0 {
+        println!(" - Voxels merged into neighbors: {}", stats.merged_voxel_count);
+    }
+
+    if stats.dropped_voxel_count > 0 {
+        println!(" - Voxels in small bricks left out: {}", stats.dropped_voxel_count);
+    }
+    println!(" - Colors: {}", stats.color_count);
+
+    if let Some((min, max)) = brick_bounds(&save.bricks) {
+        println!(" - Size: {} x {} x {} units", max.0 - min.0, max.1 - min.1, max.2 - min.2);
+    }
+}
+
+/// Name of an enum value as it's written on the command line.
+fn arg_name(value: &impl ArgEnum) -> String {
+    value.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+/// Describes a conversion of `input` for the JSON sidecar. The saves written are added by `record_outputs`.
+fn conversion_json(args: &ConvertArgs, input: &Path, save: &SaveData, stats: &ConversionStats) -> Value {
+    let (default_width, default_height) = args.mode.default_size();
+    let width = args.width.unwrap_or(default_width);
+
+    let bounds = brick_bounds(&save.bricks).map(|(min, max)| json!({
+        "min": [min.0, min.1, min.2],
+        "max": [max.0, max.1, max.2],
+    }));
+
+    json!({
+        "input": input.display().to_string(),
+        "options": {
+            "mode": arg_name(&args.mode),
+            "width": width,
+            "depth": args.depth.unwrap_or(width),
+            "height": args.height.unwrap_or(default_height),
+            "up_axis": arg_name(&args.up_axis),
+            "scale": args.scale,
+            "simplify": args.simplify,
+            "rampify": args.rampify,
+            "smooth_edges": args.smooth_edges,
+            "fill_strategy": arg_name(&args.fill_strategy),
+            "optimize_axis_order": args.optimize_axis_order,
+            "collision": arg_name(&args.collision),
+            "min_brick_volume": args.min_brick_volume,
+            "merge_small_bricks": args.merge_small_bricks,
+            "no_simplify_colors": args.no_simplify_colors,
+            "color_merge_tolerance": args.color_merge_tolerance,
+            "preserve_model_grouping": args.preserve_model_grouping,
+            "hollow": args.hollow,
+            "fill_interior": args.fill_interior,
+            "surface_only": args.surface_only,
+            "studs": args.studs,
+            "flatten": args.flatten.as_ref().map(arg_name),
+            "gamma_correct": args.gamma_correct,
+            "no_gamma_for_indices": args.no_gamma_for_indices,
+            "center": args.center,
+            "grid_align": args.grid_align,
+            "layers": args.layers,
+            "offset": args.offset,
+            "rotate": args.rotate,
         },
-        Err(_) => {
-            Err("Could not convert vox to brs.")
+        "voxel_brick_count": stats.voxel_brick_count,
+        "interior_voxel_count": stats.interior_voxel_count,
+        "brick_count": stats.brick_count,
+        "ramp_count": stats.ramp_count,
+        "wedge_count": stats.wedge_count,
+        "dropped_voxel_count": stats.dropped_voxel_count,
+        "merged_voxel_count": stats.merged_voxel_count,
+        "merged_color_savings": stats.merged_color_savings,
+        "weld_savings": stats.weld_savings,
+        "color_count": stats.color_count,
+        "downsample_factor": stats.downsample_factor,
+        "out_of_range_voxel_count": stats.out_of_range_voxel_count,
+        "bounds": bounds,
+        "elapsed_seconds": stats.elapsed.as_secs_f64(),
+        "stage_seconds": stage_timings(stats)
+            .iter()
+            .map(|(stage, time)| (stage.to_lowercase(), json!(time.as_secs_f64())))
+            .collect::<serde_json::Map<_, _>>(),
+    })
+}
+
+/// Adds the saves written for a conversion to its JSON. `output` is the one save written, or null
+/// for dry runs and saves split by color, and `outputs` lists every save written.
+fn record_outputs(args: &ConvertArgs, entry: &mut Value, outputs: &[PathBuf]) {
+    let output = match outputs {
+        [output] if !args.split_by_color => Some(output.display().to_string()),
+        _ => None,
+    };
+
+    entry["output"] = json!(output);
+    entry["outputs"] = json!(outputs.iter().map(|output| output.display().to_string()).collect::<Vec<_>>());
+}
+
+/// Writes a save's colors to `output`, in the format its extension names.
+fn write_palette(colors: &[Color], output: &Path) -> Result<(), String> {
+    let error = |error: io::Error| format!("Could not write to {}, {}", output.display(), error);
+
+    match output.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => File::create(output)
+            .and_then(|file| palette::write_palette_png(colors, io::BufWriter::new(file)))
+            .map_err(error),
+        Some("json") => {
+            let entries = colors
+                .iter()
+                .enumerate()
+                .map(|(index, color)| json!({
+                    "index": index,
+                    "r": color.r,
+                    "g": color.g,
+                    "b": color.b,
+                    "a": color.a,
+                    "hex": palette::hex_color(color),
+                }))
+                .collect();
+
+            write_json(&Value::Array(entries), output)
+        },
+        _ => fs::write(output, palette::palette_text(colors)).map_err(error),
+    }
+}
+
+fn write_json(value: &Value, output: &Path) -> Result<(), String> {
+    let file = File::create(output)
+        .map_err(|error| format!("Could not write to {}, {}", output.display(), error))?;
+
+    serde_json::to_writer_pretty(file, value)
+        .map_err(|error| format!("Could not write to {}, {}", output.display(), error))
+}
+
+/// Fails if writing to `output` would replace an existing save without --force.
+/// Saves split by color are checked when they're written, as their colors aren't known yet.
+fn check_overwrite(args: &ConvertArgs, output: &Path) -> Result<(), String> {
+    if args.force || args.dry_run || args.split_by_color || output.as_os_str() == "-" || !output.exists() {
+        return Ok(());
+    }
+
+    Err(format!("{} already exists, use --force to overwrite it.", output.display()))
+}
+
+fn write_save(save: SaveData, output: &Path) -> Result<(), String> {
+    // Output name templates can put saves in subfolders.
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|error| format!("Could not create {}, {}", parent.display(), error))?;
+    }
+
+    vox2brs::write_save(save, output)
+        .map_err(|error| format!("Could not write to {}, {}", output.display(), error))
+}
+
+/// Path of the save for the color at `index` when splitting the save for `output` by color,
+/// or for the bricks with their own color when there's no index.
+fn color_output_path(output: &Path, index: Option<u32>) -> PathBuf {
+    let stem = output.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+    match index {
+        Some(index) => output.with_file_name(format!("{}_color{}.brs", stem, index)),
+        None => output.with_file_name(format!("{}_unique.brs", stem)),
+    }
+}
+
+/// Writes the save to `output`, or a save per color next to it with --split-by-color.
+/// Returns the paths of the saves written.
+fn write_output(args: &ConvertArgs, save: SaveData, output: &Path) -> Result<Vec<PathBuf>, String> {
+    if !args.split_by_color {
+        write_save(save, output)?;
+        args.info(format!("Save written to {}", output.display()));
+        return Ok(vec![output.to_path_buf()]);
+    }
+
+    let parts: Vec<(PathBuf, SaveData)> = vox2brs::split_by_color(save)
+        .into_iter()
+        .map(|(index, part)| (color_output_path(output, index), part))
+        .collect();
+
+    // Checked before writing any, so a split isn't left half written.
+    if !args.force {
+        if let Some((path, _)) = parts.iter().find(|(path, _)| path.exists()) {
+            return Err(format!("{} already exists, use --force to overwrite it.", path.display()));
         }
     }
-}
\ No newline at end of file
+
+    let mut written = vec![];
+
+    for (path, part) in parts {
+        write_save(part, &path)?;
+        args.info(format!("Save written to {}", path.display()));
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Path of the save written into `directory` for `input`, named by the output name template.
+fn output_path_in(args: &ConvertArgs, directory: &Path, input: &Path) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let mode = arg_name(&args.mode);
+    let (default_width, default_height) = args.mode.default_size();
+
+    let name = args.output_name
+        .replace("{stem}", &stem)
+        .replace("{mode}", &mode)
+        .replace("{width}", &args.width.unwrap_or(default_width).to_string())
+        .replace("{height}", &args.height.unwrap_or(default_height).to_string());
+
+    directory.join(name)
+}
+
+fn convert_directory(args: &ConvertArgs, cancel: &Arc<AtomicBool>) -> Result<(), String> {
+    if !args.output.is_dir() {
+        return Err("Output must be a directory when the input is a directory.".into());
+    }
+
+    if !args.merge.is_empty() {
+        return Err("Files can't be merged when the input is a directory.".into());
+    }
+
+    if args.palette_out.is_some() {
+        return Err("The palette can't be written when the input is a directory.".into());
+    }
+
+    #[cfg(feature = "thumbnail")]
+    if args.thumbnail.is_some() {
+        return Err("A thumbnail can't be rendered when the input is a directory.".into());
+    }
+
+    let mut inputs: Vec<PathBuf> = fs::read_dir(&args.input)
+        .map_err(|error| format!("Could not read {}: {}", args.input.display(), error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_supported_file(path))
+        .collect();
+
+    inputs.sort();
+
+    let mut failures = vec![];
+    let mut entries = vec![];
+
+    for input in &inputs {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Cancelled, the remaining files were not converted.".into());
+        }
+
+        let output = output_path_in(args, &args.output, input);
+
+        args.info(format!("\nConverting {}...", input.display()));
+
+        let result = check_overwrite(args, &output).and_then(|_| convert(args, input, cancel)).and_then(|(save, stats)| {
+            let mut entry = conversion_json(args, input, &save, &stats);
+
+            let outputs = if args.dry_run {
+                report(&save, &stats);
+                vec![]
+            } else {
+                write_output(args, save, &output)?
+            };
+
+            record_outputs(args, &mut entry, &outputs);
+            Ok(entry)
+        });
+
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(error) => {
+                eprintln!("{}", error);
+                entries.push(json!({ "input": input.display().to_string(), "error": error }));
+                failures.push((input, error));
+            },
+        }
+    }
+
+    args.info(format!("\nConverted {} of {} files.", inputs.len() - failures.len(), inputs.len()));
+
+    if let Some(path) = &args.emit_json {
+        write_json(&Value::Array(entries), path)?;
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for (input, error) in &failures {
+        eprintln!(" - {}: {}", input.display(), error);
+    }
+
+    Err(format!("{} files failed to convert.", failures.len()))
+}
+
+/// Makes Ctrl-C stop the conversion before anything is written. A second Ctrl-C exits right away.
+fn cancel_on_ctrl_c() -> Arc<AtomicBool> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = cancel.clone();
+
+    let result = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+
+        eprintln!("\nCancelling...");
+    });
+
+    if let Err(error) = result {
+        eprintln!("Ctrl-C won't cancel the conversion: {}", error);
+    }
+
+    cancel
+}
+
+/// Prints what's in a voxel file.
+fn info(args: &InfoArgs) -> Result<(), String> {
+    if args.input.is_dir() {
+        return Err("Info needs a file, not a directory.".into());
+    }
+
+    let block_colors = args.block_colors.as_deref().map(load_block_colors).transpose()?;
+    let source = load(&args.input, block_colors.as_ref())?;
+    let models = source.models();
+
+    println!("{}", args.input.display());
+    println!(" - Models: {}", models.len());
+
+    for (index, model) in models.iter().enumerate() {
+        let (x, y, z) = model.size;
+        println!("   - Model {}: {} x {} x {} voxels, {} filled", index, x, y, z, model.voxels.len());
+    }
+
+    let used: HashSet<u32> = models
+        .iter()
+        .flat_map(|model| model.voxels.iter().map(|voxel| voxel.color_index))
+        .collect();
+
+    println!(" - Voxels: {}", models.iter().map(|model| model.voxels.len()).sum::<usize>());
+    println!(" - Palette: {} colors, {} used", source.palette().len(), used.len());
+
+    Ok(())
+}
+
+fn read_save(path: &Path) -> Result<SaveData, String> {
+    File::open(path)
+        .map_err(|error| error.to_string())
+        .and_then(|file| SaveReader::new(file).map_err(|error| error.to_string()))
+        .and_then(|mut reader| reader.read_all().map_err(|error| error.to_string()))
+        .map_err(|error| format!("Could not read {}: {}", path.display(), error))
+}
+
+/// Prints what's in an existing save.
+fn inspect(args: &InspectArgs) -> Result<(), String> {
+    let save = read_save(&args.input)?;
+
+    let mut colors = HashSet::new();
+    let mut assets = BTreeMap::new();
+
+    for brick in &save.bricks {
+        match &brick.color {
+            BrickColor::Index(index) => colors.insert(save.header2.colors.get(*index as usize).map(|c| (c.r, c.g, c.b, c.a))),
+            BrickColor::Unique(c) => colors.insert(Some((c.r, c.g, c.b, c.a))),
+        };
+
+        let asset = save.header2.brick_assets
+            .get(brick.asset_name_index as usize)
+            .map_or("unknown", String::as_str);
+        *assets.entry(asset).or_insert(0) += 1;
+    }
+
+    println!("{}", args.input.display());
+    println!(" - Description: {}", save.header1.description);
+    println!(" - Author: {}", save.header1.author.name);
+    println!(" - Bricks: {}", save.bricks.len());
+
+    for (asset, count) in &assets {
+        println!("   - {}: {}", asset, count);
+    }
+
+    println!(" - Colors: {} used, {} in the palette", colors.len(), save.header2.colors.len());
+    println!(" - Owners: {}", save.header2.brick_owners.len());
+
+    if let Some((min, max)) = brick_bounds(&save.bricks) {
+        println!(" - Size: {} x {} x {} units", max.0 - min.0, max.1 - min.1, max.2 - min.2);
+    }
+
+    Ok(())
+}
+
+/// Command line arguments, with `convert` put in front when no subcommand is named so the
+/// old `vox2brs <input> <output>` form keeps working.
+fn command_line() -> Vec<OsString> {
+    let mut arguments: Vec<OsString> = env::args_os().collect();
+
+    let named = arguments
+        .get(1)
+        .and_then(|argument| argument.to_str())
+        .map_or(false, |argument| SUBCOMMANDS.contains(&argument));
+
+    if !named {
+        arguments.insert(1, "convert".into());
+    }
+
+    arguments
+}
+
+fn convert_command(args: &ConvertArgs) -> Result<(), String> {
+    let cancel = cancel_on_ctrl_c();
+
+    if args.input.is_dir() {
+        return convert_directory(args, &cancel);
+    }
+
+    let output = if args.output.is_dir() {
+        output_path_in(args, &args.output, &args.input)
+    } else {
+        args.output.clone()
+    };
+
+    if args.split_by_color && args.output.as_os_str() == "-" {
+        return Err("Saves split by color can't be written to stdout.".into());
+    }
+
+    // Checked before converting, so a long conversion isn't thrown away.
+    check_overwrite(args, &output)?;
+
+    let (out_save, stats) = convert(args, &args.input, &cancel)?;
+
+    #[cfg(feature = "thumbnail")]
+    if let Some(path) = &args.thumbnail {
+        vox2brs::thumbnail::write_thumbnail(&out_save, path)
+            .map_err(|error| format!("Could not write thumbnail to {}: {}", path.display(), error))?;
+        args.info(format!("Thumbnail written to {}", path.display()));
+    }
+
+    // Written after the save, so a failed write leaves no sidecar behind.
+    let sidecar = args.emit_json.as_ref().map(|path| (path, conversion_json(args, &args.input, &out_save, &stats)));
+
+    let palette = args.palette_out.as_ref().map(|path| (path, out_save.header2.colors.clone()));
+
+    let outputs = if args.dry_run {
+        report(&out_save, &stats);
+        vec![]
+    } else if args.output.as_os_str() == "-" {
+        args.info("\nWriting save to stdout...");
+
+        let stdout = io::stdout();
+
+        SaveWriter::new(stdout.lock(), out_save)
+            .write()
+            .map_err(|error| format!("Could not write to stdout, {}", error))?;
+
+        vec![output]
+    } else {
+        args.info("\nWriting save file...");
+        write_output(args, out_save, &output)?
+    };
+
+    if let Some((path, mut value)) = sidecar {
+        record_outputs(args, &mut value, &outputs);
+        write_json(&value, path)?;
+    }
+
+    if let Some((path, colors)) = palette {
+        write_palette(&colors, path)?;
+        args.info(format!("Palette written to {}", path.display()));
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), String> {
+    match Cli::parse_from(command_line()).command {
+        Command::Convert(args) => convert_command(&args),
+        Command::Info(args) => info(&args),
+        Command::Inspect(args) => inspect(&args),
+        #[cfg(feature = "server")]
+        Command::Serve(args) => server::run(&args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vox2brs::new_save;
+
+    const STAIRS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../vox2brs/tests/fixtures/stairs.vox");
+
+    fn convert_with(template: &Path, flags: &[&str]) -> Result<(SaveData, ConversionStats), String> {
+        let mut arguments = vec!["vox2brs", "convert", STAIRS, "-"];
+        arguments.extend_from_slice(flags);
+        arguments.extend_from_slice(&["--quiet", "--assets-from-save", template.to_str().unwrap()]);
+
+        match Cli::parse_from(arguments).command {
+            Command::Convert(args) => convert(&args, &args.input, &Arc::new(AtomicBool::new(false))),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn assets_from_save_are_found_by_name() {
+        // The defaults in another order, with no ramp.
+        let assets = ["PB_DefaultWedge", "PB_DefaultTile", "PB_DefaultMicroBrick", "PB_DefaultBrick"];
+
+        let mut template = new_save(User { name: "Builder".into(), id: Uuid::nil() }, String::new());
+        template.header2.brick_assets = assets.iter().map(|&asset| asset.into()).collect();
+
+        let path = env::temp_dir().join(format!("vox2brs_template_{}.brs", process::id()));
+        write_save(template, &path).unwrap();
+
+        let (save, _) = convert_with(&path, &["plate", "--simplify"]).unwrap();
+        assert_eq!(save.header2.brick_assets, assets);
+        assert!(save.bricks.iter().all(|brick| brick.asset_name_index == 3));
+
+        let (save, _) = convert_with(&path, &["micro-brick", "--author-from-save"]).unwrap();
+        assert!(save.bricks.iter().all(|brick| brick.asset_name_index == 2));
+        assert_eq!(save.header1.author.name, "Builder");
+
+        let error = convert_with(&path, &["--brick-asset-list", "PB_DefaultBrick"]).unwrap_err();
+        assert!(error.contains("can't be used together"));
+
+        // Rampifying needs the ramp, which the template doesn't have.
+        let error = convert_with(&path, &["plate", "--rampify"]).unwrap_err();
+        assert!(error.contains("PB_DefaultRamp"), "{}", error);
+        assert!(error.contains("--assets-from-save"));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
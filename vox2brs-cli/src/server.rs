@@ -0,0 +1,175 @@
+//! The `serve` subcommand, converting voxel files sent over TCP without a process per file.
+//!
+//! A request is a JSON list of `convert` flags, like `["plate", "--simplify"]`, followed by the
+//! bytes of a voxel file, each prefixed by its length as a little-endian u32. The response is a
+//! status byte, 0 on success, then the .brs bytes or an error message, prefixed the same way.
+//! A connection can send any number of requests.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use brickadia::write::SaveWriter;
+use clap::Parser;
+use vox2brs::source::read_source;
+use crate::{convert_source, ConvertArgs, ServeArgs};
+
+/// Longest flags or voxel file accepted, so a bad length can't take all the server's memory.
+const MAX_LENGTH: usize = 256 * 1024 * 1024;
+
+/// Flags of a request, parsed like `convert`'s after an input and output that aren't used.
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+struct Request {
+    #[clap(flatten)]
+    args: ConvertArgs,
+}
+
+/// Reads a length-prefixed part of a request, or `None` if the connection closed before it.
+fn read_part(stream: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+
+    match stream.read_exact(&mut length) {
+        Ok(()) => {},
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+
+    let length = u32::from_le_bytes(length) as usize;
+
+    if length > MAX_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request part is too long"));
+    }
+
+    let mut bytes = vec![0; length];
+    stream.read_exact(&mut bytes)?;
+
+    Ok(Some(bytes))
+}
+
+fn write_response(stream: &mut impl Write, status: u8, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&[status])?;
+    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    stream.write_all(bytes)?;
+    stream.flush()
+}
+
+/// Converts a request's voxel file, returning the .brs bytes.
+fn convert_request(flags: &[u8], bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let flags: Vec<String> = serde_json::from_slice(flags)
+        .map_err(|error| format!("Invalid flags, expected a JSON list of strings: {}", error))?;
+
+    let arguments = [".".to_string(), "-".to_string()].into_iter().chain(flags);
+    let mut args = Request::try_parse_from(arguments).map_err(|error| error.to_string())?.args;
+
+    // The server's files aren't the client's to read or write.
+    let touches_files = !args.merge.is_empty() || args.match_palette.is_some() || args.block_colors.is_some() || args.assets_from_save.is_some();
+
+    #[cfg(feature = "debug-slices")]
+    let touches_files = touches_files || args.debug_slices.is_some();
+
+    if touches_files {
+        return Err("Flags that read or write files on the server can't be used.".into());
+    }
+
+    args.quiet = true;
+
+    let source = read_source(bytes).map_err(|error| format!("Could not read the voxel file: {}", error))?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (save, _) = convert_source(&args, source, Path::new("request"), &cancel)?;
+
+    let mut output = vec![];
+
+    SaveWriter::new(&mut output, save)
+        .write()
+        .map_err(|error| format!("Could not write the save: {}", error))?;
+
+    Ok(output)
+}
+
+fn handle_connection(mut stream: TcpStream) -> io::Result<()> {
+    while let Some(flags) = read_part(&mut stream)? {
+        let bytes = read_part(&mut stream)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the voxel file"))?;
+
+        match convert_request(&flags, &bytes) {
+            Ok(save) => write_response(&mut stream, 0, &save)?,
+            Err(error) => write_response(&mut stream, 1, error.as_bytes())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers connections to `listener` on threads of their own, until it fails.
+fn serve(listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map_or_else(|_| "unknown".into(), |addr| addr.to_string());
+
+            if let Err(error) = handle_connection(stream) {
+                eprintln!("Connection from {} failed: {}", peer, error);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &ServeArgs) -> Result<(), String> {
+    let listener = TcpListener::bind(&args.addr)
+        .map_err(|error| format!("Could not listen on {}: {}", args.addr, error))?;
+
+    println!("Listening on {}", args.addr);
+
+    serve(listener).map_err(|error| format!("Could not accept connections: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::read::SaveReader;
+
+    fn request(stream: &mut TcpStream, flags: &str, bytes: &[u8]) -> (u8, Vec<u8>) {
+        for part in [flags.as_bytes(), bytes] {
+            stream.write_all(&(part.len() as u32).to_le_bytes()).unwrap();
+            stream.write_all(part).unwrap();
+        }
+
+        let mut status = [0];
+        stream.read_exact(&mut status).unwrap();
+
+        (status[0], read_part(stream).unwrap().unwrap())
+    }
+
+    #[test]
+    fn converts_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+
+        let vox = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../vox2brs/tests/fixtures/stairs.vox")).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        let (status, brs) = request(&mut stream, r#"["plate", "--simplify"]"#, &vox);
+        assert_eq!(status, 0, "{}", String::from_utf8_lossy(&brs));
+
+        let save = SaveReader::new(&brs[..]).unwrap().read_all().unwrap();
+        assert!(!save.bricks.is_empty());
+
+        // Errors are answered on the same connection, which stays usable.
+        let (status, message) = request(&mut stream, r#"["--merge", "secret.vox"]"#, &vox);
+        assert_eq!(status, 1);
+        assert!(!message.is_empty());
+
+        let (status, _) = request(&mut stream, "not json", &vox);
+        assert_eq!(status, 1);
+
+        let (status, _) = request(&mut stream, "[]", &vox);
+        assert_eq!(status, 0);
+    }
+}
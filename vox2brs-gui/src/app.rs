@@ -1,13 +1,65 @@
-use std::fs::File;
-use std::ops::RangeInclusive;
-use std::path::Path;
-use brickadia::save::{BrickOwner, SaveData, User};
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use brickadia::save::{SaveData, User};
+#[cfg(target_arch = "wasm32")]
 use brickadia::write::SaveWriter;
-use create_vox::VoxFile;
 use eframe::{egui, epi};
-use eframe::egui::{Checkbox, Color32, Hyperlink, RichText, TextEdit, TopBottomPanel};
+use eframe::egui::{Align2, Button, Checkbox, Color32, Hyperlink, Id, Key, LayerId, Order, ProgressBar, RichText, TextEdit, TextStyle, TopBottomPanel};
 use eframe::egui::special_emojis::GITHUB;
-use vox2brs::{BrickOutputMode, vox2brs};
+use uuid::Uuid;
+use vox2brs::{convert_source_with_progress, estimate_brick_count, BrickCollision, BrickMaterial, BrickOutputMode, ConversionStats, ProgressEvent, Vox2BrsOptions};
+use vox2brs::source::{load_source, read_source, VoxelSource, SUPPORTED_EXTENSIONS};
+
+/// How many recent input files and output directories are remembered.
+const MAX_RECENT: usize = 10;
+
+/// Height kept free under the options for the convert button, progress bar and statistics.
+const CONVERT_AREA_HEIGHT: f32 = 140.0;
+
+/// Height the options are always given, even when that pushes the convert button down.
+const MIN_OPTIONS_HEIGHT: f32 = 120.0;
+
+/// Seconds the options have to stay the same before the brick estimate is redone, so
+/// dragging a value doesn't reload the input every frame.
+const ESTIMATE_DELAY: f64 = 0.3;
+
+/// Sent from the conversion thread to the UI.
+enum ConversionMessage {
+    Progress(ProgressEvent),
+    Finished(Result<ConversionStats, String>),
+    #[cfg(feature = "thumbnail")]
+    Thumbnail(egui::ColorImage),
+}
+
+/// A conversion running on a background thread.
+pub struct Conversion {
+    receiver: Receiver<ConversionMessage>,
+    stage: String,
+    fraction: f32,
+
+    /// Set by the Cancel button, stopping the conversion at its next check.
+    cancel: Arc<AtomicBool>,
+}
+
+/// What a brick estimate depends on, so it's only redone when one of them changes.
+#[derive(Clone, PartialEq)]
+pub struct EstimateKey {
+    input: String,
+    upload_len: Option<usize>,
+    mode: BrickOutputMode,
+    scale: u32,
+    surface_only: bool,
+    simplify: bool,
+    rampify: bool,
+    smooth_edges: bool,
+    skip_colors: Vec<u32>,
+}
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
@@ -16,11 +68,85 @@ pub struct Vox2BrsApp {
     pub input_file_path: String,
     pub output_directory: String,
     pub save_name: String,
+
+    /// Description of the save, or empty to describe the input file.
+    pub description: String,
+    pub author_name: String,
+    pub author_id: String,
     pub mode: BrickOutputMode,
-    pub width: f32,
-    pub height: f32,
+    pub studs: bool,
+    pub width: u32,
+    pub height: u32,
+    pub scale: u32,
+    pub surface_only: bool,
     pub simplify: bool,
     pub rampify: bool,
+    pub smooth_edges: bool,
+    pub gamma_correct: bool,
+    pub quantize: bool,
+    pub quantize_colors: usize,
+    pub dither: bool,
+    pub skip_colors: Vec<u32>,
+    pub skip_color_input: u32,
+    pub color_materials: Vec<(u32, BrickMaterial)>,
+    pub auto_materials: bool,
+    pub material_color_input: u32,
+    pub material_input: BrickMaterial,
+    pub collision: BrickCollision,
+    pub center: bool,
+    pub grid_align: bool,
+
+    pub mirror: [bool; 3],
+
+    /// Quarter turns around the Z axis.
+    pub world_rotation: u8,
+
+    /// Input files converted before, most recent first.
+    pub recent_inputs: Vec<String>,
+
+    /// Output directories written to before, most recent first.
+    pub recent_outputs: Vec<String>,
+
+    /// Statistics of the last successful conversion.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub last_stats: Option<ConversionStats>,
+
+    /// Thumbnail of the last converted save, shown under its statistics.
+    #[cfg(feature = "thumbnail")]
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub thumbnail: Option<egui::TextureHandle>,
+
+    /// Why the last conversion failed, shown under the convert button.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub last_error: Option<String>,
+
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub conversion: Option<Conversion>,
+
+    /// Why the output directory can't be written to, found when starting a conversion.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub output_error: Option<String>,
+
+    /// Existing save the conversion would replace, waiting for the user to confirm.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub confirm_overwrite: Option<PathBuf>,
+
+    /// Voxel file dropped in as bytes, as the web build has no file paths to read.
+    /// Takes the place of the input path while it's set.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub upload: Option<Arc<[u8]>>,
+
+    /// Rough brick count of the input with the current options, or `None` if it couldn't be read.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub estimate: Option<(EstimateKey, Option<usize>)>,
+
+    /// Options the estimate is waiting to be redone for, and when they were last changed.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub estimate_pending: Option<(EstimateKey, f64)>,
+
+    /// Estimate being redone on a background thread, so reading the input doesn't hold up the UI.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub estimate_running: Option<(EstimateKey, Receiver<Option<usize>>)>,
 }
 
 impl Default for Vox2BrsApp {
@@ -29,11 +155,389 @@ impl Default for Vox2BrsApp {
             input_file_path: "input.vox".into(),
             output_directory: "builds".into(),
             save_name: "output".into(),
+            description: String::new(),
+            author_name: "vox2brs".into(),
+            author_id: "a8033bee-6c37-4118-b4a6-cecc1d966133".into(),
             mode: BrickOutputMode::Brick,
-            width: 1.0,
-            height: 1.0,
+            studs: false,
+            width: 1,
+            height: 1,
+            scale: 1,
+            surface_only: false,
             simplify: true,
             rampify: false,
+            smooth_edges: false,
+            gamma_correct: false,
+            quantize: false,
+            quantize_colors: 32,
+            dither: false,
+            skip_colors: vec![],
+            skip_color_input: 0,
+            color_materials: vec![],
+            auto_materials: false,
+            material_color_input: 0,
+            material_input: BrickMaterial::Metallic,
+            collision: BrickCollision::All,
+            center: false,
+            grid_align: false,
+            world_rotation: 0,
+            mirror: [false; 3],
+            recent_inputs: vec![],
+            recent_outputs: vec![],
+            last_stats: None,
+            #[cfg(feature = "thumbnail")]
+            thumbnail: None,
+            last_error: None,
+            conversion: None,
+            output_error: None,
+            confirm_overwrite: None,
+            upload: None,
+            estimate: None,
+            estimate_pending: None,
+            estimate_running: None,
+        }
+    }
+}
+
+impl Vox2BrsApp {
+    /// Selects a voxel file to convert, naming the save after it.
+    fn set_input_file(&mut self, file_path: &Path) {
+        self.upload = None;
+        self.input_file_path = file_path.to_string_lossy().into_owned();
+        self.save_name = match file_path.file_stem() {
+            Some(s) => s.to_string_lossy().into_owned(),
+            None => self.save_name.clone()
+        };
+    }
+
+    /// Opens a file dialog to pick the input file.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn input_file_button(&mut self, ui: &mut egui::Ui) {
+        if ui.button(RichText::new("🗁").color(Color32::from_rgb(255, 206, 70))).clicked() {
+            match nfd2::open_file_dialog(Some("vox,qb,vxm,schematic"), None).unwrap() {
+                nfd2::Response::Okay(file_path) => {
+                    self.set_input_file(&file_path);
+                },
+                _ => ()
+            }
+        }
+    }
+
+    /// Browsers don't give out file paths, so the web build only takes dropped files.
+    #[cfg(target_arch = "wasm32")]
+    fn input_file_button(&mut self, _ui: &mut egui::Ui) {}
+
+    /// Conversion options picked in the UI.
+    fn options(&self) -> Vox2BrsOptions {
+        Vox2BrsOptions::default()
+            .mode(self.mode)
+            .studs(self.studs)
+            .width(self.width)
+            .height(self.height)
+            .voxel_scale(self.scale)
+            .surface_only(self.surface_only)
+            .simplify(self.simplify)
+            .rampify(self.rampify)
+            .smooth_edges(self.smooth_edges)
+            .gamma_correct(self.gamma_correct)
+            .quantize(Some(self.quantize_colors).filter(|_| self.quantize))
+            .dither(self.dither)
+            .skip_color_indices(self.skip_colors.clone())
+            .color_materials(self.color_materials.iter().copied().collect())
+            .auto_materials(self.auto_materials)
+            .collision(self.collision)
+            .center(self.center)
+            .grid_align(self.grid_align)
+            .mirror(self.mirror[0], self.mirror[1], self.mirror[2])
+            .world_rotation(self.world_rotation)
+    }
+
+    fn estimate_key(&self) -> EstimateKey {
+        EstimateKey {
+            input: self.input_file_path.clone(),
+            upload_len: self.upload.as_ref().map(|upload| upload.len()),
+            mode: self.mode,
+            scale: self.scale,
+            surface_only: self.surface_only,
+            simplify: self.simplify,
+            rampify: self.rampify,
+            smooth_edges: self.smooth_edges,
+            skip_colors: self.skip_colors.clone(),
+        }
+    }
+
+    /// Redoes the brick estimate once the input or the options it depends on have settled.
+    fn update_estimate(&mut self, ctx: &egui::CtxRef) {
+        if self.upload.is_none() && !Path::new(&self.input_file_path).is_file() {
+            self.estimate = None;
+            self.estimate_pending = None;
+            self.estimate_running = None;
+            return;
+        }
+
+        if let Some((running, receiver)) = &self.estimate_running {
+            match receiver.try_recv() {
+                Ok(bricks) => {
+                    self.estimate = Some((running.clone(), bricks));
+                    self.estimate_running = None;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    self.estimate = Some((running.clone(), None));
+                    self.estimate_running = None;
+                },
+                // Keep repainting while the thread works, so the estimate shows up without input.
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+            }
+        }
+
+        let key = self.estimate_key();
+
+        let estimated = matches!(&self.estimate, Some((estimated, _)) if *estimated == key);
+        let running = matches!(&self.estimate_running, Some((running, _)) if *running == key);
+
+        if estimated || running {
+            self.estimate_pending = None;
+            return;
+        }
+
+        let now = ctx.input().time;
+        let changed_at = match &self.estimate_pending {
+            Some((pending, changed_at)) if *pending == key => *changed_at,
+            _ => now,
+        };
+
+        if now - changed_at < ESTIMATE_DELAY {
+            self.estimate_pending = Some((key, changed_at));
+            // Nothing else may repaint once the options settle, so ask for the frame that redoes it.
+            ctx.request_repaint();
+            return;
+        }
+
+        let input = PathBuf::from(&self.input_file_path);
+        let upload = self.upload.clone();
+        let options = self.options();
+
+        let (sender, receiver) = mpsc::channel();
+
+        let job = move || {
+            let source: Option<Box<dyn VoxelSource>> = match &upload {
+                Some(bytes) => read_source(bytes).ok(),
+                None => load_source(&input).ok(),
+            };
+
+            let _ = sender.send(source.map(|source| estimate_brick_count(&*source, &options)));
+        };
+
+        // Like conversions, the web has no threads, so the estimate is made right away there.
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(job);
+        #[cfg(target_arch = "wasm32")]
+        job();
+
+        // Replacing an estimate still running drops its result once it's sent.
+        self.estimate_running = Some((key, receiver));
+        self.estimate_pending = None;
+        ctx.request_repaint();
+    }
+
+    /// Starts converting the input file on a background thread, or right away on the web.
+    /// Asks first if it would replace an existing save, unless `overwrite` is set.
+    fn start_conversion(&mut self, overwrite: bool) {
+        let author_id = match self.author_id.parse() {
+            Ok(id) => id,
+            Err(_) => {
+                self.fail(format!("Invalid author id {}, expected a UUID.", self.author_id));
+                return;
+            }
+        };
+
+        let author = User {
+            name: self.author_name.clone(),
+            id: author_id,
+        };
+
+        if self.upload.is_none() && !Path::new(&self.input_file_path).exists() {
+            self.fail("Voxel file not found.".into());
+            return;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.output_error = check_writable(Path::new(&self.output_directory)).err();
+
+            if let Some(error) = self.output_error.clone() {
+                self.fail(error);
+                return;
+            }
+        }
+
+        let output = if cfg!(target_arch = "wasm32") {
+            PathBuf::from(format!("{}.brs", self.save_name))
+        } else {
+            Path::new(&self.output_directory).join(format!("{}.brs", self.save_name))
+        };
+
+        // Downloads on the web never replace a file.
+        if !overwrite && !cfg!(target_arch = "wasm32") && output.exists() {
+            self.confirm_overwrite = Some(output);
+            return;
+        }
+
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let options = self.options()
+            .cancel(cancel.clone())
+            .author(author)
+            .description(Some(self.description.clone()).filter(|description| !description.is_empty()));
+
+        if self.upload.is_none() {
+            remember(&mut self.recent_inputs, &self.input_file_path);
+        }
+        if !cfg!(target_arch = "wasm32") {
+            remember(&mut self.recent_outputs, &self.output_directory);
+        }
+
+        let input = PathBuf::from(&self.input_file_path);
+        let upload = self.upload.clone();
+
+        let (sender, receiver) = mpsc::channel();
+
+        let job = move || {
+            let mut progress = |event: ProgressEvent| {
+                let _ = sender.send(ConversionMessage::Progress(event));
+            };
+
+            let mut converted = |_save: &SaveData| {
+                #[cfg(feature = "thumbnail")]
+                {
+                    let image = vox2brs::thumbnail::render_thumbnail(_save);
+                    let size = [image.width() as usize, image.height() as usize];
+                    let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+                    let _ = sender.send(ConversionMessage::Thumbnail(image));
+                }
+            };
+
+            let result = convert(&input, upload, &output, &options, &mut progress, &mut converted);
+
+            let _ = sender.send(ConversionMessage::Finished(result));
+        };
+
+        // The web has no threads to convert on, so the UI waits for the conversion there.
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(job);
+        #[cfg(target_arch = "wasm32")]
+        job();
+
+        self.conversion = Some(Conversion {
+            receiver,
+            stage: "Starting...".into(),
+            fraction: 0.0,
+            cancel,
+        });
+    }
+
+    /// Shows why a conversion failed in place of the last one's statistics.
+    fn fail(&mut self, error: String) {
+        self.last_stats = None;
+        self.last_error = Some(error);
+
+        #[cfg(feature = "thumbnail")]
+        {
+            self.thumbnail = None;
+        }
+    }
+
+    /// Why a conversion can't be started, if it can't.
+    fn convert_blocker(&self, input_file_valid: bool, output_dir_valid: bool, author_id_valid: bool) -> Option<&'static str> {
+        if self.conversion.is_some() {
+            Some("A conversion is already running.")
+        } else if self.confirm_overwrite.is_some() {
+            Some("Confirm whether to overwrite the existing save.")
+        } else if !input_file_valid {
+            Some("Pick a voxel file to convert.")
+        } else if !output_dir_valid {
+            Some("Pick an output directory that can be written to.")
+        } else if !author_id_valid {
+            Some("The author ID must be a UUID.")
+        } else {
+            None
+        }
+    }
+
+    /// Updates the progress bar from the conversion thread, clearing it once the conversion is done.
+    fn poll_conversion(&mut self, ctx: &egui::CtxRef) {
+        let conversion = match &mut self.conversion {
+            Some(conversion) => conversion,
+            None => return,
+        };
+
+        let mut finished = None;
+
+        for message in conversion.receiver.try_iter() {
+            match message {
+                ConversionMessage::Progress(event) => {
+                    if let Some(fraction) = event.fraction() {
+                        conversion.fraction = fraction;
+                        conversion.stage = event.to_string().trim_start_matches(" - ").into();
+                    }
+                },
+                ConversionMessage::Finished(result) => finished = Some(result),
+                #[cfg(feature = "thumbnail")]
+                ConversionMessage::Thumbnail(image) => {
+                    self.thumbnail = Some(ctx.load_texture("thumbnail", image));
+                },
+            }
+        }
+
+        // Keep repainting while the thread works, so the bar updates without input.
+        ctx.request_repaint();
+
+        if let Some(result) = finished {
+            let cancelled = conversion.cancel.load(Ordering::Relaxed);
+            self.conversion = None;
+
+            match result {
+                Ok(stats) => {
+                    self.last_stats = Some(stats);
+                    self.last_error = None;
+                },
+                Err(_) if cancelled => self.fail("Conversion cancelled.".into()),
+                Err(error) => self.fail(error),
+            }
+        }
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::CtxRef) {
+        let is_supported = |path: &Path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .map_or(false, |e| SUPPORTED_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        };
+
+        if !ctx.input().raw.hovered_files.is_empty() {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+            let screen_rect = ctx.input().screen_rect();
+
+            painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+            painter.text(screen_rect.center(), Align2::CENTER_CENTER, "Drop voxel file to convert", TextStyle::Heading, Color32::WHITE);
+        }
+
+        let dropped_files = ctx.input().raw.dropped_files.clone();
+
+        for file in dropped_files {
+            match (&file.path, &file.bytes) {
+                (Some(path), _) if is_supported(path) => {
+                    self.set_input_file(path);
+                    return;
+                },
+                // Files dropped into a browser only come with their name and contents.
+                (None, Some(bytes)) if is_supported(Path::new(&file.name)) => {
+                    self.set_input_file(Path::new(&file.name));
+                    self.upload = Some(bytes.clone());
+                    return;
+                },
+                _ => (),
+            }
         }
     }
 }
@@ -43,11 +547,30 @@ impl epi::App for Vox2BrsApp {
         "vox2brs"
     }
 
+    /// Picks up the options and recent files from the last session.
+    #[cfg(feature = "persistence")]
+    fn setup(&mut self, _ctx: &egui::CtxRef, _frame: &epi::Frame, storage: Option<&dyn epi::Storage>) {
+        if let Some(app) = storage.and_then(|storage| epi::get_value(storage, epi::APP_KEY)) {
+            *self = app;
+        }
+    }
+
+    /// Called by the framework to save state before shutdown.
+    #[cfg(feature = "persistence")]
+    fn save(&mut self, storage: &mut dyn epi::Storage) {
+        epi::set_value(storage, epi::APP_KEY, self);
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame) {
-        let input_file_valid = Path::new(&self.input_file_path).exists();
-        let output_dir_valid = Path::new(&self.output_directory).is_dir();
+        self.handle_dropped_files(ctx);
+        self.poll_conversion(ctx);
+        self.update_estimate(ctx);
+
+        let input_file_valid = self.upload.is_some() || Path::new(&self.input_file_path).exists();
+        let output_dir_valid = cfg!(target_arch = "wasm32") || Path::new(&self.output_directory).is_dir() && self.output_error.is_none();
+        let author_id_valid = self.author_id.parse::<Uuid>().is_ok();
 
         if self.mode == BrickOutputMode::MicroBrick && self.rampify {
             self.mode = BrickOutputMode::Brick;
@@ -72,27 +595,27 @@ impl epi::App for Vox2BrsApp {
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
-                    ui.label("VOX File").on_hover_text("MagicaVoxel Model to convert");
+                    ui.label("VOX File").on_hover_text("MagicaVoxel, Qubicle or VoxEdit model to convert");
                     ui.horizontal(|ui| {
-                        ui.add(TextEdit::singleline(&mut self.input_file_path).desired_width(400.0).text_color(bool_color(input_file_valid)));
-                        if ui.button(RichText::new("🗁").color(Color32::from_rgb(255, 206, 70))).clicked() {
-                            match nfd2::open_file_dialog(Some("vox"), None).unwrap() {
-                                nfd2::Response::Okay(file_path) => {
-                                    self.input_file_path = file_path.to_string_lossy().into_owned();
-                                    self.save_name = match file_path.file_stem() {
-                                        Some(s) => s.to_string_lossy().into_owned(),
-                                        None => self.save_name.clone()
-                                    };
-                                },
-                                _ => ()
-                            }
+                        if ui.add(TextEdit::singleline(&mut self.input_file_path).desired_width(400.0).text_color(bool_color(input_file_valid))).changed() {
+                            self.upload = None;
+                        }
+                        self.input_file_button(ui);
+
+                        if let Some(path) = recent_menu(ui, "recent_inputs", &self.recent_inputs) {
+                            self.set_input_file(Path::new(&path));
                         }
                     });
                     ui.end_row();
 
+                    // Saves are downloaded on the web.
+                    #[cfg(not(target_arch = "wasm32"))]
                     ui.label("Output Directory").on_hover_text("Where generated save will be written to");
+                    #[cfg(not(target_arch = "wasm32"))]
                     ui.horizontal(|ui| {
-                        ui.add(TextEdit::singleline(&mut self.output_directory).desired_width(400.0).text_color(bool_color(output_dir_valid)));
+                        if ui.add(TextEdit::singleline(&mut self.output_directory).desired_width(400.0).text_color(bool_color(output_dir_valid))).changed() {
+                            self.output_error = None;
+                        }
                         if ui.button(RichText::new("🗁").color(Color32::from_rgb(255, 206, 70))).clicked() {
                             let default_dir = if output_dir_valid {
                                 Some(Path::new(self.output_directory.as_str()))
@@ -103,140 +626,304 @@ impl epi::App for Vox2BrsApp {
                             match nfd2::open_pick_folder(default_dir).unwrap() {
                                 nfd2::Response::Okay(file_path) => {
                                     self.output_directory = file_path.to_string_lossy().into_owned();
+                                    self.output_error = None;
                                 },
                                 _ => ()
                             }
                         }
+
+                        if let Some(directory) = recent_menu(ui, "recent_outputs", &self.recent_outputs) {
+                            self.output_directory = directory;
+                            self.output_error = None;
+                        }
                     });
+                    #[cfg(not(target_arch = "wasm32"))]
                     ui.end_row();
 
+                    if let Some(error) = &self.output_error {
+                        ui.label("");
+                        ui.colored_label(bool_color(false), error);
+                        ui.end_row();
+                    }
+
                     ui.label("Save Name");
                     ui.text_edit_singleline(&mut self.save_name);
                     ui.end_row();
+
+                    ui.label("Description").on_hover_text("Leave empty to describe the input file");
+                    ui.text_edit_singleline(&mut self.description);
+                    ui.end_row();
+
+                    ui.label("Author Name").on_hover_text("Name of the save's author and brick owner");
+                    ui.text_edit_singleline(&mut self.author_name);
+                    ui.end_row();
+
+                    ui.label("Author ID").on_hover_text("UUID of the save's author and brick owner");
+                    ui.add(TextEdit::singleline(&mut self.author_id).desired_width(400.0).text_color(bool_color(author_id_valid)));
+                    ui.end_row();
                 });
 
             ui.separator();
 
-            egui::Grid::new("options")
-                .num_columns(2)
-                .spacing([40.0, 4.0])
-                .striped(true)
+            // The convert button has to stay on screen, so the options scroll instead of pushing it down.
+            egui::ScrollArea::vertical()
+                .max_height((ui.available_height() - CONVERT_AREA_HEIGHT).max(MIN_OPTIONS_HEIGHT))
                 .show(ui, |ui| {
-                    ui.label("Rampify");
-                    ui.checkbox(&mut self.rampify, "Rampify the result. NOTE: Disables Microbricks as an option.");
-                    ui.end_row();
+                    egui::Grid::new("options")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Rampify");
+                            ui.checkbox(&mut self.rampify, "Rampify the result. NOTE: Disables Microbricks as an option.");
+                            ui.end_row();
 
-                    ui.label("Simplify");
-                    ui.add_enabled(!self.rampify, Checkbox::new(&mut self.simplify, "Optimizes bricks of the same color conservatively."));
-                    ui.end_row();
+                            ui.label("Smooth Edges");
+                            ui.add_enabled(!self.rampify, Checkbox::new(&mut self.smooth_edges, "Put wedges on single steps, keeping the rest as bricks."));
+                            ui.end_row();
 
-                    ui.label("Brick Type");
-                    egui::ComboBox::from_label("What kind of brick should be output?")
-                        .selected_text(format!("{:?}", &mut self.mode))
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut self.mode, BrickOutputMode::Brick, "Brick");
-                            ui.selectable_value(&mut self.mode, BrickOutputMode::Plate, "Plate");
-                            if !self.rampify {
-                                ui.selectable_value(&mut self.mode, BrickOutputMode::MicroBrick, "MicroBrick");
-                            }
-                            else {
+                            ui.label("Simplify");
+                            ui.add_enabled(!self.rampify, Checkbox::new(&mut self.simplify, "Optimizes bricks of the same color conservatively."));
+                            ui.end_row();
+
+                            ui.label("Surface Only");
+                            ui.checkbox(&mut self.surface_only, "Leave out voxels that are covered on every side.");
+                            ui.end_row();
+
+                            ui.label("Gamma Correction");
+                            ui.checkbox(&mut self.gamma_correct, "Convert colors into linear space. Makes colors darker in-game.");
+                            ui.end_row();
+
+                            ui.label("Reduce Colors").on_hover_text("Merges colors that look alike until the palette has this many");
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut self.quantize, "");
+                                ui.add_enabled(self.quantize, egui::Slider::new(&mut self.quantize_colors, 1..=256).text("colors"));
+                                ui.add_enabled(self.quantize, egui::Checkbox::new(&mut self.dither, "Dither"))
+                                    .on_hover_text("Mixes the reduced colors so gradients don't turn into bands");
+                            });
+                            ui.end_row();
+
+                            ui.label("Center");
+                            ui.checkbox(&mut self.center, "Center the build on the origin, with its base at the ground.");
+                            ui.end_row();
+
+                            ui.label("Grid Align");
+                            ui.checkbox(&mut self.grid_align, "Snap the build's corner to the stud grid.");
+                            ui.end_row();
+
+                            ui.label("Mirror").on_hover_text("Flips each model along its own axes");
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut self.mirror[0], "X");
+                                ui.checkbox(&mut self.mirror[1], "Y");
+                                ui.checkbox(&mut self.mirror[2], "Z");
+                            });
+                            ui.end_row();
+
+                            ui.label("Rotation").on_hover_text("Rotates the whole build around the vertical axis");
+                            egui::ComboBox::from_id_source("world_rotation")
+                                .selected_text(format!("{}°", self.world_rotation as u32 * 90))
+                                .show_ui(ui, |ui| {
+                                    for turns in 0..4u8 {
+                                        ui.selectable_value(&mut self.world_rotation, turns, format!("{}°", turns as u32 * 90));
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Skip Colors").on_hover_text("Palette indices of voxels to leave out");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::DragValue::new(&mut self.skip_color_input).clamp_range(0..=255));
+                                if ui.button("Add").clicked() && !self.skip_colors.contains(&self.skip_color_input) {
+                                    self.skip_colors.push(self.skip_color_input);
+                                }
+
+                                let mut removed = None;
+                                for (i, index) in self.skip_colors.iter().enumerate() {
+                                    if ui.button(format!("{} ✖", index)).clicked() {
+                                        removed = Some(i);
+                                    }
+                                }
+                                if let Some(i) = removed {
+                                    self.skip_colors.remove(i);
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Auto Materials");
+                            ui.checkbox(&mut self.auto_materials, "Use metallic, glass and glow for MagicaVoxel metal, glass and emit materials.");
+                            ui.end_row();
+
+                            ui.label("Materials").on_hover_text("Brick material for voxels of a palette index");
+                            ui.vertical(|ui| {
                                 ui.horizontal(|ui| {
-                                    ui.add_space(ui.spacing().button_padding.x);
-                                    ui.colored_label(bool_color(false), "MicroBrick");
+                                    ui.add(egui::DragValue::new(&mut self.material_color_input).clamp_range(0..=255));
+                                    egui::ComboBox::from_id_source("material")
+                                        .selected_text(format!("{:?}", self.material_input))
+                                        .show_ui(ui, |ui| {
+                                            for material in [BrickMaterial::Plastic, BrickMaterial::Glow, BrickMaterial::Metallic, BrickMaterial::Glass, BrickMaterial::Hologram] {
+                                                ui.selectable_value(&mut self.material_input, material, format!("{:?}", material));
+                                            }
+                                        });
+                                    if ui.button("Add").clicked() {
+                                        self.color_materials.retain(|(index, _)| *index != self.material_color_input);
+                                        self.color_materials.push((self.material_color_input, self.material_input));
+                                    }
                                 });
-                            }
-                        });
-                    ui.end_row();
 
-                    ui.label("Brick Size");
-                    ui.horizontal(|ui| {
-                        let range = RangeInclusive::new(1.0, f32::MAX);
+                                let mut removed = None;
+                                for (i, (index, material)) in self.color_materials.iter().enumerate() {
+                                    ui.horizontal(|ui| {
+                                        ui.label(format!("{}: {:?}", index, material));
+                                        if ui.small_button("✖").clicked() {
+                                            removed = Some(i);
+                                        }
+                                    });
+                                }
+                                if let Some(i) = removed {
+                                    self.color_materials.remove(i);
+                                }
+                            });
+                            ui.end_row();
 
-                        ui.label("Width");
-                        ui.add(egui::DragValue::new(&mut self.width).clamp_range(range.clone()).speed(1.0));
-                        ui.label("Height");
-                        ui.add(egui::DragValue::new(&mut self.height).clamp_range(range).speed(1.0));
-                    });
-                    ui.end_row();
+                            ui.label("Collision").on_hover_text("What the bricks collide with. Pass-through bricks can still be used with tools");
+                            egui::ComboBox::from_id_source("collision")
+                                .selected_text(format!("{:?}", self.collision))
+                                .show_ui(ui, |ui| {
+                                    for collision in [BrickCollision::All, BrickCollision::PassThrough, BrickCollision::NoWeapons, BrickCollision::None] {
+                                        ui.selectable_value(&mut self.collision, collision, format!("{:?}", collision));
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Brick Type");
+                            egui::ComboBox::from_label("What kind of brick should be output?")
+                                .selected_text(format!("{:?}", &mut self.mode))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.mode, BrickOutputMode::Brick, "Brick");
+                                    ui.selectable_value(&mut self.mode, BrickOutputMode::Plate, "Plate");
+                                    ui.selectable_value(&mut self.mode, BrickOutputMode::Tile, "Tile");
+                                    if !self.rampify {
+                                        ui.selectable_value(&mut self.mode, BrickOutputMode::MicroBrick, "MicroBrick");
+                                    }
+                                    else {
+                                        ui.horizontal(|ui| {
+                                            ui.add_space(ui.spacing().button_padding.x);
+                                            ui.colored_label(bool_color(false), "MicroBrick");
+                                        });
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Studded Tops").on_hover_text("Studded bricks use the brick asset, and the rest the smooth tile asset");
+                            ui.add_enabled(self.mode != BrickOutputMode::MicroBrick, Checkbox::new(&mut self.studs, "Only put studs on bricks with nothing on top of them."));
+                            ui.end_row();
+
+                            let (width_unit, height_unit, width_hint, height_hint) = match self.mode {
+                                BrickOutputMode::Brick => ("studs", "bricks", "Studs each voxel covers along X and Y", "Bricks each voxel stacks up, 3 plates each"),
+                                BrickOutputMode::Plate => ("studs", "plates", "Studs each voxel covers along X and Y", "Plates each voxel stacks up, a third of a brick each"),
+                                BrickOutputMode::Tile => ("studs", "plates", "Studs each voxel covers along X and Y", "Plate heights each voxel's tile is tall"),
+                                BrickOutputMode::MicroBrick => ("microbricks", "microbricks", "Microbricks each voxel covers along X and Y, 5 to a stud", "Microbricks each voxel stacks up"),
+                            };
+
+                            // Past these a voxel's brick is too big for Brickadia.
+                            let (max_width, max_height) = self.mode.max_size();
+
+                            ui.label("Brick Size").on_hover_text("Size of each voxel. Width and height can differ, even for microbricks");
+                            ui.horizontal(|ui| {
+                                ui.label("Width");
+                                ui.add(egui::DragValue::new(&mut self.width).clamp_range(1..=max_width).speed(0.1).suffix(format!(" {}", width_unit)))
+                                    .on_hover_text(width_hint);
+                                ui.label("Height");
+                                ui.add(egui::DragValue::new(&mut self.height).clamp_range(1..=max_height).speed(0.1).suffix(format!(" {}", height_unit)))
+                                    .on_hover_text(height_hint);
+                            });
+                            ui.end_row();
+
+                            ui.label("Voxel Scale").on_hover_text("Turns each voxel into a block of this many bricks per side");
+                            ui.add(egui::DragValue::new(&mut self.scale).clamp_range(1..=64).speed(0.1));
+                            ui.end_row();
+
+                            ui.label("Estimated Bricks").on_hover_text("A quick guess, simplifying usually does better than this");
+                            match (&self.estimate, &self.estimate_running) {
+                                (Some((_, Some(bricks))), _) => ui.label(format!("About {} bricks", bricks)),
+                                (Some((_, None)), _) => ui.colored_label(bool_color(false), "The voxel file couldn't be read."),
+                                (None, Some(_)) => ui.label("Estimating..."),
+                                (None, None) => ui.label("Pick a voxel file to estimate."),
+                            };
+                            ui.end_row();
+                        });
                 });
 
             ui.separator();
 
             ui.vertical_centered(|ui| {
-                if ui.button("Convert VOX to BRS").clicked() {
-                    let public = User {
-                        name: "vox2brs".into(),
-                        id: "a8033bee-6c37-4118-b4a6-cecc1d966133".parse().unwrap(),
-                    };
-
-                    let mut save = SaveData::default();
-
-                    // set the first header
-                    save.header1.author = public.clone();
-                    save.header1.host = Some(public.clone());
-                    save.header1.description = "Converted .vox file.".into();
-
-                    // set the second header
-                    save.header2
-                        .brick_owners
-                        .push(BrickOwner::from_user_bricks(public.clone(), 100));
-
-                    save.header2.brick_assets =
-                        vec![
-                            "PB_DefaultBrick".into(),
-                            "PB_DefaultMicroBrick".into(),
-                            "PB_DefaultRamp".into(),
-                            "PB_DefaultWedge".into(),
-                        ];
-
-                    if !Path::new(&self.input_file_path).exists() {
-                        println!("Voxel file not found.");
-                        return;
+                let blocker = self.convert_blocker(input_file_valid, output_dir_valid, author_id_valid);
+                let shortcut = {
+                    let input = ctx.input();
+                    input.modifiers.command && input.key_pressed(Key::Enter)
+                };
+
+                let button = ui.add_enabled(blocker.is_none(), Button::new("Convert VOX to BRS"))
+                    .on_hover_text("Ctrl+Enter")
+                    .on_disabled_hover_text(blocker.unwrap_or_default());
+
+                if button.clicked() || shortcut && blocker.is_none() {
+                    self.start_conversion(false);
+                }
+
+                if let Some(conversion) = &mut self.conversion {
+                    ui.add(ProgressBar::new(conversion.fraction).text(conversion.stage.as_str()).animate(true));
+
+                    let cancelling = conversion.cancel.load(Ordering::Relaxed);
+
+                    if ui.add_enabled(!cancelling, Button::new("Cancel")).clicked() {
+                        conversion.cancel.store(true, Ordering::Relaxed);
+                        conversion.stage = "Cancelling...".into();
                     }
+                }
 
-                    let vox_data = VoxFile::load(&self.input_file_path);
-
-                    let result = vox2brs(
-                        vox_data,
-                        save,
-                        self.mode,
-                        Some(self.width as u32),
-                        Some(self.height as u32),
-                        self.simplify,
-                        self.rampify,
-                        0,
-                        1,
-                        2,
-                        3,
-                    );
-
-                    let output_file_path = format!("{}\\{}.brs", self.output_directory, self.save_name);
-
-                    match result {
-                        Ok(out_save) => {
-                            println!("\nWriting save file...");
-                            let file = File::create(&output_file_path);
-
-                            match file {
-                                Ok(file) => {
-                                    SaveWriter::new(file, out_save)
-                                        .write()
-                                        .unwrap();
-
-                                    println!("Save written to {}", &output_file_path);
-                                },
-                                Err(_) => {
-                                    println!("Could not write to {}", &output_file_path);
-                                }
-                            }
-                        },
-                        Err(_) => {
-                            println!("Could not convert VOX file.");
-                        }
+                if let Some(error) = &self.last_error {
+                    ui.colored_label(bool_color(false), error);
+                }
+
+                if let Some(stats) = &self.last_stats {
+                    ui.label(format!(
+                        "Saved! {} voxel bricks became {} bricks ({} ramps, {} wedges) with {} colors in {:.2}s.",
+                        stats.voxel_brick_count,
+                        stats.brick_count,
+                        stats.ramp_count,
+                        stats.wedge_count,
+                        stats.color_count,
+                        stats.elapsed.as_secs_f64(),
+                    ));
+
+                    #[cfg(feature = "thumbnail")]
+                    if let Some(thumbnail) = &self.thumbnail {
+                        ui.image(thumbnail, thumbnail.size_vec2());
                     }
                 }
             });
 
+            if let Some(output) = self.confirm_overwrite.clone() {
+                egui::Window::new("Overwrite save?")
+                    .collapsible(false)
+                    .resizable(false)
+                    .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                    .show(ctx, |ui| {
+                        ui.label(format!("{} already exists. Replace it?", output.display()));
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Overwrite").clicked() {
+                                self.confirm_overwrite = None;
+                                self.start_conversion(true);
+                            }
+
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_overwrite = None;
+                            }
+                        });
+                    });
+            }
+
             TopBottomPanel::bottom("bottom").show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     ui.label("vox2brs");
@@ -251,6 +938,100 @@ impl epi::App for Vox2BrsApp {
     }
 }
 
+/// Moves `entry` to the front of `recent`, dropping the oldest entries past `MAX_RECENT`.
+fn remember(recent: &mut Vec<String>, entry: &str) {
+    recent.retain(|e| e != entry);
+    recent.insert(0, entry.into());
+    recent.truncate(MAX_RECENT);
+}
+
+/// Dropdown of recently used paths, returning the one picked this frame.
+fn recent_menu(ui: &mut egui::Ui, id: &str, recent: &[String]) -> Option<String> {
+    let mut picked = None;
+
+    ui.add_enabled_ui(!recent.is_empty(), |ui| {
+        egui::ComboBox::from_id_source(id)
+            .selected_text("Recent")
+            .width(60.0)
+            .show_ui(ui, |ui| {
+                for path in recent {
+                    if ui.selectable_label(false, path).clicked() {
+                        picked = Some(path.clone());
+                    }
+                }
+            });
+    });
+
+    picked
+}
+
+/// Checks that `directory` exists and that files can be created in it.
+#[cfg(not(target_arch = "wasm32"))]
+fn check_writable(directory: &Path) -> Result<(), String> {
+    if !directory.is_dir() {
+        return Err(format!("Output directory {} doesn't exist.", directory.display()));
+    }
+
+    let test_file = directory.join(".vox2brs_write_test");
+
+    File::create(&test_file)
+        .map_err(|error| format!("Can't write to output directory {}: {}", directory.display(), error))?;
+
+    let _ = fs::remove_file(&test_file);
+
+    Ok(())
+}
+
+/// Converts `upload`, or the file at `input` without one, into a save written to `output`.
+/// `converted` sees the save before it's written.
+fn convert(
+    input: &Path,
+    upload: Option<Arc<[u8]>>,
+    output: &Path,
+    options: &Vox2BrsOptions,
+    progress: &mut dyn FnMut(ProgressEvent),
+    converted: &mut dyn FnMut(&SaveData),
+) -> Result<ConversionStats, String> {
+    let vox_data = match upload {
+        Some(bytes) => read_source(&bytes),
+        #[cfg(not(target_arch = "wasm32"))]
+        None => load_source(input),
+        #[cfg(target_arch = "wasm32")]
+        None => return Err("Drop a voxel file in to convert it.".into()),
+    };
+
+    let vox_data = vox_data.map_err(|error| format!("Could not read voxel file: {}", error))?;
+
+    let (save, stats) = convert_source_with_progress(vox_data, input, options, progress)
+        .map_err(|error| format!("Could not convert VOX file: {}", error))?;
+
+    converted(&save);
+    write_save(output, save)?;
+
+    Ok(stats)
+}
+
+/// Writes the save to `output`.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_save(output: &Path, out_save: SaveData) -> Result<(), String> {
+    vox2brs::write_save(out_save, output)
+        .map_err(|error| format!("Could not write to {}, {}", output.display(), error))
+}
+
+/// Downloads the save, named after `output`'s file name.
+#[cfg(target_arch = "wasm32")]
+fn write_save(output: &Path, out_save: SaveData) -> Result<(), String> {
+    let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+    let mut bytes = vec![];
+
+    SaveWriter::new(&mut bytes, out_save)
+        .write()
+        .map_err(|error| format!("Could not write {}, {}", file_name, error))?;
+
+    crate::web::download(&file_name, &bytes)
+        .map_err(|error| format!("Could not download {}, {:?}", file_name, error))
+}
+
 pub fn bool_color(b: bool) -> Color32 {
     if b {
         Color32::WHITE
@@ -0,0 +1,25 @@
+#![forbid(unsafe_code)]
+#![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
+#![warn(clippy::all, rust_2018_idioms)]
+
+mod app;
+pub use app::Vox2BrsApp;
+
+#[cfg(target_arch = "wasm32")]
+mod web;
+
+// When compiling for web:
+#[cfg(target_arch = "wasm32")]
+use eframe::wasm_bindgen::{self, prelude::*};
+
+/// Entry point for the web build. Call it from JavaScript with the id of the canvas to draw in.
+///
+/// There's no Brickadia install to write into on the web, so saves are downloaded instead.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
+    console_error_panic_hook::set_once();
+
+    let app = Vox2BrsApp::default();
+    eframe::start_web(canvas_id, Box::new(app))
+}
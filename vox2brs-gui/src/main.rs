@@ -2,10 +2,8 @@
 #![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
 #![warn(clippy::all, rust_2018_idioms)]
 
-mod app;
-
 use std::env;
-use app::Vox2BrsApp;
+use vox2brs_gui::Vox2BrsApp;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
@@ -22,8 +20,7 @@ fn main() {
     };
 
     let native_options = eframe::NativeOptions {
-        initial_window_size: Some([590.0, 400.0].into()),
-        resizable: false,
+        initial_window_size: Some([590.0, 640.0].into()),
         ..Default::default()
     };
 
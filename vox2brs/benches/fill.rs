@@ -0,0 +1,47 @@
+//! Times filling a noisy 256³ grid on one thread and on every thread.
+//!
+//! Run with `cargo bench --bench fill`. Each color is filled on its own thread, so the speedup
+//! grows with the number of colors, up to the number of cores. A single color gets none.
+
+use std::time::{Duration, Instant};
+use vox2brs::simplify::{fill, Cell, FillStrategy, GridPos, DEFAULT_MAX_BOX_SIZE};
+
+/// Deterministic noisy grid with a handful of colors.
+fn noisy_grid(grid_size: GridPos, colors: u32) -> Vec<Cell> {
+    let mut seed: u32 = 12345;
+    let mut grid = vec![None; grid_size.0 * grid_size.1 * grid_size.2];
+
+    for cell in grid.iter_mut() {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let roll = (seed >> 16) % (colors + 1);
+        if roll > 0 {
+            *cell = Some(roll as u16);
+        }
+    }
+
+    grid
+}
+
+fn main() {
+    let grid_size = (256, 256, 256);
+    let grid = noisy_grid(grid_size, 8);
+
+    let time_fill = |threads: usize| -> (Duration, usize) {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+
+        let now = Instant::now();
+        let boxes = pool.install(|| fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE));
+
+        (now.elapsed(), boxes.len())
+    };
+
+    let threads = rayon::current_num_threads();
+    let (serial, serial_boxes) = time_fill(1);
+    let (parallel, parallel_boxes) = time_fill(threads);
+
+    assert_eq!(serial_boxes, parallel_boxes);
+
+    println!("{} boxes", serial_boxes);
+    println!("1 thread: {:?}", serial);
+    println!("{} threads: {:?} ({:.1}x)", threads, parallel, serial.as_secs_f64() / parallel.as_secs_f64());
+}
@@ -0,0 +1,65 @@
+//! Pictures of the simplifier's grid, for seeing where voxels end up before they're merged.
+//! Only built with the `debug-slices` feature, as it needs the `image` crate.
+
+use std::fs;
+use std::path::Path;
+use brickadia::save::Color;
+use image::{ImageError, Rgba, RgbaImage};
+use crate::simplify::GridPos;
+
+/// Writes each Z layer of a grid `size` cells large into `directory`, from `slice_000.png` at
+/// the bottom up. `cells` holds each filled cell with its index into `colors`, and empty cells
+/// are left transparent.
+///
+/// Slices are seen from above, with the source's +Y at the top, as Brickadia's Y is flipped.
+pub fn write_slices(
+    directory: &Path,
+    size: GridPos,
+    cells: impl IntoIterator<Item = (GridPos, u16)>,
+    colors: &[Color],
+) -> Result<(), ImageError> {
+    fs::create_dir_all(directory)?;
+
+    let mut slices = vec![RgbaImage::new(size.0 as u32, size.1 as u32); size.2];
+
+    for ((x, y, z), index) in cells {
+        let color = match colors.get(index as usize) {
+            Some(color) => color,
+            None => continue,
+        };
+
+        if let Some(slice) = slices.get_mut(z) {
+            if x < size.0 && y < size.1 {
+                slice.put_pixel(x as u32, y as u32, Rgba([color.r, color.g, color.b, color.a]));
+            }
+        }
+    }
+
+    for (z, slice) in slices.iter().enumerate() {
+        slice.save(directory.join(format!("slice_{:03}.png", z)))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_are_written_bottom_up() {
+        let directory = std::env::temp_dir().join(format!("vox2brs_debug_slices_{}", std::process::id()));
+        let colors = [Color { r: 255, g: 0, b: 0, a: 255 }, Color { r: 0, g: 0, b: 255, a: 255 }];
+
+        write_slices(&directory, (2, 3, 2), [((0, 0, 0), 0), ((1, 2, 1), 1)], &colors).unwrap();
+
+        let bottom = image::open(directory.join("slice_000.png")).unwrap().to_rgba8();
+        let top = image::open(directory.join("slice_001.png")).unwrap().to_rgba8();
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(bottom.dimensions(), (2, 3));
+        assert_eq!(*bottom.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*bottom.get_pixel(1, 2), Rgba([0, 0, 0, 0]));
+        assert_eq!(*top.get_pixel(1, 2), Rgba([0, 0, 255, 255]));
+    }
+}
@@ -0,0 +1,206 @@
+//! Updating a save after its voxels were edited, replacing only the bricks of cells that changed.
+//!
+//! Every voxel gets a brick of its own, so cells can be compared brick by brick. Start from an
+//! empty save and `GridSnapshot::default()`, then pass the returned snapshot to the next update.
+
+use std::collections::BTreeMap;
+use brickadia::save::{Brick, BrickColor, Color, SaveData};
+use crate::source::VoxelSource;
+use crate::{count_owner_bricks, vox2brs, ConversionStats, Vox2BrsError, Vox2BrsOptions};
+
+/// Color of each cell a save's voxel bricks were placed in, by brick position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GridSnapshot {
+    pub cells: BTreeMap<(i32, i32, i32), Color>,
+}
+
+impl GridSnapshot {
+    /// Snapshot of every brick in `save`. Only meaningful for saves with a brick per voxel.
+    pub fn of_save(save: &SaveData) -> Self {
+        let cells = save.bricks
+            .iter()
+            .filter_map(|brick| Some((brick.position, brick_color(save, brick)?)))
+            .collect();
+
+        GridSnapshot { cells }
+    }
+}
+
+/// Color of `brick`, looked up in `save`'s palette if it uses it.
+fn brick_color(save: &SaveData, brick: &Brick) -> Option<Color> {
+    match &brick.color {
+        BrickColor::Index(index) => save.header2.colors.get(*index as usize).cloned(),
+        BrickColor::Unique(color) => Some(color.clone()),
+    }
+}
+
+/// Index of `color` in the save's palette, adding it if it isn't there yet.
+fn palette_index(save: &mut SaveData, color: Color) -> u32 {
+    let colors = &mut save.header2.colors;
+
+    match colors.iter().position(|existing| *existing == color) {
+        Some(index) => index as u32,
+        None => {
+            colors.push(color);
+            colors.len() as u32 - 1
+        },
+    }
+}
+
+/// Removes the palette colors no brick uses, renumbering the bricks using the rest.
+fn prune_palette(save: &mut SaveData) {
+    let mut used = vec![false; save.header2.colors.len()];
+
+    for brick in &save.bricks {
+        if let BrickColor::Index(index) = brick.color {
+            if let Some(used) = used.get_mut(index as usize) {
+                *used = true;
+            }
+        }
+    }
+
+    // Where each color ends up once the unused ones before it are gone.
+    let new_index: Vec<u32> = used
+        .iter()
+        .scan(0, |kept, &used| {
+            let index = *kept;
+            *kept += used as u32;
+            Some(index)
+        })
+        .collect();
+
+    let mut used = used.into_iter();
+    save.header2.colors.retain(|_| used.next().unwrap_or(true));
+
+    for brick in &mut save.bricks {
+        if let BrickColor::Index(index) = &mut brick.color {
+            if let Some(&new) = new_index.get(*index as usize) {
+                *index = new;
+            }
+        }
+    }
+}
+
+/// Converts `source` and updates `save` to match it, where `previous` is the snapshot returned
+/// by the last update. Bricks of cells whose color changed, appeared or emptied are removed and
+/// added again, and every other brick in the save is left as it is.
+///
+/// Colors no brick uses anymore are removed from the palette, renumbering the bricks using
+/// the colors after them.
+///
+/// Options that merge voxels into larger bricks (simplifying, rampifying, smoothing and the
+/// baseplate) are turned off, and so are studs, as a cell's studs depend on the cell above.
+/// Centering and grid aligning shift every cell when the bounds change, which replaces every
+/// brick.
+pub fn convert_incremental(
+    mut save: SaveData,
+    previous: &GridSnapshot,
+    source: impl VoxelSource,
+    mut options: Vox2BrsOptions,
+) -> Result<(SaveData, GridSnapshot, ConversionStats), Vox2BrsError> {
+    options.simplify = false;
+    options.rampify = false;
+    options.smooth_edges = false;
+    options.studs = false;
+    options.color_merge_tolerance = None;
+    options.baseplate = None;
+    options.preserve_model_grouping = false;
+    options.extra_owners.clear();
+    options.brick_assets = None;
+
+    // Converted into an empty save sharing the assets, so asset indices carry over.
+    let mut scratch = SaveData::default();
+    scratch.header2.brick_assets = save.header2.brick_assets.clone();
+    scratch.header2.brick_owners = save.header2.brick_owners.clone();
+
+    let (scratch, mut stats) = vox2brs(source, scratch, options)?;
+    let current = GridSnapshot::of_save(&scratch);
+
+    let changed = |position: &(i32, i32, i32)| previous.cells.get(position) != current.cells.get(position);
+
+    save.bricks.retain(|brick| !(previous.cells.contains_key(&brick.position) && changed(&brick.position)));
+
+    for brick in scratch.bricks.iter().filter(|brick| changed(&brick.position)) {
+        let mut brick = brick.clone();
+
+        if let (BrickColor::Index(_), Some(color)) = (&brick.color, brick_color(&scratch, &brick)) {
+            brick.color = BrickColor::Index(palette_index(&mut save, color));
+        }
+
+        save.bricks.push(brick);
+    }
+
+    prune_palette(&mut save);
+    count_owner_bricks(&mut save);
+
+    stats.brick_count = save.bricks.len();
+    stats.color_count = save.header2.colors.len();
+
+    Ok((save, current, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{SourceCopy, SourceModel, SourceVoxel};
+    use crate::BrickOutputMode;
+
+    struct Row {
+        colors: Vec<u32>,
+    }
+
+    impl VoxelSource for Row {
+        fn palette(&self) -> Vec<Color> {
+            vec![Color { r: 255, g: 0, b: 0, a: 255 }, Color { r: 0, g: 0, b: 255, a: 255 }]
+        }
+
+        fn models(&self) -> Vec<SourceModel> {
+            vec![SourceModel {
+                size: (self.colors.len() as u32, 1, 1),
+                voxels: self.colors
+                    .iter()
+                    .enumerate()
+                    .map(|(x, &color_index)| SourceVoxel { position: (x as u32, 0, 0), color_index })
+                    .collect(),
+                position: None,
+                rotation: None,
+            }]
+        }
+
+        fn copies(&self) -> Vec<SourceCopy> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn only_changed_cells_are_replaced() {
+        let options = || Vox2BrsOptions::default().mode(BrickOutputMode::MicroBrick).width(1).height(1);
+        let mut save = SaveData::default();
+        save.header2.brick_assets = vec!["PB_DefaultBrick".into(), "PB_DefaultMicroBrick".into()];
+
+        let (save, snapshot, stats) = convert_incremental(save, &GridSnapshot::default(), Row { colors: vec![0, 0, 0] }, options()).unwrap();
+        assert_eq!(stats.brick_count, 3);
+        assert_eq!(snapshot.cells.len(), 3);
+        assert_eq!(save.header2.colors.len(), 1);
+
+        let before = save.bricks.clone();
+        let (save, next, stats) = convert_incremental(save, &snapshot, Row { colors: vec![0, 1, 0] }, options()).unwrap();
+        assert_eq!(stats.brick_count, 3);
+        assert_eq!(save.header2.colors.len(), 2);
+
+        // The untouched bricks stay in place, and the recolored one is added after them.
+        let positions: Vec<_> = save.bricks.iter().map(|brick| brick.position).collect();
+        assert_eq!(positions, vec![before[0].position, before[2].position, before[1].position]);
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(0)));
+        assert!(matches!(save.bricks[2].color, BrickColor::Index(1)));
+
+        let changed: Vec<_> = snapshot.cells.keys().filter(|cell| snapshot.cells.get(*cell) != next.cells.get(*cell)).collect();
+        assert_eq!(changed, vec![&before[1].position]);
+
+        // Red is gone once every cell is blue, so only blue is left in the palette.
+        let (save, _, stats) = convert_incremental(save, &next, Row { colors: vec![1, 1, 1] }, options()).unwrap();
+        assert_eq!(stats.color_count, 1);
+        assert_eq!(save.header2.colors, vec![Color { r: 0, g: 0, b: 255, a: 255 }]);
+        assert!(save.bricks.iter().all(|brick| matches!(brick.color, BrickColor::Index(0))));
+    }
+}
@@ -1,11 +1,39 @@
-use std::time::Instant;
-use brickadia::save::SaveData;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use brickadia::save::{BrickOwner, SaveData, User};
+use brickadia::write::SaveWriter;
 use clap::{ArgEnum};
-use brickadia::save::{Brick, BrickColor, Color, Size};
-use create_vox::{Model, VoxFile};
-use rampifier::{Rampifier, RampifierConfig};
+use brickadia::save::{Brick, BrickColor, Collision, Color, Direction, Rotation, Size};
+use rampifier::Rampifier;
+pub use rampifier::RampifierConfig;
+use uuid::Uuid;
+
+#[cfg(feature = "debug-slices")]
+pub mod debug;
+pub mod incremental;
+pub mod magica;
+pub mod palette;
+pub mod qb;
+pub mod ramps;
+pub mod schematic;
+pub mod simplify;
+pub mod smooth;
+pub mod source;
+#[cfg(feature = "thumbnail")]
+pub mod thumbnail;
+pub mod vxm;
+
+use ramps::WallAxis;
+use simplify::{Cell, FillStrategy, GridPos, GrowOrder, HollowMode, SparseGrid};
+use source::{MaterialKind, SourceCopy, SourceModel, VoxelSource};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum BrickOutputMode {
     /// Default 1x1 brick.
     Brick,
@@ -15,428 +43,4662 @@ pub enum BrickOutputMode {
 
     /// Default 1x1x1 micro brick.
     MicroBrick,
+
+    /// Default 1x1f tile, a plate without studs.
+    Tile,
 }
 
-fn gamma_correction(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+impl BrickOutputMode {
+    /// Width and height used when they aren't given.
+    pub fn default_size(self) -> (u32, u32) {
+        match self {
+            BrickOutputMode::Brick => (1, 3),
+            BrickOutputMode::Plate | BrickOutputMode::MicroBrick | BrickOutputMode::Tile => (1, 1),
+        }
+    }
 
-    let r = (r as f32) / 255.0;
-    let g = (g as f32) / 255.0;
-    let b = (b as f32) / 255.0;
+    /// Half size in Brickadia units of one unit of width, depth and height.
+    pub fn unit_size(self) -> (u32, u32, u32) {
+        match self {
+            BrickOutputMode::Brick => (5, 5, 6),
+            BrickOutputMode::Plate | BrickOutputMode::Tile => (5, 5, 2),
+            BrickOutputMode::MicroBrick => (1, 1, 1),
+        }
+    }
 
-    let r = r.powf(2.2);
-    let g = g.powf(2.2);
-    let b = b.powf(2.2);
+    /// Largest width and height a voxel's brick can have before it's too big for Brickadia.
+    pub fn max_size(self) -> (u32, u32) {
+        let (width, _, height) = self.unit_size();
+        (MAX_PROCEDURAL_SIZE / width, MAX_PROCEDURAL_SIZE / height)
+    }
+}
 
-    let r = (r * 255.0) as u8;
-    let g = (g * 255.0) as u8;
-    let b = (b * 255.0) as u8;
+/// An axis of the source's coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
 
-    (r, g, b)
+impl Axis {
+    /// Moves this axis of `pos` to Z, cycling the others along so the handedness is kept.
+    fn to_z_up(self, pos: (i32, i32, i32)) -> (i32, i32, i32) {
+        let (x, y, z) = pos;
+
+        match self {
+            Axis::X => (y, z, x),
+            Axis::Y => (z, x, y),
+            Axis::Z => (x, y, z),
+        }
+    }
 }
 
-pub fn vox2brs(
-    in_vox_data: VoxFile,
-    mut brs_save: SaveData,
-    mode: BrickOutputMode,
-    width: Option<u32>,
-    height: Option<u32>,
-    simplify: bool,
-    rampify: bool,
-    brick_asset_index: u32,
-    microbrick_asset_index: u32,
-    ramp_asset_index: u32,
-    wedge_asset_index: u32,
-) -> Result<SaveData, ()> {
-    let now = Instant::now();
+/// Brickadia's brick materials.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, ArgEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum BrickMaterial {
+    Plastic,
+    Glow,
+    Metallic,
+    Glass,
+    Hologram,
+}
 
-    println!("Running vox2brs...");
-    println!("Loading colors...");
+/// A brick asset, by its index in the save's brick asset list or by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrickAsset {
+    Index(u32),
+    Name(String),
+}
 
-    // Add voxel colors to brickadia save color palette.
-    for vox_color in in_vox_data.palette {
-        // Color correction
-        let rgb = gamma_correction(vox_color.r, vox_color.g, vox_color.b);
+impl From<u32> for BrickAsset {
+    fn from(index: u32) -> Self {
+        BrickAsset::Index(index)
+    }
+}
 
-        let brs_color = Color {
-            r: rgb.0,
-            g: rgb.1,
-            b: rgb.2,
-            a: 255,
+impl From<&str> for BrickAsset {
+    fn from(name: &str) -> Self {
+        BrickAsset::Name(name.into())
+    }
+}
+
+impl From<String> for BrickAsset {
+    fn from(name: String) -> Self {
+        BrickAsset::Name(name)
+    }
+}
+
+/// Brick assets of saves made by `new_save`, in the order the default asset indices expect.
+pub const DEFAULT_BRICK_ASSETS: [&str; 5] = [
+    "PB_DefaultBrick",
+    "PB_DefaultMicroBrick",
+    "PB_DefaultTile",
+    "PB_DefaultRamp",
+    "PB_DefaultWedge",
+];
+
+impl BrickMaterial {
+    /// Name of the material in the save's material list.
+    pub fn asset_name(self) -> &'static str {
+        match self {
+            BrickMaterial::Plastic => "BMC_Plastic",
+            BrickMaterial::Glow => "BMC_Glow",
+            BrickMaterial::Metallic => "BMC_Metallic",
+            BrickMaterial::Glass => "BMC_Glass",
+            BrickMaterial::Hologram => "BMC_Hologram",
+        }
+    }
+}
+
+/// Which collisions the converted bricks take part in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, ArgEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum BrickCollision {
+    /// Bricks collide with everything, as placed bricks do in-game.
+    All,
+    /// Players and weapons pass through, but bricks can still be clicked and used with tools.
+    PassThrough,
+    /// Bricks collide with players, but weapons pass through.
+    NoWeapons,
+    /// Nothing collides with the bricks, and tools can't select them.
+    None,
+}
+
+impl BrickCollision {
+    /// Brickadia's collision flags for bricks with this collision.
+    pub fn flags(self) -> Collision {
+        let (player, weapon, interaction, tool) = match self {
+            BrickCollision::All => (true, true, true, true),
+            BrickCollision::PassThrough => (false, false, true, true),
+            BrickCollision::NoWeapons => (true, false, true, true),
+            BrickCollision::None => (false, false, false, false),
         };
 
-        brs_save.header2.colors.push(brs_color);
+        Collision { player, weapon, interaction, tool }
     }
+}
 
-    println!(" - Done\n");
+/// Brick material matching a MagicaVoxel material, for materials Brickadia has an equivalent of.
+fn auto_material(kind: MaterialKind) -> Option<BrickMaterial> {
+    match kind {
+        MaterialKind::Metal => Some(BrickMaterial::Metallic),
+        MaterialKind::Glass => Some(BrickMaterial::Glass),
+        MaterialKind::Emit => Some(BrickMaterial::Glow),
+        _ => None,
+    }
+}
 
-    let (brick_size, brick_asset): ((u32, u32), u32) = match mode {
-        BrickOutputMode::Brick => {
-            let w = width.unwrap_or(1) * 5;
-            let h = height.unwrap_or(3) * 6;
-            ((w, h), brick_asset_index)
-        },
-        BrickOutputMode::Plate => {
-            let w = width.unwrap_or(1) * 5;
-            let h = height.unwrap_or(1) * 2;
-            ((w, h), brick_asset_index)
-        },
-        BrickOutputMode::MicroBrick => {
-            let w = width.unwrap_or(1);
-            let h = height.unwrap_or(1);
-            ((w, h), microbrick_asset_index)
-        },
-    };
+/// Reasons a conversion can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vox2BrsError {
+    /// There are no voxels to convert.
+    EmptyModel,
 
-    println!("Converting voxels into bricks...");
+    /// A color index doesn't fit in the simplifier's grid, or the rampifier's for indices above 255.
+    TooManyColors(u32),
 
-    let models_len = in_vox_data.models.len() + in_vox_data.copies.len();
+    /// The simplifier's grid is too large to allocate.
+    GridTooLarge,
 
-    fn row_major_rotation(pos: (i32, i32, i32), rotation_byte: u8) -> (i32, i32, i32) {
-        let (x, y, z) = pos;
+    /// An asset index doesn't point into the save's brick asset list.
+    InvalidAssetIndex(u32),
 
-        let r1_i = (rotation_byte >> 0) & 0b11;
-        let r2_i = (rotation_byte >> 2) & 0b11;
+    /// An asset name isn't in the save's brick asset list.
+    UnknownAsset(String),
 
-        let r1_sign = if (rotation_byte >> 4) & 0b1 == 1 { -1 } else { 1 };
-        let r2_sign = if (rotation_byte >> 5) & 0b1 == 1 { -1 } else { 1 };
-        let r3_sign = if (rotation_byte >> 6) & 0b1 == 1 { -1 } else { 1 };
+    /// The conversion made more bricks than the limit allows.
+    TooManyBricks { produced: usize, limit: usize },
 
-        let mut m = (
-            [0, 0, 0],
-            [0, 0, 0],
-            [r3_sign, r3_sign, r3_sign],
-        );
+    /// A voxel's brick would be empty or larger than `MAX_PROCEDURAL_SIZE`, from the width, depth
+    /// and height times `voxel_scale`.
+    InvalidSize { width: u32, depth: u32, height: u32 },
 
-        m.0[r1_i as usize] = r1_sign;
-        m.1[r2_i as usize] = r2_sign;
-        m.2[r1_i as usize] = 0;
-        m.2[r2_i as usize] = 0;
+    /// A model is more voxels across than `max_dimension` allows, and downsampling is off.
+    ModelTooLarge { size: u32, limit: u32 },
 
-        // Gotta do some funky stuff here because the coordinate system is different in brs...
-        let x_prime =  x * m.0[0] +  y * m.0[1] +  z * m.0[2];
-        let y_prime =  x * m.1[0] +  y * m.1[1] +  z * m.1[2];
-        let z_prime =  x * m.2[0] +  y * m.2[1] +  z * m.2[2];
+    /// The requested save version is newer than any version vox2brs knows about.
+    UnknownSaveVersion(u16),
 
-        (x_prime, y_prime, z_prime)
-    }
+    /// The requested save version can't store something the conversion produced.
+    UnsupportedBySaveVersion { version: u16, feature: &'static str, required: u16 },
 
-    let model_to_bricks = |model: &Model, pos: (i32, i32, i32), rot_option: Option<u8>, bricks: &mut Vec<Brick>| {
-        let size = (model.size.0 as i32, model.size.1 as i32, model.size.2 as i32);
+    /// The voxel file couldn't be read.
+    InvalidFile(String),
+
+    /// The save couldn't be written.
+    WriteFailed(String),
 
-        if let Some(rot) = rot_option {
-            row_major_rotation(pos, rot);
-            println!("model rotation: {:#016b}", rot);
+    /// The conversion's cancel flag was set before it finished.
+    Cancelled,
+}
+
+impl fmt::Display for Vox2BrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Vox2BrsError::EmptyModel => write!(f, "the model has no voxels to convert"),
+            Vox2BrsError::TooManyColors(index) => write!(f, "color index {} is too large to simplify", index),
+            Vox2BrsError::GridTooLarge => write!(f, "the model is too large to simplify"),
+            Vox2BrsError::InvalidAssetIndex(index) => write!(f, "brick asset index {} is not in the save's asset list", index),
+            Vox2BrsError::UnknownAsset(name) => write!(f, "brick asset {} is not in the save's asset list", name),
+            Vox2BrsError::TooManyBricks { produced, limit } => write!(f, "the conversion made {} bricks, more than the limit of {}", produced, limit),
+            Vox2BrsError::InvalidSize { width, depth, height } => {
+                write!(f, "a {}x{}x{} brick per voxel is empty or larger than Brickadia allows", width, depth, height)
+            },
+            Vox2BrsError::ModelTooLarge { size, limit } => write!(f, "a model is {} voxels across, more than the limit of {}", size, limit),
+            Vox2BrsError::UnknownSaveVersion(version) => write!(f, "save version {} is unknown, the latest is {}", version, LATEST_SAVE_VERSION),
+            Vox2BrsError::UnsupportedBySaveVersion { version, feature, required } => {
+                write!(f, "save version {} can't store {}, which needs version {} or newer", version, feature, required)
+            },
+            Vox2BrsError::InvalidFile(error) => write!(f, "the voxel file couldn't be read: {}", error),
+            Vox2BrsError::WriteFailed(error) => write!(f, "the save couldn't be written: {}", error),
+            Vox2BrsError::Cancelled => write!(f, "the conversion was cancelled"),
         }
+    }
+}
 
-        for voxel in model.voxels.iter() {
+impl std::error::Error for Vox2BrsError {}
 
-            let mut vox_pos = (
-                voxel.position.0 as i32 - size.0 / 2,
-                voxel.position.1 as i32 - size.1 / 2,
-                voxel.position.2 as i32 - size.2 / 2
-            );
+/// Largest procedural brick Brickadia loads along any axis, in half units.
+pub const MAX_PROCEDURAL_SIZE: u32 = 1000;
 
-            if let Some(rot) = rot_option {
-                vox_pos = row_major_rotation(vox_pos, rot);
-            }
+/// Latest BRS save version vox2brs can target.
+pub const LATEST_SAVE_VERSION: u16 = 10;
 
-            let pos = (
-                vox_pos.0 + pos.0,
-                vox_pos.1 + pos.1,
-                vox_pos.2 + pos.2,
-            );
+/// Save version that added brick and save components.
+const COMPONENTS_SAVE_VERSION: u16 = 8;
 
-            let mut brick = Brick::default();
-            brick.size = Size::Procedural(brick_size.0, brick_size.0, brick_size.1);
-            brick.asset_name_index = brick_asset;
+/// Save version that added material intensity, used by glowing colors.
+const MATERIAL_INTENSITY_SAVE_VERSION: u16 = 9;
 
-            brick.position = (
-                pos.0 * brick_size.0 as i32 * 2 + brick_size.0 as i32,
-                -pos.1 * brick_size.0 as i32 * 2 + brick_size.0 as i32,
-                pos.2 * brick_size.1 as i32 * 2 + brick_size.1 as i32,
-            );
+/// Checks that `save` can be written as save `version`, naming the first feature that can't be.
+pub fn check_save_version(save: &SaveData, version: u16) -> Result<(), Vox2BrsError> {
+    if version > LATEST_SAVE_VERSION {
+        return Err(Vox2BrsError::UnknownSaveVersion(version));
+    }
+
+    let unsupported = |feature, required| Vox2BrsError::UnsupportedBySaveVersion { version, feature, required };
 
-            brick.color = BrickColor::Index(voxel.color_index as u32 - 1);
+    let has_components = !save.components.is_empty() || save.bricks.iter().any(|brick| !brick.components.is_empty());
+    if has_components && version < COMPONENTS_SAVE_VERSION {
+        return Err(unsupported("components", COMPONENTS_SAVE_VERSION));
+    }
 
-            brick.owner_index = 1;
+    let default_intensity = Brick::default().material_intensity;
+    let has_intensity = save.bricks.iter().any(|brick| brick.material_intensity != default_intensity);
+    if has_intensity && version < MATERIAL_INTENSITY_SAVE_VERSION {
+        return Err(unsupported("material intensity (used by --emissive glow)", MATERIAL_INTENSITY_SAVE_VERSION));
+    }
 
-            bricks.push(brick);
-        }
-    };
+    Ok(())
+}
+
+/// Progress reported by `vox2brs` while it converts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// Started loading the palette.
+    LoadingColors,
+
+    /// Loaded `count` colors into the save's palette.
+    ColorsLoaded { count: usize },
+
+    /// Started converting `count` models into bricks.
+    ReadingModels { count: usize },
+
+    /// Converted all models into `bricks` bricks.
+    ModelsRead { count: usize, bricks: usize },
+
+    /// Started simplifying the bricks.
+    Simplifying,
+
+    /// Started generating ramps over a grid of `voxels` cells.
+    Rampifying { voxels: usize },
+
+    /// Generated `ramps` ramps.
+    Rampified { ramps: usize, elapsed: Duration },
+
+    /// Started merging the remaining cells into bricks.
+    FillingGaps,
+
+    /// Picked the order bricks grow in for one group of cells, with `optimize_axis_order`.
+    GrowOrderChosen { order: GrowOrder },
+
+    /// Merged the remaining cells into `bricks` bricks.
+    GapsFilled { bricks: usize },
 
-    for model in in_vox_data.models.iter() {
-        let pos = model.position.unwrap_or((0, 0, 0));
-        model_to_bricks(model, pos, model.rotation, &mut brs_save.bricks);
+    /// The conversion is done.
+    Finished { bricks: usize, elapsed: Duration },
+
+    /// Something looks wrong, but the conversion carries on.
+    Warning(String),
+}
+
+impl ProgressEvent {
+    /// Rough fraction of the conversion that's done once this event is reported, from 0 to 1.
+    /// Warnings don't mark a stage, so they have no fraction.
+    pub fn fraction(&self) -> Option<f32> {
+        let fraction = match self {
+            ProgressEvent::LoadingColors => 0.0,
+            ProgressEvent::ColorsLoaded { .. } => 0.05,
+            ProgressEvent::ReadingModels { .. } => 0.1,
+            ProgressEvent::ModelsRead { .. } => 0.3,
+            ProgressEvent::Simplifying => 0.35,
+            ProgressEvent::Rampifying { .. } => 0.4,
+            ProgressEvent::Rampified { .. } => 0.6,
+            ProgressEvent::FillingGaps => 0.65,
+            ProgressEvent::GapsFilled { .. } => 0.95,
+            ProgressEvent::Finished { .. } => 1.0,
+            ProgressEvent::GrowOrderChosen { .. } | ProgressEvent::Warning(_) => return None,
+        };
+
+        Some(fraction)
     }
+}
 
-    for model_copy in in_vox_data.copies.iter() {
-        if let Some(model) = in_vox_data.get_model_by_id(model_copy.model_id) {
-            let pos = model_copy.position.unwrap_or((0, 0, 0));
-            model_to_bricks(model, pos, model_copy.rotation, &mut brs_save.bricks);
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressEvent::LoadingColors => write!(f, "Loading colors..."),
+            ProgressEvent::ColorsLoaded { count } => write!(f, " - Loaded {} colors.", count),
+            ProgressEvent::ReadingModels { .. } => write!(f, "Converting voxels into bricks..."),
+            ProgressEvent::ModelsRead { count, .. } => write!(f, " - Read {} models.", count),
+            ProgressEvent::Simplifying => write!(f, "Simplifying BRS..."),
+            ProgressEvent::Rampifying { voxels } => write!(f, "Generating ramps for {} voxels...", voxels),
+            ProgressEvent::Rampified { ramps, elapsed } => write!(f, " - Generated {} ramps in {}s", ramps, elapsed.as_secs_f64()),
+            ProgressEvent::FillingGaps => write!(f, "Filling Gaps..."),
+            ProgressEvent::GapsFilled { .. } => write!(f, " - Gaps filled."),
+            ProgressEvent::GrowOrderChosen { order } => {
+                let axis = |axis: usize| ["X", "Y", "Z"][axis];
+                write!(f, " - Growing bricks along {}, {}, then {}.", axis(order[0]), axis(order[1]), axis(order[2]))
+            },
+            ProgressEvent::Finished { bricks, elapsed } => write!(f, "Finished vox2brs in {}s.\n - Created {} bricks.", elapsed.as_secs_f64(), bricks),
+            ProgressEvent::Warning(message) => write!(f, "Warning: {}", message),
         }
     }
+}
 
-    println!(" - Read {} models.", models_len);
+/// Numbers gathered during a conversion.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ConversionStats {
+    /// Bricks made from voxels before simplifying, after skipped colors and interior voxels are
+    /// left out. Each scaled or downsampled voxel is one brick, so this can differ from the
+    /// source's voxel count.
+    pub voxel_brick_count: usize,
 
-    // I ripped this from rampifier because I'm lazy. Too bad!
-    if simplify || rampify {
-        // Move brick vector so we can re-write the optimized version into the save.
-        let bricks = brs_save.bricks;
-        brs_save.bricks = vec![];
+    /// Voxels left out by `surface_only` because every side was covered.
+    pub interior_voxel_count: usize,
 
-        println!("\nSimplifying BRS...");
+    /// Bricks in the save, including ramps.
+    pub brick_count: usize,
 
-        let brick_size = if rampify {
-            (5, 2)
-        }
-        else {
-            (brick_size.0 as i32, brick_size.1 as i32)
-        };
+    /// Ramps and wedges generated by the rampifier.
+    pub ramp_count: usize,
 
-        let fix_brick_pos = |brick: &Brick| -> (i32, i32, i32) {
-            let (mut x, mut  y, mut  z) = brick.position;
-            if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-                x -= w_half as i32;
-                y -= l_half as i32;
-                z -= h_half as i32;
+    /// Wedges added by `smooth_edges`. The save would have `brick_count - wedge_count` bricks without them.
+    pub wedge_count: usize,
 
-                x /= brick_size.0 * 2;
-                y /= brick_size.0 * 2;
-                z /= brick_size.1 * 2;
+    /// Voxels in simplified bricks below `min_brick_volume` that were left out.
+    pub dropped_voxel_count: usize,
 
-                return (x, y, z);
-            }
+    /// Voxels in simplified bricks below `min_brick_volume` recolored by `merge_small_bricks`.
+    pub merged_voxel_count: usize,
 
-            (0, 0, 0)
-        };
+    /// Bricks `merge_colors` saved, compared to simplifying with colors kept apart.
+    pub merged_color_savings: usize,
 
-        // Find bounds for bricks.
-        let mut min_bounds = (i32::MAX, i32::MAX, i32::MAX);
-        let mut max_bounds = (i32::MIN, i32::MIN, i32::MIN);
+    /// Bricks `weld` saved by refilling. The save would have `brick_count + weld_savings` bricks without it.
+    pub weld_savings: usize,
 
-        for brick in &bricks {
-            if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-                let w = w_half as i32 / brick_size.0;
-                let l = l_half as i32 / brick_size.0;
-                let h = h_half as i32 / brick_size.1;
+    /// Colors added to the save's palette, or given to bricks directly with `use_unique_colors`.
+    pub color_count: usize,
 
-                let pos = fix_brick_pos(&brick);
+    /// Voxels per side of the blocks `downsample` merged into single voxels, if models were downsampled.
+    pub downsample_factor: Option<u32>,
 
-                min_bounds.0 = min_bounds.0.min(pos.0);
-                min_bounds.1 = min_bounds.1.min(pos.1);
-                min_bounds.2 = min_bounds.2.min(pos.2);
+    /// Voxels left out for being past their model's size, which malformed files can have.
+    pub out_of_range_voxel_count: usize,
 
-                let pos = (
-                    pos.0 + w + 1,
-                    pos.1 + l + 1,
-                    pos.2 + h + 1,
-                );
+    /// Cells in the largest dense grid the simplifier made. Mostly empty chunks use a sparse
+    /// grid instead and don't count, so this bounds the simplifier's memory use.
+    pub largest_dense_grid: usize,
 
-                max_bounds.0 = max_bounds.0.max(pos.0);
-                max_bounds.1 = max_bounds.1.max(pos.1);
-                max_bounds.2 = max_bounds.2.max(pos.2);
-            }
+    pub elapsed: Duration,
+
+    /// How `elapsed` splits up between the stages of the conversion.
+    pub timings: StageTimings,
+}
+
+/// Time spent in each stage of a conversion. Stages that didn't run took no time.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct StageTimings {
+    /// Reading the palette and working out the save's colors.
+    pub colors: Duration,
+
+    /// Turning every model's voxels into bricks.
+    pub models: Duration,
+
+    /// Simplifying bricks into larger ones, leaving out time spent rampifying.
+    pub simplify: Duration,
+
+    /// Generating ramps, over every chunk.
+    pub rampify: Duration,
+
+    /// Moving simplified bricks from the grid back to where the model was.
+    pub offset: Duration,
+
+    /// The baseplate, rotation, materials and checks at the end.
+    pub finish: Duration,
+}
+
+/// Tuning for the rampifier pass.
+#[derive(Clone)]
+pub struct RampifyOptions {
+    /// Base rampifier configuration. The ramp and wedge indices are always
+    /// replaced with the asset indices set in `Vox2BrsOptions`.
+    pub config: RampifierConfig,
+
+    /// Generate ramps on upward facing slopes.
+    pub floors: bool,
+
+    /// Generate ramps on downward facing slopes.
+    pub ceilings: bool,
+
+    /// Generate ramps on stepped walls facing +X and -X.
+    pub walls_x: bool,
+
+    /// Generate ramps on stepped walls facing +Y and -Y.
+    pub walls_y: bool,
+}
+
+impl Default for RampifyOptions {
+    fn default() -> Self {
+        Self {
+            config: RampifierConfig::default(),
+            floors: true,
+            ceilings: true,
+            walls_x: false,
+            walls_y: false,
         }
+    }
+}
 
-        let grid_size = (
-            (max_bounds.0 - min_bounds.0) as usize,
-            (max_bounds.1 - min_bounds.1) as usize,
-            (max_bounds.2 - min_bounds.2) as usize,
-        );
+/// Converts a color into linear space, darkening midtones.
+///
+/// MagicaVoxel and Brickadia both store sRGB colors, so this is only applied
+/// when explicitly requested.
+fn gamma_correction(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
 
-        let get_index = |pos: (usize, usize, usize)| -> usize {
-            pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
-        };
+    let r = (r as f32) / 255.0;
+    let g = (g as f32) / 255.0;
+    let b = (b as f32) / 255.0;
 
-        let mut grid: Vec<Option<u8>> = vec![None; grid_size.0 * grid_size.1 * grid_size.2];
+    let r = r.powf(2.2);
+    let g = g.powf(2.2);
+    let b = b.powf(2.2);
 
-        for brick in &bricks {
-            if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-                let pos = fix_brick_pos(&brick);
-                let pos = (
-                    (pos.0 - min_bounds.0) as usize,
-                    (pos.1 - min_bounds.1) as usize,
-                    (pos.2 - min_bounds.2) as usize,
-                );
+    let r = (r * 255.0) as u8;
+    let g = (g * 255.0) as u8;
+    let b = (b * 255.0) as u8;
 
-                let w = w_half as usize / brick_size.0 as usize;
-                let l = l_half as usize / brick_size.0 as usize;
-                let h = h_half as usize / brick_size.1 as usize;
+    (r, g, b)
+}
 
-                for i in 0..w {
-                    for j in 0..l {
-                        for k in 0..h {
-                            let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
+/// Finds a material in the save's material list, adding it if it's missing.
+fn material_index(brs_save: &mut SaveData, material: &str) -> u32 {
+    let materials = &mut brs_save.header2.materials;
 
-                            if let BrickColor::Index(index) = brick.color {
-                                grid[get_index(pos)] = Some(index as u8);
-                            }
-                        }
-                    }
-                }
-            }
+    match materials.iter().position(|m| m == material) {
+        Some(index) => index as u32,
+        None => {
+            materials.push(material.into());
+            materials.len() as u32 - 1
         }
+    }
+}
 
-        if rampify {
-            println!("Generating ramps...");
+/// Roughly how many bricks converting `source` with `options` would make, quickly enough to
+/// redo whenever the options change.
+///
+/// Without simplifying every voxel is a block of `voxel_scale` bricks per side. When
+/// simplifying, runs of same colored voxels along X are counted, leaving out runs matching the
+/// one in the row behind them. The simplifier also merges along Z, so it usually does better.
+/// `surface_only` is applied to each model on its own. Rotations, `flatten`, `max_box_size`
+/// and `min_brick_volume` are ignored.
+pub fn estimate_brick_count(source: &dyn VoxelSource, options: &Vox2BrsOptions) -> usize {
+    let simplify = options.simplify || options.rampify || options.smooth_edges;
+    let scale = options.voxel_scale.max(1) as usize;
 
-            let vox_count = grid.len();
+    let model_estimate = |model: &SourceModel| -> usize {
+        let voxels: Vec<_> = model.voxels
+            .iter()
+            .filter(|voxel| !options.skip_color_indices.contains(&voxel.color_index))
+            .filter(|voxel| in_z_range(options.z_range, voxel.position.2))
+            .collect();
 
-            let rampifier_config = RampifierConfig {
-                ramp_index: ramp_asset_index,
-                wedge_index: wedge_asset_index,
-                ..RampifierConfig::default()
+        let voxels = if options.surface_only {
+            let filled: HashSet<(u32, u32, u32)> = voxels.iter().map(|voxel| voxel.position).collect();
+            let is_filled = |x: Option<u32>, y: Option<u32>, z: Option<u32>| match (x, y, z) {
+                (Some(x), Some(y), Some(z)) => filled.contains(&(x, y, z)),
+                _ => false,
             };
 
-            let mut rampifier = Rampifier::new(
-                grid_size,
-                grid,
-                rampifier_config
-            );
+            voxels
+                .into_iter()
+                .filter(|voxel| {
+                    let (x, y, z) = voxel.position;
+                    let covered = is_filled(x.checked_sub(1), Some(y), Some(z))
+                        && is_filled(x.checked_add(1), Some(y), Some(z))
+                        && is_filled(Some(x), y.checked_sub(1), Some(z))
+                        && is_filled(Some(x), y.checked_add(1), Some(z))
+                        && is_filled(Some(x), Some(y), z.checked_sub(1))
+                        && is_filled(Some(x), Some(y), z.checked_add(1));
+                    !covered
+                })
+                .collect()
+        } else {
+            voxels
+        };
+
+        if !simplify {
+            return voxels.len() * scale * scale * scale;
+        }
+
+        // Voxels ordered by layer, then row, then along the row.
+        let cells: BTreeMap<(u32, u32, u32), u32> = voxels
+            .into_iter()
+            .map(|voxel| ((voxel.position.2, voxel.position.1, voxel.position.0), voxel.color_index))
+            .collect();
+
+        // Runs of each row, as their first and last X and their color.
+        let mut rows: BTreeMap<(u32, u32), Vec<(u32, u32, u32)>> = BTreeMap::new();
 
-            let now = Instant::now();
+        for ((z, y, x), color) in cells {
+            let row = rows.entry((z, y)).or_default();
 
-            // Generate ramps for floor and ceiling.
-            let ramps = &mut rampifier.generate_ramps(true);
-            let ramps2 = &mut rampifier.generate_ramps(false);
+            match row.last_mut() {
+                Some((_, end, run_color)) if *end + 1 == x && *run_color == color => *end = x,
+                _ => row.push((x, x, color)),
+            }
+        }
+
+        rows.iter()
+            .map(|(&(z, y), row)| {
+                let behind = y.checked_sub(1).and_then(|y| rows.get(&(z, y)));
+                row.iter().filter(|run| behind.map_or(true, |behind| !behind.contains(run))).count()
+            })
+            .sum()
+    };
 
-            let ramp_count = ramps.len();
-            let ramp2_count = ramps2.len();
+    let estimates: Vec<usize> = source.models().iter().map(model_estimate).collect();
+    let copies: usize = source.copies().iter().filter_map(|copy| estimates.get(copy.model_index)).sum();
 
-            brs_save.bricks.append(ramps);
-            brs_save.bricks.append(ramps2);
+    estimates.iter().sum::<usize>() + copies
+}
+
+/// Finds the minimum and maximum corners of the bricks, in Brickadia units.
+pub fn brick_bounds(bricks: &[Brick]) -> Option<((i32, i32, i32), (i32, i32, i32))> {
+    let mut min_bounds = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max_bounds = (i32::MIN, i32::MIN, i32::MIN);
 
-            println!(" - Processed {} voxels", vox_count);
-            println!(" - Generated {} ramps in {}s\n", ramp_count + ramp2_count, now.elapsed().as_millis() as f64 / 1000.0);
+    for brick in bricks.iter() {
+        if let Some((w, l, h)) = ramps::world_size(brick) {
+            let (x, y, z) = brick.position;
 
-            // Sets the voxels occupied by ramps to empty.
-            rampifier.remove_occupied_voxels();
+            min_bounds.0 = min_bounds.0.min(x - w as i32);
+            min_bounds.1 = min_bounds.1.min(y - l as i32);
+            min_bounds.2 = min_bounds.2.min(z - h as i32);
 
-            // Move grid back out of the rampifier to do further processing.
-            grid = rampifier.move_grid();
+            max_bounds.0 = max_bounds.0.max(x + w as i32);
+            max_bounds.1 = max_bounds.1.max(y + l as i32);
+            max_bounds.2 = max_bounds.2.max(z + h as i32);
         }
+    }
 
-        let box_remove = |g: &mut Vec<Option<u8>>, pos: &(usize, usize, usize), size: &(usize, usize, usize)| {
-            let &(x, y, z) = pos;
-            let &(w, l, h) = size;
+    if min_bounds.0 > max_bounds.0 {
+        return None;
+    }
 
-            for i in 0..w {
-                for j in 0..l {
-                    for k in 0..h {
-                        let p = (x + i, y + j, z + k);
+    Some((min_bounds, max_bounds))
+}
 
-                        g[get_index((p.0, p.1, p.2))] = None;
-                    }
-                }
-            }
-        };
+/// Rotates a position by MagicaVoxel's packed rotation byte.
+///
+/// The byte holds the column of the non-zero entry of the first two rows in bits 0-1 and 2-3,
+/// and the signs of the three rows in bits 4, 5 and 6. The third row's entry is in the remaining column.
+fn row_major_rotation(pos: (i32, i32, i32), rotation_byte: u8) -> (i32, i32, i32) {
+    magica::rotate(&magica::decode_rotation(rotation_byte), pos)
+}
 
-        let can_box = |g: &Vec<Option<u8>>, value: u8, pos: &(usize, usize, usize), size: &(usize, usize, usize)| -> bool {
-            let &(w, l, h) = size;
+/// Center of a brick along one axis, from its cell and half size, or `None` if it doesn't fit in an `i32`.
+fn brick_center(cell: i32, half_size: u32) -> Option<i32> {
+    let half_size = i32::try_from(half_size).ok()?;
+    cell.checked_mul(half_size.checked_mul(2)?)?.checked_add(half_size)
+}
 
-            if pos.0 + w > grid_size.0 {
-                return false;
-            }
-            if pos.1 + l > grid_size.1 {
-                return false;
-            }
-            if pos.2 + h > grid_size.2 {
-                return false;
-            }
+/// Position of a voxel's brick, from the voxel's position in its model and the model's position.
+fn voxel_brick_position(vox_pos: (i32, i32, i32), model_pos: (i32, i32, i32), voxel_size: (u32, u32, u32)) -> Option<(i32, i32, i32)> {
+    let x = vox_pos.0.checked_add(model_pos.0)?;
+    // Y is flipped in Brickadia.
+    let y = vox_pos.1.checked_add(model_pos.1)?.checked_neg()?;
+    let z = vox_pos.2.checked_add(model_pos.2)?;
 
-            for i in 0..w {
-                for j in 0..l {
-                    for k in 0..h {
-                        let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
-                        if g[get_index((pos.0, pos.1, pos.2))] != Some(value) {
-                            return false;
-                        }
-                    }
-                }
-            }
+    Some((
+        brick_center(x, voxel_size.0)?,
+        brick_center(y, voxel_size.1)?,
+        brick_center(z, voxel_size.2)?,
+    ))
+}
 
-            return true;
-        };
+/// Direction, rotation and size of a voxel's brick when the voxel is turned by `turn`.
+///
+/// `turn` is in the source's axes, so it's moved into Brickadia's, where Z is up and Y is
+/// flipped. The size turns along with the brick so the voxel keeps its footprint. Turns that
+/// mirror the voxel have no brick orientation and give `None`.
+fn voxel_orientation(turn: &magica::Rotation, up_axis: Axis, voxel_size: (u32, u32, u32)) -> Option<(Direction, Rotation, Size)> {
+    let flip_y = |(x, y, z): (i32, i32, i32)| (x, -y, z);
+    let to_world = |v| flip_y(up_axis.to_z_up(v));
+    // Cycling the axes three times gets back to the start.
+    let to_source = |v| up_axis.to_z_up(up_axis.to_z_up(flip_y(v)));
 
-        println!("\nFilling Gaps...");
+    let mut m = [[0; 3]; 3];
 
-        for x in 0..grid_size.0 {
-            for y in 0..grid_size.1 {
-                for z in 0..grid_size.2 {
-                    let mut brick = Brick::default();
+    for (axis, unit) in [(1, 0, 0), (0, 1, 0), (0, 0, 1)].iter().enumerate() {
+        let (x, y, z) = to_world(magica::rotate(turn, to_source(*unit)));
+        m[0][axis] = x;
+        m[1][axis] = y;
+        m[2][axis] = z;
+    }
 
-                    if let Some(val) = grid[get_index((x, y, z))] {
-                        let mut w = 1;
-                        let mut l = 1;
-                        let mut h = 1;
+    let (direction, rotation) = ramps::orientation(&m)?;
 
-                        while can_box(&grid, val, &(x, y, z), &(w, l, h)) && h <= 64 {
-                            h += 1;
-                        }
+    // The brick's own axis lined up with each world axis takes that axis's size.
+    let world = [voxel_size.0, voxel_size.1, voxel_size.2];
+    let mut local = [0; 3];
 
-                        h -= 1;
+    for (axis, row) in m.iter().enumerate() {
+        local[row.iter().position(|&v| v != 0)?] = world[axis];
+    }
 
-                        if h > 0 {
-                            while can_box(&grid, val, &(x, y, z), &(w, l, h)) && w <= 64 {
-                                w += 1;
-                            }
+    Some((direction, rotation, Size::Procedural(local[0], local[1], local[2])))
+}
+
+/// Whether layer `z` is in `z_range`, which includes every layer when it's `None`.
+fn in_z_range(z_range: Option<(u32, u32)>, z: u32) -> bool {
+    z_range.map_or(true, |(start, end)| (start..end).contains(&z))
+}
+
+/// Whether `voxel` is within `model`'s size.
+fn voxel_in_model(model: &SourceModel, voxel: &source::SourceVoxel) -> bool {
+    let (x, y, z) = voxel.position;
+    x < model.size.0 && y < model.size.1 && z < model.size.2
+}
+
+/// Shrinks a model by `factor` along every axis, each block of `factor` voxels a side becoming
+/// one voxel of the color most of them have, with ties going to the lower index.
+fn downsample_model(model: &SourceModel, factor: u32) -> SourceModel {
+    let mut blocks: BTreeMap<(u32, u32, u32), BTreeMap<u32, usize>> = BTreeMap::new();
 
-                            w -= 1;
+    for voxel in model.voxels.iter().filter(|voxel| voxel_in_model(model, voxel)) {
+        let (x, y, z) = voxel.position;
+        let block = blocks.entry((x / factor, y / factor, z / factor)).or_default();
+        *block.entry(voxel.color_index).or_default() += 1;
+    }
 
-                            if w > 0 {
-                                while can_box(&grid, val, &(x, y, z), &(w, l, h)) && l <= 64 {
-                                    l += 1;
-                                }
+    let voxels = blocks
+        .into_iter()
+        .map(|(position, colors)| source::SourceVoxel {
+            position,
+            color_index: colors.into_iter().rev().max_by_key(|&(_, count)| count).map_or(0, |(color, _)| color),
+        })
+        .collect();
 
-                                l -= 1;
+    let shrink = |size: u32| (size + factor - 1) / factor;
 
-                                if l > 0 {
-                                    box_remove(&mut grid, &(x, y, z), &(w, l, h));
+    SourceModel {
+        size: (shrink(model.size.0), shrink(model.size.1), shrink(model.size.2)),
+        voxels,
+        position: downsample_position(model.position, factor),
+        rotation: model.rotation,
+    }
+}
 
-                                    let size = (w as u32 * brick_size.0 as u32, l as u32 * brick_size.0 as u32, h as u32 * brick_size.1 as u32);
-                                    {
-                                        let (x, y, z) = (x as i32 * brick_size.0 * 2, y as i32 * brick_size.0 * 2, z as i32 * brick_size.1 * 2);
+/// Where a model placed at `position` ends up once downsampled by `factor`.
+fn downsample_position(position: Option<(i32, i32, i32)>, factor: u32) -> Option<(i32, i32, i32)> {
+    let factor = factor as i32;
+    position.map(|(x, y, z)| (x.div_euclid(factor), y.div_euclid(factor), z.div_euclid(factor)))
+}
 
-                                        brick.position = (
-                                            x + size.0 as i32,
-                                            y + size.1 as i32,
-                                            z + size.2 as i32
-                                        );
+/// Color most of a box's cells had before similar colors were grouped, with ties going to the
+/// lower index. Keeps the box's color if none of its cells are known.
+fn dominant_color(originals: &HashMap<(u32, GridPos), u16>, owner: u32, filled: &simplify::FilledBox) -> u16 {
+    let mut counts: BTreeMap<u16, usize> = BTreeMap::new();
 
-                                        brick.size = Size::Procedural(size.0, size.1, size.2);
-                                    }
+    for i in 0..filled.size.0 {
+        for j in 0..filled.size.1 {
+            for k in 0..filled.size.2 {
+                let cell = (filled.pos.0 + i, filled.pos.1 + j, filled.pos.2 + k);
 
-                                    brick.color = BrickColor::Index(val as u32);
-                                    brick.asset_name_index = brick_asset;
-                                    brs_save.bricks.push(brick);
-                                }
-                            }
-                        }
-                    }
+                if let Some(&value) = originals.get(&(owner, cell)) {
+                    *counts.entry(value).or_default() += 1;
                 }
             }
         }
+    }
+
+    counts.into_iter().rev().max_by_key(|&(_, count)| count).map_or(filled.value, |(value, _)| value)
+}
+
+/// Rotates bricks a number of quarter turns around the Z axis, through the origin.
+fn rotate_bricks(bricks: &mut [Brick], quarter_turns: u8) {
+    let turns = quarter_turns % 4;
+
+    if turns == 0 {
+        return;
+    }
+
+    let to_index = |rotation: &Rotation| match rotation {
+        Rotation::Deg0 => 0,
+        Rotation::Deg90 => 1,
+        Rotation::Deg180 => 2,
+        Rotation::Deg270 => 3,
+    };
+
+    let from_index = |index: u8| match index % 4 {
+        0 => Rotation::Deg0,
+        1 => Rotation::Deg90,
+        2 => Rotation::Deg180,
+        _ => Rotation::Deg270,
+    };
+
+    for brick in bricks.iter_mut() {
+        for _ in 0..turns {
+            let (x, y, z) = brick.position;
+            brick.position = (-y, x, z);
+        }
+
+        // Bricks facing down are rotated around -Z, so they turn the other way.
+        let turns = match brick.direction {
+            Direction::ZNegative => 4 - turns,
+            _ => turns,
+        };
+
+        brick.rotation = from_index(to_index(&brick.rotation) + turns);
+    }
+}
+
+/// Removes voxel bricks whose six neighbors are all voxels too, returning how many were removed.
+///
+/// Every brick is expected to be a single voxel of `voxel_size`, on the same grid. Their cells
+/// are cleared with `SparseGrid::remove_interior`, like the simplifier's hollowing.
+fn remove_interior_bricks(bricks: &mut Vec<Brick>, voxel_size: (u32, u32, u32)) -> usize {
+    // Bricks are centered on their cell, at twice the cell position.
+    let cell = |position: i32, half_size: u32| (position as i64 - half_size as i64).div_euclid(half_size.max(1) as i64 * 2);
+    let cells: Vec<(i64, i64, i64)> = bricks
+        .iter()
+        .map(|brick| (cell(brick.position.0, voxel_size.0), cell(brick.position.1, voxel_size.1), cell(brick.position.2, voxel_size.2)))
+        .collect();
+
+    let min = match cells.iter().copied().reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2))) {
+        Some(min) => min,
+        None => return 0,
+    };
+    let max = cells.iter().copied().fold(min, |a, b| (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)));
+
+    let grid_pos = |(x, y, z): (i64, i64, i64)| ((x - min.0) as usize, (y - min.1) as usize, (z - min.2) as usize);
+
+    let mut grid = SparseGrid::new(grid_pos((max.0 + 1, max.1 + 1, max.2 + 1)));
+    for &cell in &cells {
+        grid.set(grid_pos(cell), Some(0));
+    }
+
+    grid.remove_interior();
+
+    let before = bricks.len();
+    let mut kept = cells.into_iter().map(|cell| grid.get(grid_pos(cell)).is_some());
+    bricks.retain(|_| kept.next().unwrap_or(true));
+
+    before - bricks.len()
+}
+
+/// Gives bricks using `asset` the `studded` asset when any of their top face is uncovered,
+/// and the `smooth` asset when other bricks cover all of it.
+fn stud_top_faces(bricks: &mut [Brick], asset: u32, studded: u32, smooth: u32) {
+    // Bottom faces of every brick as min and max corners, by the height they're at.
+    let mut bottoms: HashMap<i32, Vec<(i64, i64, i64, i64)>> = HashMap::new();
+
+    for brick in bricks.iter() {
+        if let Some((w, l, h)) = ramps::world_size(brick) {
+            let (x, y, z) = brick.position;
+            let (x, y, w, l) = (x as i64, y as i64, w as i64, l as i64);
+
+            bottoms.entry(z - h as i32).or_default().push((x - w, y - l, x + w, y + l));
+        }
+    }
+
+    for brick in bricks.iter_mut().filter(|brick| brick.asset_name_index == asset) {
+        if let Some((w, l, h)) = ramps::world_size(brick) {
+            let (x, y, z) = brick.position;
+            let (x, y, w, l) = (x as i64, y as i64, w as i64, l as i64);
 
-        for mut brick in &mut brs_save.bricks {
-            brick.position.0 += min_bounds.0 * brick_size.0 * 2;
-            brick.position.1 += min_bounds.1 * brick_size.0 * 2;
-            brick.position.2 += min_bounds.2 * brick_size.1 * 2;
+            // Bricks don't overlap, so the areas they cover of the top face add up.
+            let overlap = |&(min_x, min_y, max_x, max_y): &(i64, i64, i64, i64)| {
+                let width = (x + w).min(max_x) - (x - w).max(min_x);
+                let length = (y + l).min(max_y) - (y - l).max(min_y);
+                width.max(0) * length.max(0)
+            };
+
+            let covered: i64 = bottoms
+                .get(&(z + h as i32))
+                .map_or(0, |faces| faces.iter().map(overlap).sum());
+
+            brick.asset_name_index = if covered < 4 * w * l { studded } else { smooth };
         }
+    }
+}
+
+/// Collapses voxel bricks into a single layer across `axis`, keeping the brick furthest
+/// along the axis in each column, like the top view for Z. The layer takes the place of the
+/// bricks nearest the start of the axis.
+///
+/// Every brick is expected to be a single voxel, on the same grid.
+fn flatten_bricks(bricks: &mut Vec<Brick>, axis: Axis) {
+    // A brick's position along the axis, and the column it's in.
+    let split = |(x, y, z): (i32, i32, i32)| match axis {
+        Axis::X => (x, (y, z)),
+        Axis::Y => (y, (x, z)),
+        Axis::Z => (z, (x, y)),
+    };
+
+    let mut fronts: HashMap<(i32, i32), i32> = HashMap::new();
+    let mut layer = i32::MAX;
 
-        println!(" - Gaps filled.");
+    for brick in bricks.iter() {
+        let (along, column) = split(brick.position);
+        let front = fronts.entry(column).or_insert(along);
+        *front = (*front).max(along);
+        layer = layer.min(along);
     }
 
-    println!("\nFinished vox2brs in {}s.", now.elapsed().as_millis() as f64 / 1000.0);
-    println!(" - Created {} bricks.", brs_save.bricks.len());
+    bricks.retain(|brick| {
+        let (along, column) = split(brick.position);
+        fronts.get(&column) == Some(&along)
+    });
 
-    Ok(brs_save)
+    for brick in bricks.iter_mut() {
+        match axis {
+            Axis::X => brick.position.0 = layer,
+            Axis::Y => brick.position.1 = layer,
+            Axis::Z => brick.position.2 = layer,
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
+/// Moves bricks so the build is centered on the origin, with its base at Z 0.
+///
+/// Offsets are rounded to multiples of `step` so bricks stay on their grid.
+fn center_bricks(bricks: &mut [Brick], step: (i32, i32, i32)) {
+    let (min_bounds, max_bounds) = match brick_bounds(bricks) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+
+    let round = |v: i32, step: i32| (v as f64 / step as f64).round() as i32 * step;
+
+    let offset = (
+        -round((min_bounds.0 + max_bounds.0) / 2, step.0),
+        -round((min_bounds.1 + max_bounds.1) / 2, step.1),
+        -round(min_bounds.2, step.2),
+    );
+
+    for brick in bricks.iter_mut() {
+        brick.position.0 += offset.0;
+        brick.position.1 += offset.1;
+        brick.position.2 += offset.2;
+    }
+}
+
+/// Width of a stud, in Brickadia units.
+const STUD: i32 = 10;
+
+/// Height of a plate, in Brickadia units.
+const PLATE_HEIGHT: i32 = 4;
+
+/// Moves bricks the least distance down and back along each axis so the build's minimum corner
+/// lands on a stud boundary along X and Y and on a plate boundary along Z.
+fn align_bricks(bricks: &mut [Brick]) {
+    let (min_bounds, _) = match brick_bounds(bricks) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+
+    let offset = (
+        -min_bounds.0.rem_euclid(STUD),
+        -min_bounds.1.rem_euclid(STUD),
+        -min_bounds.2.rem_euclid(PLATE_HEIGHT),
+    );
+
+    for brick in bricks.iter_mut() {
+        brick.position.0 += offset.0;
+        brick.position.1 += offset.1;
+        brick.position.2 += offset.2;
+    }
+}
+
+/// Makes a plate under `bricks`, `size` output bricks wide and long, and at least
+/// one brick wider than their footprint on every side.
+///
+/// `brick_footprint` is the half size of an output brick along X and Y.
+fn baseplate_brick(bricks: &[Brick], size: (u32, u32), brick_footprint: (u32, u32), asset: u32) -> Option<Brick> {
+    let (min_bounds, max_bounds) = brick_bounds(bricks)?;
+
+    // Half sizes, as Brickadia sizes bricks from their center.
+    let footprint = ((max_bounds.0 - min_bounds.0) as u32 / 2, (max_bounds.1 - min_bounds.1) as u32 / 2);
+    let w = (size.0 * brick_footprint.0).max(footprint.0 + brick_footprint.0);
+    let l = (size.1 * brick_footprint.1).max(footprint.1 + brick_footprint.1);
+    let h = 2;
+
+    let mut brick = Brick::default();
+    brick.asset_name_index = asset;
+    brick.size = Size::Procedural(w, l, h);
+    brick.position = (
+        (min_bounds.0 + max_bounds.0) / 2,
+        (min_bounds.1 + max_bounds.1) / 2,
+        min_bounds.2 - h as i32,
+    );
+
+    Some(brick)
+}
+
+/// Settings for a conversion.
+///
+/// Fields can be set directly, or through the builder methods:
+///
+/// ```
+/// use vox2brs::{BrickOutputMode, Vox2BrsOptions};
+///
+/// let options = Vox2BrsOptions::default()
+///     .mode(BrickOutputMode::Plate)
+///     .simplify(true);
+/// ```
+#[derive(Clone)]
+pub struct Vox2BrsOptions {
+    pub mode: BrickOutputMode,
+
+    /// Width of the output brick, in studs for bricks, plates and tiles, or in microbricks.
+    ///
+    /// Width and height are independent, so in micro mode a voxel can be 1 microbrick wide
+    /// and 2 tall to stretch models made with non-cubic proportions in mind.
+    pub width: Option<u32>,
+
+    /// Depth of the output brick along Y, in the same units as the width.
+    /// Defaults to the width, for a square footprint.
+    pub depth: Option<u32>,
+
+    /// Height of the output brick, in bricks, plates or microbricks depending on the mode.
+    pub height: Option<u32>,
+
+    /// Put studs only on bricks with an uncovered top face: those bricks use `brick_asset`,
+    /// which has studs, and the rest use the smooth `tile_asset`. Bricks, plates and tiles
+    /// only, as microbricks have no studs. Ramps and wedges are left alone.
+    pub studs: bool,
+
+    /// Axis pointing up in the source. MagicaVoxel is Z-up.
+    pub up_axis: Axis,
+
+    /// Flip each model along its own X, Y or Z axis.
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+    pub mirror_z: bool,
+
+    /// Each voxel becomes a `voxel_scale`×`voxel_scale`×`voxel_scale` block of bricks.
+    pub voxel_scale: u32,
+
+    /// Only convert voxels with at least one uncovered side, leaving out the model's inside.
+    pub surface_only: bool,
+
+    /// Project the build onto a single layer across this axis of the output, with Z up,
+    /// keeping the voxel seen from the axis' positive end in each column. For pixel art
+    /// plates of a model's top or side. Runs before simplifying, so the layer still merges.
+    pub flatten: Option<Axis>,
+
+    pub simplify: bool,
+    pub rampify: bool,
+    pub rampify_options: Option<RampifyOptions>,
+
+    /// Put wedges on steps one cell tall, keeping everything else as bricks. A lighter
+    /// alternative to `rampify`, which it does nothing alongside. Implies simplifying.
+    pub smooth_edges: bool,
+
+    /// Refill each color's bricks with whichever order of growing them makes the fewest, once the
+    /// grid is filled. Bricks filled around ramps or in separate chunks can often be covered by fewer.
+    /// Only used when simplifying.
+    pub weld: bool,
+
+    pub fill_strategy: FillStrategy,
+
+    /// Try every order of growing bricks on a sample of each model, and fill the model in the
+    /// order making the fewest bricks, in place of `fill_strategy`. Each model is sampled
+    /// apart with `preserve_model_grouping`, or else the whole build is one, and each chunk
+    /// with `chunk_size`. The chosen orders are reported as `ProgressEvent::GrowOrderChosen`.
+    pub optimize_axis_order: bool,
+
+    /// Largest brick the simplifier makes, in voxels along each axis. Values below 1 are treated as 1.
+    pub max_box_size: GridPos,
+
+    /// Bricks the simplifier makes from fewer than this many voxels are left out, trading
+    /// detail for fewer bricks. 1 keeps every brick. Only used when simplifying.
+    pub min_brick_volume: usize,
+
+    /// Before leaving out bricks below `min_brick_volume`, recolor them to the color touching
+    /// them most and simplify again, so they merge into their neighbors instead.
+    pub merge_small_bricks: bool,
+
+    /// Give each placed model's bricks an owner of their own, so they stay separate groups in
+    /// the save. Models are simplified apart, so bricks never span two of them.
+    pub preserve_model_grouping: bool,
+
+    /// Simplify as if every voxel had the same color, letting bricks span color boundaries.
+    /// Every brick, ramp and wedge gets `merged_color`. Meant for builds painted in-game.
+    pub merge_colors: bool,
+
+    /// Index into the save's palette of the color bricks get with `merge_colors`, or `None`
+    /// for the color most voxels have.
+    pub merged_color: Option<u32>,
+
+    /// Simplify colors within this RGB distance of each other as one, so gradients of nearly
+    /// the same color merge into fewer bricks. Each brick takes the color most of its voxels
+    /// had, with ties going to the lower palette index. Colors are grouped around the most
+    /// used ones, so no voxel changes by more than the tolerance. Only used when simplifying.
+    pub color_merge_tolerance: Option<f32>,
+
+    /// When set, the simplifier works on cubes of this many cells at a time instead of the
+    /// model's whole bounding box, so large sparse models don't need one huge grid. Boxes cut
+    /// off at chunk edges are joined back up where they line up, but ramps, wedges and hollowing
+    /// don't see past the edges.
+    pub chunk_size: Option<usize>,
+
+    /// Removes or fills voxels that can't be seen, before the simplifier merges them. Only used when simplifying.
+    pub hollow: HollowMode,
+
+    /// When set, each color's opacity is carried into the save's colors and bricks whose
+    /// alpha is below the threshold use the glass material. As with `palette_alpha_threshold`,
+    /// opacity is the palette alpha scaled by the material's, which is where .vox files keep it.
+    pub alpha_threshold: Option<u8>,
+
+    /// When set, voxels whose color is less opaque than this are left out, as colors in
+    /// `skip_color_indices` are. A color's opacity is its palette alpha scaled by its material's.
+    pub palette_alpha_threshold: Option<u8>,
+
+    pub gamma_correct: bool,
+
+    /// Colors left as they are by `gamma_correct`, by index into the source's palette, for
+    /// colors that have to match an in-game color exactly.
+    pub no_gamma_for_indices: Vec<u32>,
+
+    /// When set, colors with an emissive MagicaVoxel material use the glow material,
+    /// with the emission strength scaled by this value.
+    pub emissive_intensity: Option<f32>,
+
+    /// When set, the save's palette is this palette instead of the source's, and each
+    /// voxel takes the color in it that looks closest to its own. With `gamma_correct`, the
+    /// palette is corrected too, so it's matched against colors in the same space.
+    pub reference_palette: Option<Vec<Color>>,
+
+    /// When set, the source's palette is reduced to at most this many colors with median cut,
+    /// and each voxel takes the reduced color closest to its own. Only colors voxels use are
+    /// counted. Ignored when `reference_palette` is set.
+    pub quantize: Option<usize>,
+
+    /// When the palette is quantized or a reference palette is set, pick voxels' colors with
+    /// Floyd–Steinberg dithering across each Z layer instead of the closest color, so
+    /// gradients don't turn into flat bands.
+    pub dither: bool,
+
+    /// Give every brick its own color instead of an index into the save's palette,
+    /// leaving the save's palette empty.
+    pub use_unique_colors: bool,
+
+    /// Voxels whose color is in this list (indices into the source's palette) are left out.
+    pub skip_color_indices: Vec<u32>,
+
+    /// Only voxels from the first Z layer up to, but not including, the second are converted.
+    /// Layers are counted along each model's own Z, and the voxels keep their places, so saves
+    /// of neighboring bands line up.
+    pub z_range: Option<(u32, u32)>,
+
+    /// Material of bricks of each color, by index into the save's palette.
+    /// Overrides the glass and glow materials picked from alpha and emission.
+    /// Ordered, so the save's material list comes out the same on every run.
+    pub color_materials: BTreeMap<u32, BrickMaterial>,
+
+    /// Pick brick materials from the source's materials: metal becomes metallic,
+    /// glass becomes glass and emit becomes glow. `color_materials` takes priority.
+    pub auto_materials: bool,
+
+    /// Collision of every brick in the save. Decorative builds are often cheaper without any.
+    pub collision: BrickCollision,
+
+    /// Moves every model by this many voxels along the source's axes, on top of its own
+    /// position. Centering the build undoes it.
+    pub global_offset: (i32, i32, i32),
+
+    /// Move the build so it's centered on the origin with its base at Z 0.
+    pub center: bool,
+
+    /// Move the finished build, baseplate included, so its minimum corner sits on a stud
+    /// boundary along X and Y and a plate boundary along Z, where Brickadia's build grid snaps.
+    /// The build moves less than a stud, and after centering, so a centered build may end up
+    /// just off the origin. Bricks keep their own sizes, so only the corner is on the grid.
+    pub grid_align: bool,
+
+    /// Width and length, in output bricks, of a plate placed under the build.
+    /// The plate always extends at least one brick past the build's footprint. It uses
+    /// `tile_asset` in tile mode and `brick_asset` otherwise, so microbrick builds get a plate.
+    pub baseplate: Option<(u32, u32)>,
+
+    /// Index into the save's palette of the baseplate's color.
+    pub baseplate_color_index: u32,
+
+    /// Quarter turns to rotate the whole build around the Z axis, once it's converted.
+    pub world_rotation: u8,
+
+    /// Most bricks the save may contain. Checked after the voxels are read when not
+    /// simplifying, and again once the conversion is done.
+    pub max_bricks: Option<usize>,
+
+    /// Most voxels a model may be across along any axis. Larger models fail the conversion
+    /// with `Vox2BrsError::ModelTooLarge`, or are shrunk with `downsample`.
+    pub max_dimension: Option<u32>,
+
+    /// Shrink every model by the same whole factor when one is larger than `max_dimension`,
+    /// so the largest fits. Each block of voxels becomes one voxel of the color most of them
+    /// have, and blocks with any voxel in them are kept so thin parts don't vanish.
+    /// Voxels turned by `VoxelSource::voxel_rotations` face up once downsampled, and when
+    /// simplifying.
+    pub downsample: bool,
+
+    /// Save version to write. The conversion fails if the version can't store its result.
+    /// Defaults to the save's own version.
+    pub save_version: Option<u16>,
+
+    /// Stops the conversion with `Vox2BrsError::Cancelled` once set. Checked between models
+    /// and while filling the grid with bricks.
+    pub cancel: Option<Arc<AtomicBool>>,
+
+    /// Directory to write the simplifier's grid to before it's merged into bricks, as a PNG
+    /// per Z layer. Only written when simplifying, rampifying or smoothing, which are what
+    /// build the grid. Failing to write them is a warning, not an error.
+    #[cfg(feature = "debug-slices")]
+    pub debug_slices: Option<std::path::PathBuf>,
+
+    /// Author of saves made by `convert_file`, who also owns their bricks.
+    pub author: User,
+
+    /// More owners to share the bricks with. The save's first owner's bricks are split into
+    /// even runs between it and these, in order. Ignored with `preserve_model_grouping`,
+    /// which gives each model an owner of its own.
+    pub extra_owners: Vec<User>,
+
+    /// Description of saves made by `convert_file`, or `None` to describe the input file.
+    pub description: Option<String>,
+
+    /// When set, replaces the save's brick asset list.
+    pub brick_assets: Option<Vec<String>>,
+
+    /// Assets of each kind of brick, by index or name in the save's brick asset list.
+    /// Ramp and wedge assets only need to exist when they're used.
+    pub brick_asset: BrickAsset,
+    pub microbrick_asset: BrickAsset,
+    pub tile_asset: BrickAsset,
+    pub ramp_asset: BrickAsset,
+    pub wedge_asset: BrickAsset,
+}
+
+impl Default for Vox2BrsOptions {
+    /// Asset indices default to the order
+    /// `PB_DefaultBrick`, `PB_DefaultMicroBrick`, `PB_DefaultTile`, `PB_DefaultRamp`, `PB_DefaultWedge`.
+    fn default() -> Self {
+        Self {
+            mode: BrickOutputMode::Brick,
+            width: None,
+            depth: None,
+            height: None,
+            studs: false,
+            up_axis: Axis::Z,
+            mirror_x: false,
+            mirror_y: false,
+            mirror_z: false,
+            voxel_scale: 1,
+            surface_only: false,
+            flatten: None,
+            simplify: false,
+            rampify: false,
+            rampify_options: None,
+            smooth_edges: false,
+            weld: false,
+            fill_strategy: FillStrategy::HeightFirst,
+            optimize_axis_order: false,
+            max_box_size: simplify::DEFAULT_MAX_BOX_SIZE,
+            min_brick_volume: 1,
+            merge_small_bricks: false,
+            preserve_model_grouping: false,
+            merge_colors: false,
+            merged_color: None,
+            color_merge_tolerance: None,
+            chunk_size: None,
+            hollow: HollowMode::None,
+            alpha_threshold: None,
+            palette_alpha_threshold: None,
+            gamma_correct: false,
+            no_gamma_for_indices: vec![],
+            emissive_intensity: None,
+            reference_palette: None,
+            quantize: None,
+            dither: false,
+            use_unique_colors: false,
+            skip_color_indices: vec![],
+            z_range: None,
+            color_materials: BTreeMap::new(),
+            auto_materials: false,
+            collision: BrickCollision::All,
+            global_offset: (0, 0, 0),
+            center: false,
+            grid_align: false,
+            baseplate: None,
+            baseplate_color_index: 0,
+            world_rotation: 0,
+            max_bricks: None,
+            max_dimension: None,
+            downsample: false,
+            save_version: None,
+            cancel: None,
+            #[cfg(feature = "debug-slices")]
+            debug_slices: None,
+            author: User {
+                name: "vox2brs".into(),
+                id: Uuid::from_u128(0xa8033bee_6c37_4118_b4a6_cecc1d966133),
+            },
+            extra_owners: vec![],
+            description: None,
+            brick_assets: None,
+            brick_asset: BrickAsset::Index(0),
+            microbrick_asset: BrickAsset::Index(1),
+            tile_asset: BrickAsset::Index(2),
+            ramp_asset: BrickAsset::Index(3),
+            wedge_asset: BrickAsset::Index(4),
+        }
+    }
+}
+
+impl Vox2BrsOptions {
+    pub fn mode(mut self, mode: BrickOutputMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn width(mut self, width: impl Into<Option<u32>>) -> Self {
+        self.width = width.into();
+        self
+    }
+
+    pub fn depth(mut self, depth: impl Into<Option<u32>>) -> Self {
+        self.depth = depth.into();
+        self
+    }
+
+    pub fn height(mut self, height: impl Into<Option<u32>>) -> Self {
+        self.height = height.into();
+        self
+    }
+
+    pub fn up_axis(mut self, up_axis: Axis) -> Self {
+        self.up_axis = up_axis;
+        self
+    }
+
+    /// Flips each model along the axes that are set.
+    pub fn mirror(mut self, x: bool, y: bool, z: bool) -> Self {
+        self.mirror_x = x;
+        self.mirror_y = y;
+        self.mirror_z = z;
+        self
+    }
+
+    pub fn voxel_scale(mut self, voxel_scale: u32) -> Self {
+        self.voxel_scale = voxel_scale;
+        self
+    }
+
+    pub fn studs(mut self, studs: bool) -> Self {
+        self.studs = studs;
+        self
+    }
+
+    pub fn surface_only(mut self, surface_only: bool) -> Self {
+        self.surface_only = surface_only;
+        self
+    }
+
+    pub fn flatten(mut self, flatten: impl Into<Option<Axis>>) -> Self {
+        self.flatten = flatten.into();
+        self
+    }
+
+    pub fn simplify(mut self, simplify: bool) -> Self {
+        self.simplify = simplify;
+        self
+    }
+
+    pub fn rampify(mut self, rampify: bool) -> Self {
+        self.rampify = rampify;
+        self
+    }
+
+    pub fn rampify_options(mut self, rampify_options: RampifyOptions) -> Self {
+        self.rampify_options = Some(rampify_options);
+        self
+    }
+
+    pub fn smooth_edges(mut self, smooth_edges: bool) -> Self {
+        self.smooth_edges = smooth_edges;
+        self
+    }
+
+    pub fn weld(mut self, weld: bool) -> Self {
+        self.weld = weld;
+        self
+    }
+
+    pub fn fill_strategy(mut self, fill_strategy: FillStrategy) -> Self {
+        self.fill_strategy = fill_strategy;
+        self
+    }
+
+    pub fn optimize_axis_order(mut self, optimize_axis_order: bool) -> Self {
+        self.optimize_axis_order = optimize_axis_order;
+        self
+    }
+
+    pub fn max_box_size(mut self, max_box_size: GridPos) -> Self {
+        self.max_box_size = max_box_size;
+        self
+    }
+
+    pub fn min_brick_volume(mut self, min_brick_volume: usize) -> Self {
+        self.min_brick_volume = min_brick_volume;
+        self
+    }
+
+    pub fn merge_small_bricks(mut self, merge_small_bricks: bool) -> Self {
+        self.merge_small_bricks = merge_small_bricks;
+        self
+    }
+
+    pub fn preserve_model_grouping(mut self, preserve_model_grouping: bool) -> Self {
+        self.preserve_model_grouping = preserve_model_grouping;
+        self
+    }
+
+    pub fn merge_colors(mut self, merge_colors: bool) -> Self {
+        self.merge_colors = merge_colors;
+        self
+    }
+
+    pub fn merged_color(mut self, merged_color: impl Into<Option<u32>>) -> Self {
+        self.merged_color = merged_color.into();
+        self
+    }
+
+    pub fn color_merge_tolerance(mut self, tolerance: impl Into<Option<f32>>) -> Self {
+        self.color_merge_tolerance = tolerance.into();
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: impl Into<Option<usize>>) -> Self {
+        self.chunk_size = chunk_size.into();
+        self
+    }
+
+    pub fn hollow(mut self, hollow: HollowMode) -> Self {
+        self.hollow = hollow;
+        self
+    }
+
+    pub fn alpha_threshold(mut self, alpha_threshold: impl Into<Option<u8>>) -> Self {
+        self.alpha_threshold = alpha_threshold.into();
+        self
+    }
+
+    pub fn palette_alpha_threshold(mut self, palette_alpha_threshold: impl Into<Option<u8>>) -> Self {
+        self.palette_alpha_threshold = palette_alpha_threshold.into();
+        self
+    }
+
+    pub fn gamma_correct(mut self, gamma_correct: bool) -> Self {
+        self.gamma_correct = gamma_correct;
+        self
+    }
+
+    pub fn no_gamma_for_indices(mut self, indices: Vec<u32>) -> Self {
+        self.no_gamma_for_indices = indices;
+        self
+    }
+
+    pub fn emissive_intensity(mut self, emissive_intensity: impl Into<Option<f32>>) -> Self {
+        self.emissive_intensity = emissive_intensity.into();
+        self
+    }
+
+    pub fn reference_palette(mut self, reference_palette: impl Into<Option<Vec<Color>>>) -> Self {
+        self.reference_palette = reference_palette.into();
+        self
+    }
+
+    pub fn quantize(mut self, quantize: impl Into<Option<usize>>) -> Self {
+        self.quantize = quantize.into();
+        self
+    }
+
+    pub fn dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    pub fn use_unique_colors(mut self, use_unique_colors: bool) -> Self {
+        self.use_unique_colors = use_unique_colors;
+        self
+    }
+
+    pub fn skip_color_indices(mut self, skip_color_indices: Vec<u32>) -> Self {
+        self.skip_color_indices = skip_color_indices;
+        self
+    }
+
+    pub fn z_range(mut self, z_range: impl Into<Option<(u32, u32)>>) -> Self {
+        self.z_range = z_range.into();
+        self
+    }
+
+    /// Makes bricks of the color at `color_index` use `material`.
+    pub fn color_material(mut self, color_index: u32, material: BrickMaterial) -> Self {
+        self.color_materials.insert(color_index, material);
+        self
+    }
+
+    pub fn color_materials(mut self, color_materials: BTreeMap<u32, BrickMaterial>) -> Self {
+        self.color_materials = color_materials;
+        self
+    }
+
+    pub fn auto_materials(mut self, auto_materials: bool) -> Self {
+        self.auto_materials = auto_materials;
+        self
+    }
+
+    pub fn collision(mut self, collision: BrickCollision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn global_offset(mut self, global_offset: (i32, i32, i32)) -> Self {
+        self.global_offset = global_offset;
+        self
+    }
+
+    pub fn center(mut self, center: bool) -> Self {
+        self.center = center;
+        self
+    }
+
+    pub fn grid_align(mut self, grid_align: bool) -> Self {
+        self.grid_align = grid_align;
+        self
+    }
+
+    /// Places a plate of `width`×`length` bricks under the build, in the given color.
+    pub fn baseplate(mut self, baseplate: impl Into<Option<(u32, u32)>>, color_index: u32) -> Self {
+        self.baseplate = baseplate.into();
+        self.baseplate_color_index = color_index;
+        self
+    }
+
+    pub fn world_rotation(mut self, quarter_turns: u8) -> Self {
+        self.world_rotation = quarter_turns;
+        self
+    }
+
+    pub fn max_bricks(mut self, max_bricks: impl Into<Option<usize>>) -> Self {
+        self.max_bricks = max_bricks.into();
+        self
+    }
+
+    pub fn max_dimension(mut self, max_dimension: impl Into<Option<u32>>, downsample: bool) -> Self {
+        self.max_dimension = max_dimension.into();
+        self.downsample = downsample;
+        self
+    }
+
+    pub fn save_version(mut self, save_version: impl Into<Option<u16>>) -> Self {
+        self.save_version = save_version.into();
+        self
+    }
+
+    pub fn cancel(mut self, cancel: impl Into<Option<Arc<AtomicBool>>>) -> Self {
+        self.cancel = cancel.into();
+        self
+    }
+
+    #[cfg(feature = "debug-slices")]
+    pub fn debug_slices(mut self, directory: Option<std::path::PathBuf>) -> Self {
+        self.debug_slices = directory;
+        self
+    }
+
+    pub fn author(mut self, author: User) -> Self {
+        self.author = author;
+        self
+    }
+
+    pub fn extra_owners(mut self, extra_owners: Vec<User>) -> Self {
+        self.extra_owners = extra_owners;
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<Option<String>>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn brick_assets(mut self, brick_assets: impl Into<Option<Vec<String>>>) -> Self {
+        self.brick_assets = brick_assets.into();
+        self
+    }
+
+    /// Sets the brick, microbrick, tile, ramp and wedge asset indices, in that order.
+    pub fn asset_indices(self, brick: u32, microbrick: u32, tile: u32, ramp: u32, wedge: u32) -> Self {
+        self.assets(brick, microbrick, tile, ramp, wedge)
+    }
+
+    /// Sets the brick, microbrick, tile, ramp and wedge assets, in that order, by index or name.
+    pub fn assets(
+        mut self,
+        brick: impl Into<BrickAsset>,
+        microbrick: impl Into<BrickAsset>,
+        tile: impl Into<BrickAsset>,
+        ramp: impl Into<BrickAsset>,
+        wedge: impl Into<BrickAsset>,
+    ) -> Self {
+        self.brick_asset = brick.into();
+        self.microbrick_asset = microbrick.into();
+        self.tile_asset = tile.into();
+        self.ramp_asset = ramp.into();
+        self.wedge_asset = wedge.into();
+        self
+    }
+}
+
+/// Converts a voxel file into bricks, appending them to `brs_save`.
+///
+/// Returns the save along with statistics about the conversion. The same source and options
+/// always make the same save, down to the order of its colors and materials.
+pub fn vox2brs(
+    in_vox_data: impl VoxelSource,
+    brs_save: SaveData,
+    options: Vox2BrsOptions,
+) -> Result<(SaveData, ConversionStats), Vox2BrsError> {
+    vox2brs_with_progress(in_vox_data, brs_save, options, &mut |_| {})
+}
+
+/// Same as `vox2brs`, reading the voxel file from memory. See `source::read_source` for the formats it reads.
+pub fn vox2brs_from_bytes(
+    data: &[u8],
+    brs_save: SaveData,
+    options: Vox2BrsOptions,
+) -> Result<(SaveData, ConversionStats), Vox2BrsError> {
+    let source = source::read_source(data).map_err(|error| Vox2BrsError::InvalidFile(error.to_string()))?;
+    vox2brs(source, brs_save, options)
+}
+
+/// Owner index of the save's author, counting from 1 like bricks do. Falls back to the first
+/// owner when the author isn't one of them.
+fn author_owner_index(brs_save: &SaveData) -> u32 {
+    brs_save.header2.brick_owners
+        .iter()
+        .position(|owner| owner.id == brs_save.header1.author.id)
+        .map_or(1, |index| index as u32 + 1)
+}
+
+/// Adds `extra_owners` to the save, splitting the author's bricks into even runs between the
+/// author and them.
+fn share_bricks(brs_save: &mut SaveData, extra_owners: Vec<User>) {
+    let author = author_owner_index(brs_save);
+    let first_extra = brs_save.header2.brick_owners.len() as u32 + 1;
+    let owners = extra_owners.len() + 1;
+
+    brs_save.header2.brick_owners.extend(extra_owners.into_iter().map(|owner| BrickOwner::from_user_bricks(owner, 0)));
+
+    let owned = brs_save.bricks.iter().filter(|brick| brick.owner_index == author).count();
+
+    for (i, brick) in brs_save.bricks.iter_mut().filter(|brick| brick.owner_index == author).enumerate() {
+        let share = (i * owners / owned) as u32;
+
+        if share > 0 {
+            brick.owner_index = first_extra + share - 1;
+        }
+    }
+}
+
+/// Sets each of the save's owners' brick counts to the bricks they own.
+fn count_owner_bricks(brs_save: &mut SaveData) {
+    let mut counts = vec![0; brs_save.header2.brick_owners.len() + 1];
+
+    for brick in &brs_save.bricks {
+        if let Some(count) = counts.get_mut(brick.owner_index as usize) {
+            *count += 1;
+        }
+    }
+
+    for (owner, &count) in brs_save.header2.brick_owners.iter_mut().zip(&counts[1..]) {
+        owner.bricks = count;
+    }
+}
+
+/// Splits a converted save into one save per palette color, each with only that color's
+/// bricks, ordered by palette index.
+///
+/// The bricks aren't moved, so the saves line up when loaded together. Each keeps the whole
+/// palette, so indices stay the same. Bricks with their own color instead of a palette index
+/// go in one more save at the end, with no index.
+pub fn split_by_color(mut save: SaveData) -> Vec<(Option<u32>, SaveData)> {
+    let mut groups: BTreeMap<u32, Vec<Brick>> = BTreeMap::new();
+    let mut unique = vec![];
+
+    for brick in std::mem::take(&mut save.bricks) {
+        match brick.color {
+            BrickColor::Index(index) => groups.entry(index).or_default().push(brick),
+            BrickColor::Unique(_) => unique.push(brick),
+        }
+    }
+
+    let unique = Some((None, unique)).filter(|(_, bricks)| !bricks.is_empty());
+
+    groups
+        .into_iter()
+        .map(|(index, bricks)| (Some(index), bricks))
+        .chain(unique)
+        .map(|(index, bricks)| {
+            let mut part = save.clone();
+            part.bricks = bricks;
+            count_owner_bricks(&mut part);
+            (index, part)
+        })
+        .collect()
+}
+
+/// Makes an empty save by `author`, with the brick assets the default asset indices point to.
+pub fn new_save(author: User, description: String) -> SaveData {
+    let mut save = SaveData::default();
+
+    save.header1.author = author.clone();
+    save.header1.host = Some(author.clone());
+    save.header1.description = description;
+
+    save.header2
+        .brick_owners
+        .push(BrickOwner::from_user_bricks(author, 0));
+
+    save.header2.brick_assets = DEFAULT_BRICK_ASSETS.iter().map(|&asset| asset.into()).collect();
+
+    // In case this changes in the future... it should already be empty.
+    save.header2.colors.clear();
+
+    save
+}
+
+/// Writes `save` to a .brs file at `output`.
+pub fn write_save(save: SaveData, output: &Path) -> Result<(), Vox2BrsError> {
+    let file = File::create(output).map_err(|error| Vox2BrsError::WriteFailed(error.to_string()))?;
+
+    SaveWriter::new(file, save)
+        .write()
+        .map_err(|error| Vox2BrsError::WriteFailed(error.to_string()))
+}
+
+/// Converts `source` into a new save by the options' author. Without a description in the
+/// options, the save's describes `input`, the file the source was read from.
+pub fn convert_source(source: impl VoxelSource, input: &Path, options: &Vox2BrsOptions) -> Result<(SaveData, ConversionStats), Vox2BrsError> {
+    convert_source_with_progress(source, input, options, &mut |_| {})
+}
+
+/// Same as `convert_source`, reporting progress to `progress` as it goes.
+pub fn convert_source_with_progress(
+    source: impl VoxelSource,
+    input: &Path,
+    options: &Vox2BrsOptions,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<(SaveData, ConversionStats), Vox2BrsError> {
+    let description = options.description.clone()
+        .unwrap_or_else(|| source::default_description(input, &source));
+
+    let save = new_save(options.author.clone(), description);
+    vox2brs_with_progress(source, save, options.clone(), progress)
+}
+
+/// Converts the voxel file at `input` into a new save written to `output`, using the
+/// options' author and description.
+pub fn convert_file(input: &Path, output: &Path, options: &Vox2BrsOptions) -> Result<ConversionStats, Vox2BrsError> {
+    convert_file_with_progress(input, output, options, &mut |_| {})
+}
+
+/// Same as `convert_file`, reporting progress to `progress` as it goes.
+pub fn convert_file_with_progress(
+    input: &Path,
+    output: &Path,
+    options: &Vox2BrsOptions,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<ConversionStats, Vox2BrsError> {
+    let source = source::load_source(input).map_err(|error| Vox2BrsError::InvalidFile(error.to_string()))?;
+    let (save, stats) = convert_source_with_progress(source, input, options, progress)?;
+
+    write_save(save, output)?;
+
+    Ok(stats)
+}
+
+/// Index of `asset` in the save's brick asset list `assets`.
+fn resolve_asset(assets: &[String], asset: &BrickAsset) -> Result<u32, Vox2BrsError> {
+    match asset {
+        BrickAsset::Index(index) if (*index as usize) < assets.len() => Ok(*index),
+        BrickAsset::Index(index) => Err(Vox2BrsError::InvalidAssetIndex(*index)),
+        BrickAsset::Name(name) => assets
+            .iter()
+            .position(|asset| asset == name)
+            .map(|index| index as u32)
+            .ok_or_else(|| Vox2BrsError::UnknownAsset(name.clone())),
+    }
+}
+
+fn is_cancelled(cancel: &Option<Arc<AtomicBool>>) -> bool {
+    cancel.as_ref().map_or(false, |cancel| cancel.load(Ordering::Relaxed))
+}
+
+fn check_brick_count(options: &Vox2BrsOptions, produced: usize) -> Result<(), Vox2BrsError> {
+    match options.max_bricks {
+        Some(limit) if produced > limit => Err(Vox2BrsError::TooManyBricks { produced, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether bricks go through the simplifier's grid, which rampifying and smoothing work on too.
+fn uses_grid(options: &Vox2BrsOptions) -> bool {
+    options.simplify || options.rampify || options.smooth_edges
+}
+
+/// The save's colors as the later stages need them. Indices are into the save's palette,
+/// apart from `color_map`'s and `skipped`, which are into the source's.
+struct SaveColors {
+    /// Index into the save's palette of each source color.
+    color_map: Vec<u32>,
+
+    /// Palette index of each voxel of each model, when dithering replaces the closest colors.
+    dithered: Vec<Vec<Option<u32>>>,
+
+    /// Colors below the alpha threshold, which get the glass material.
+    translucent: Vec<bool>,
+
+    /// Glow intensity of emissive colors.
+    glow: Vec<Option<u32>>,
+
+    /// `color_materials`, along with the source's materials when `auto_materials` is set.
+    materials: BTreeMap<u32, BrickMaterial>,
+
+    /// `skip_color_indices`, along with the colors below `palette_alpha_threshold`.
+    skipped: Vec<u32>,
+}
+
+impl SaveColors {
+    fn map(&self, index: u32) -> u32 {
+        self.color_map.get(index as usize).copied().unwrap_or(index)
+    }
+}
+
+/// Sizes and asset indices of the bricks voxels become.
+struct BrickLayout {
+    /// Half sizes of an output brick along X, Y and Z.
+    brick_size: (u32, u32, u32),
+
+    /// Half sizes of a scaled voxel's brick. Scaled voxels are single bricks covering several
+    /// grid cells, which the simplifier splits back into cells.
+    voxel_size: (u32, u32, u32),
+
+    brick_asset: u32,
+
+    /// Studded and smooth assets for top faces, when `studs` applies.
+    stud_assets: Option<(u32, u32)>,
+
+    baseplate_asset: u32,
+    ramp_asset: u32,
+    wedge_asset: u32,
+}
+
+impl BrickLayout {
+    /// Half sizes of a cell of the simplifier's grid. The rampifier always works in bricks.
+    fn grid_cell(&self, options: &Vox2BrsOptions) -> (i32, i32, i32) {
+        if options.rampify {
+            (5, 5, 2)
+        } else {
+            (self.brick_size.0 as i32, self.brick_size.1 as i32, self.brick_size.2 as i32)
+        }
+    }
+}
+
+/// Reads the source's models and copies, leaving out voxels past their model's size and
+/// downsampling when a model is larger than `max_dimension`.
+fn load_models(
+    source: &dyn VoxelSource,
+    options: &Vox2BrsOptions,
+    stats: &mut ConversionStats,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<(Vec<SourceModel>, Vec<SourceCopy>), Vox2BrsError> {
+    let mut models = source.models();
+    let mut copies = source.copies();
+
+    // Voxels past their model's size would land outside its bounds once centered, so they're left out.
+    stats.out_of_range_voxel_count = models
+        .iter()
+        .map(|model| model.voxels.iter().filter(|voxel| !voxel_in_model(model, voxel)).count())
+        .sum();
+
+    if stats.out_of_range_voxel_count > 0 {
+        progress(ProgressEvent::Warning(format!(
+            "left out {} voxels outside their model's size",
+            stats.out_of_range_voxel_count,
+        )));
+    }
+
+    let largest_dimension = models
+        .iter()
+        .map(|model| model.size.0.max(model.size.1).max(model.size.2))
+        .max()
+        .unwrap_or(0);
+
+    match options.max_dimension {
+        Some(limit) if largest_dimension > limit && !options.downsample => {
+            return Err(Vox2BrsError::ModelTooLarge { size: largest_dimension, limit });
+        },
+        Some(limit) if largest_dimension > limit => {
+            let factor = (largest_dimension + limit.max(1) - 1) / limit.max(1);
+
+            models = models.iter().map(|model| downsample_model(model, factor)).collect();
+
+            for model_copy in &mut copies {
+                model_copy.position = downsample_position(model_copy.position, factor);
+            }
+
+            stats.downsample_factor = Some(factor);
+        },
+        _ => {},
+    }
+
+    Ok((models, copies))
+}
+
+/// Works out the save's palette from the source's and adds it to `brs_save`. Quantizing and
+/// dithering look at the colors voxels use, so this needs the models.
+fn load_colors(
+    source: &dyn VoxelSource,
+    options: &Vox2BrsOptions,
+    models: &[SourceModel],
+    brs_save: &mut SaveData,
+    stats: &mut ConversionStats,
+) -> SaveColors {
+    let alpha_threshold = options.alpha_threshold;
+
+    // Opacity of each color, with its material's alpha applied.
+    let mut alphas: Vec<f32> = source.palette().iter().map(|color| color.a as f32).collect();
+
+    for material in source.materials() {
+        if let Some(alpha) = alphas.get_mut(material.color_index as usize) {
+            *alpha *= material.alpha;
+        }
+    }
+
+    let mut source_colors: Vec<Color> = vec![];
+
+    for (index, vox_color) in source.palette().into_iter().enumerate() {
+        // Color correction
+        let rgb = if options.gamma_correct && !options.no_gamma_for_indices.contains(&(index as u32)) {
+            gamma_correction(vox_color.r, vox_color.g, vox_color.b)
+        } else {
+            (vox_color.r, vox_color.g, vox_color.b)
+        };
+
+        let a = match alpha_threshold {
+            Some(_) => alphas[index].round().clamp(0.0, 255.0) as u8,
+            None => 255,
+        };
+
+        let brs_color = Color {
+            r: rgb.0,
+            g: rgb.1,
+            b: rgb.2,
+            a,
+        };
+
+        source_colors.push(brs_color);
+    }
+
+    let mut skipped = options.skip_color_indices.clone();
+
+    if let Some(threshold) = options.palette_alpha_threshold {
+        for (index, alpha) in alphas.iter().enumerate() {
+            if *alpha < threshold as f32 && !skipped.contains(&(index as u32)) {
+                skipped.push(index as u32);
+            }
+        }
+    }
+
+    // Only colors voxels use are quantized, so unused palette entries don't take up any of the colors.
+    let quantized_palette = options.quantize.filter(|_| options.reference_palette.is_none()).map(|count| {
+        let mut used = vec![false; source_colors.len()];
+
+        for voxel in models.iter().flat_map(|model| model.voxels.iter()) {
+            if let Some(used) = used.get_mut(voxel.color_index as usize) {
+                *used = !skipped.contains(&voxel.color_index);
+            }
+        }
+
+        let used_colors: Vec<Color> = source_colors
+            .iter()
+            .zip(&used)
+            .filter(|(_, &used)| used)
+            .map(|(color, _)| color.clone())
+            .collect();
+
+        palette::median_cut(&used_colors, count)
+    });
+
+    // Source colors are already corrected, so a given palette is corrected to match them.
+    let reference_palette = options.reference_palette.clone().map(|palette| {
+        if !options.gamma_correct {
+            return palette;
+        }
+
+        palette
+            .into_iter()
+            .map(|color| {
+                let (r, g, b) = gamma_correction(color.r, color.g, color.b);
+                Color { r, g, b, a: color.a }
+            })
+            .collect()
+    });
+
+    let reference_palette = reference_palette
+        .or(quantized_palette)
+        .filter(|palette| !palette.is_empty());
+
+    let color_map: Vec<u32> = match &reference_palette {
+        Some(reference) => source_colors
+            .iter()
+            .map(|color| palette::nearest_color(reference, color).unwrap_or(0) as u32)
+            .collect(),
+        None => (0..source_colors.len() as u32).collect(),
+    };
+
+    let dithered: Vec<Vec<Option<u32>>> = match &reference_palette {
+        Some(reference) if options.dither => models
+            .iter()
+            .map(|model| {
+                let kept: Vec<usize> = (0..model.voxels.len())
+                    .filter(|&index| {
+                        let voxel = &model.voxels[index];
+                        (voxel.color_index as usize) < source_colors.len()
+                            && !skipped.contains(&voxel.color_index)
+                            && voxel_in_model(model, voxel)
+                    })
+                    .collect();
+
+                let cells: Vec<((u32, u32, u32), Color)> = kept
+                    .iter()
+                    .map(|&index| {
+                        let voxel = &model.voxels[index];
+                        (voxel.position, source_colors[voxel.color_index as usize].clone())
+                    })
+                    .collect();
+
+                let mut picks = vec![None; model.voxels.len()];
+
+                for (index, pick) in kept.into_iter().zip(palette::dither(&cells, reference)) {
+                    picks[index] = Some(pick as u32);
+                }
+
+                picks
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    let mut colors = SaveColors {
+        color_map,
+        dithered,
+        translucent: vec![false; reference_palette.as_ref().map_or(source_colors.len(), Vec::len)],
+        glow: vec![],
+        materials: options.color_materials.clone(),
+        skipped,
+    };
+
+    for (index, color) in source_colors.iter().enumerate() {
+        if alpha_threshold.map_or(false, |threshold| color.a < threshold) {
+            let mapped = colors.map(index as u32) as usize;
+            colors.translucent[mapped] = true;
+        }
+    }
+
+    // Add voxel colors to brickadia save color palette.
+    brs_save.header2.colors.extend(reference_palette.unwrap_or(source_colors));
+
+    colors.glow = vec![None; colors.translucent.len()];
+
+    if let Some(scale) = options.emissive_intensity {
+        for material in source.materials() {
+            if material.kind != MaterialKind::Emit {
+                continue;
+            }
+
+            let mapped = colors.map(material.color_index) as usize;
+
+            if let Some(glow) = colors.glow.get_mut(mapped) {
+                let intensity = material.emit * (1.0 + material.flux) * scale * 10.0;
+                *glow = Some(intensity.round().clamp(0.0, 10.0) as u32);
+            }
+        }
+    }
+
+    stats.color_count = colors.translucent.len();
+
+    if options.auto_materials {
+        for material in source.materials() {
+            if let Some(brick_material) = auto_material(material.kind) {
+                let mapped = colors.map(material.color_index);
+                colors.materials.entry(mapped).or_insert(brick_material);
+            }
+        }
+    }
+
+    colors
+}
+
+/// Works out the size of each voxel's brick and looks up the assets the options use in the
+/// save's brick asset list.
+fn brick_layout(options: &Vox2BrsOptions, assets: &[String]) -> Result<BrickLayout, Vox2BrsError> {
+    let mode = options.mode;
+    let resolve_asset = |asset: &BrickAsset| resolve_asset(assets, asset);
+
+    let (default_width, default_height) = mode.default_size();
+    let (width, height) = (options.width.unwrap_or(default_width), options.height.unwrap_or(default_height));
+    let depth = options.depth.unwrap_or(width);
+
+    // Studded and smooth assets for top faces, looked up before the mode's asset takes the name.
+    let stud_assets = if options.studs && mode != BrickOutputMode::MicroBrick {
+        Some((resolve_asset(&options.brick_asset)?, resolve_asset(&options.tile_asset)?))
+    } else {
+        None
+    };
+
+    let baseplate_asset = match (options.baseplate, mode) {
+        (None, _) => 0,
+        (Some(_), BrickOutputMode::Tile) => resolve_asset(&options.tile_asset)?,
+        (Some(_), _) => resolve_asset(&options.brick_asset)?,
+    };
+
+    let brick_asset = match mode {
+        BrickOutputMode::Brick | BrickOutputMode::Plate => &options.brick_asset,
+        BrickOutputMode::MicroBrick => &options.microbrick_asset,
+        BrickOutputMode::Tile => &options.tile_asset,
+    };
+
+    let voxel_scale = options.voxel_scale.max(1);
+
+    // A scaled voxel's brick has to fit too.
+    let unit = mode.unit_size();
+    let half_size = |count: u32, unit: u32| {
+        count.checked_mul(unit).filter(|size| (1..=MAX_PROCEDURAL_SIZE).contains(&size.saturating_mul(voxel_scale)))
+    };
+
+    let brick_size = match (half_size(width, unit.0), half_size(depth, unit.1), half_size(height, unit.2)) {
+        (Some(w), Some(l), Some(h)) => (w, l, h),
+        _ => return Err(Vox2BrsError::InvalidSize { width, depth, height }),
+    };
+
+    let brick_asset = resolve_asset(brick_asset)?;
+
+    // Ramps and wedges are only looked up when they're used, so other saves don't need them.
+    let ramp_asset = if options.rampify { resolve_asset(&options.ramp_asset)? } else { 0 };
+    let wedge_asset = if options.rampify || options.smooth_edges { resolve_asset(&options.wedge_asset)? } else { 0 };
+
+    Ok(BrickLayout {
+        brick_size,
+        voxel_size: (brick_size.0 * voxel_scale, brick_size.1 * voxel_scale, brick_size.2 * voxel_scale),
+        brick_asset,
+        stud_assets,
+        baseplate_asset,
+        ramp_asset,
+        wedge_asset,
+    })
+}
+
+/// Turns every voxel of every placed model and copy into a brick in `brs_save`, then flattens,
+/// hollows and centers them as the options ask.
+#[allow(clippy::too_many_arguments)]
+fn place_models(
+    source: &dyn VoxelSource,
+    options: &Vox2BrsOptions,
+    models: &[SourceModel],
+    copies: &[SourceCopy],
+    colors: &SaveColors,
+    layout: &BrickLayout,
+    brs_save: &mut SaveData,
+    stats: &mut ConversionStats,
+) -> Result<(), Vox2BrsError> {
+    let (mirror_x, mirror_y, mirror_z) = (options.mirror_x, options.mirror_y, options.mirror_z);
+    let up_axis = options.up_axis;
+    let voxel_size = layout.voxel_size;
+    let downsample_factor = stats.downsample_factor;
+
+    let model_to_bricks = |model_index: usize, pos: (i32, i32, i32), rot_option: Option<u8>, owner_index: u32, bricks: &mut Vec<Brick>| -> Result<(), Vox2BrsError> {
+        let model = &models[model_index];
+        let size = (model.size.0 as i32, model.size.1 as i32, model.size.2 as i32);
+
+        // Voxels face up unless the source turns them. Downsampled voxels don't line up with the
+        // source's, and the simplifier reads each brick's size as its footprint, so simplified
+        // voxels face up too.
+        let voxel_rotations = source
+            .voxel_rotations(model_index)
+            .filter(|_| downsample_factor.is_none() && !uses_grid(options))
+            .unwrap_or_default();
+        let model_turn = rot_option.map_or(magica::IDENTITY, magica::decode_rotation);
+
+        // A mirrored voxel faces the mirrored way, but keeps its handedness as bricks can't be mirrored.
+        let mirror_signs = [mirror_x, mirror_y, mirror_z].map(|mirror| if mirror { -1 } else { 1 });
+        let voxel_turn = |rotation_byte: u8| {
+            let mut turn = magica::decode_rotation(rotation_byte);
+
+            for (row, values) in turn.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value *= mirror_signs[row] * mirror_signs[column];
+                }
+            }
+
+            magica::multiply_rotation(&model_turn, &turn)
+        };
+
+        for (voxel_index, voxel) in model.voxels.iter().enumerate() {
+            if colors.skipped.contains(&voxel.color_index) || !voxel_in_model(model, voxel) || !in_z_range(options.z_range, voxel.position.2) {
+                continue;
+            }
+
+            // Mirroring flips the voxel within the model's bounds, before it's rotated into place.
+            let mirror = |p: u32, size: i32, mirror: bool| if mirror { size - 1 - p as i32 } else { p as i32 };
+
+            let mut vox_pos = (
+                mirror(voxel.position.0, size.0, mirror_x) - size.0 / 2,
+                mirror(voxel.position.1, size.1, mirror_y) - size.1 / 2,
+                mirror(voxel.position.2, size.2, mirror_z) - size.2 / 2
+            );
+
+            if let Some(rot) = rot_option {
+                vox_pos = row_major_rotation(vox_pos, rot);
+            }
+
+            let vox_pos = up_axis.to_z_up(vox_pos);
+            let pos = up_axis.to_z_up(pos);
+
+            let mut brick = Brick::default();
+            brick.size = Size::Procedural(voxel_size.0, voxel_size.1, voxel_size.2);
+            brick.asset_name_index = layout.brick_asset;
+
+            brick.position = voxel_brick_position(vox_pos, pos, voxel_size)
+                .ok_or(Vox2BrsError::GridTooLarge)?;
+
+            if let Some(&rotation_byte) = voxel_rotations.get(voxel_index) {
+                if let Some((direction, rotation, size)) = voxel_orientation(&voxel_turn(rotation_byte), up_axis, voxel_size) {
+                    brick.direction = direction;
+                    brick.rotation = rotation;
+                    brick.size = size;
+                }
+            }
+
+            brick.color = BrickColor::Index(
+                colors.dithered
+                    .get(model_index)
+                    .and_then(|picks| picks[voxel_index])
+                    .unwrap_or_else(|| colors.map(voxel.color_index)),
+            );
+
+            brick.owner_index = owner_index;
+
+            bricks.push(brick);
+        }
+
+        Ok(())
+    };
+
+    // With grouping, each placed model's bricks belong to an owner of their own, named after the author.
+    let author = brs_save.header1.author.clone();
+    let author_owner = author_owner_index(brs_save);
+    let mut group_owners: u128 = 0;
+
+    let mut next_owner = |brs_save: &mut SaveData| -> u32 {
+        if !options.preserve_model_grouping {
+            return author_owner;
+        }
+
+        group_owners += 1;
+
+        let owner = User {
+            name: format!("{} (model {})", author.name, group_owners),
+            id: Uuid::from_u128(author.id.as_u128().wrapping_add(group_owners)),
+        };
+
+        brs_save.header2.brick_owners.push(BrickOwner::from_user_bricks(owner, 0));
+        brs_save.header2.brick_owners.len() as u32
+    };
+
+    let global_offset = options.global_offset;
+    let offset_position = |position: Option<(i32, i32, i32)>| -> Result<(i32, i32, i32), Vox2BrsError> {
+        let (x, y, z) = position.unwrap_or((0, 0, 0));
+
+        match (x.checked_add(global_offset.0), y.checked_add(global_offset.1), z.checked_add(global_offset.2)) {
+            (Some(x), Some(y), Some(z)) => Ok((x, y, z)),
+            _ => Err(Vox2BrsError::GridTooLarge),
+        }
+    };
+
+    for (model_index, model) in models.iter().enumerate() {
+        if is_cancelled(&options.cancel) {
+            return Err(Vox2BrsError::Cancelled);
+        }
+
+        let pos = offset_position(model.position)?;
+        let owner = next_owner(brs_save);
+        model_to_bricks(model_index, pos, model.rotation, owner, &mut brs_save.bricks)?;
+    }
+
+    for model_copy in copies.iter() {
+        if is_cancelled(&options.cancel) {
+            return Err(Vox2BrsError::Cancelled);
+        }
+
+        if model_copy.model_index < models.len() {
+            let pos = offset_position(model_copy.position)?;
+            let owner = next_owner(brs_save);
+            model_to_bricks(model_copy.model_index, pos, model_copy.rotation, owner, &mut brs_save.bricks)?;
+        }
+    }
+
+    if let Some(axis) = options.flatten {
+        flatten_bricks(&mut brs_save.bricks, axis);
+    }
+
+    // Runs before simplifying, so the remaining shell still merges into large bricks.
+    if options.surface_only {
+        stats.interior_voxel_count = remove_interior_bricks(&mut brs_save.bricks, voxel_size);
+    }
+
+    if options.center {
+        let brick_size = layout.brick_size;
+        center_bricks(&mut brs_save.bricks, (brick_size.0 as i32 * 2, brick_size.1 as i32 * 2, brick_size.2 as i32 * 2));
+    }
+
+    Ok(())
+}
+
+/// Generates ramps and wedges for a chunk's dense grid, clearing the cells they take. The
+/// ramps are positioned within the chunk.
+fn rampify_chunk(
+    options: &Vox2BrsOptions,
+    layout: &BrickLayout,
+    grid: &mut Vec<Cell>,
+    grid_size: GridPos,
+    stats: &mut ConversionStats,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<Vec<Brick>, Vox2BrsError> {
+    progress(ProgressEvent::Rampifying { voxels: grid.len() });
+
+    let rampify_options = options.rampify_options.clone().unwrap_or_default();
+
+    let rampifier_config = RampifierConfig {
+        ramp_index: layout.ramp_asset,
+        wedge_index: layout.wedge_asset,
+        ..rampify_options.config
+    };
+
+    // The rampifier's grid only holds 256 colors.
+    let ramp_grid = grid
+        .iter()
+        .map(|cell| cell.map(|index| u8::try_from(index).map_err(|_| Vox2BrsError::TooManyColors(index as u32))).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rampifier = Rampifier::new(
+        grid_size,
+        ramp_grid,
+        rampifier_config.clone()
+    );
+
+    let now = Instant::now();
+
+    // Generate ramps for floor and ceiling.
+    let mut ramps = if rampify_options.floors {
+        rampifier.generate_ramps(true)
+    } else {
+        vec![]
+    };
+    let ramps2 = &mut if rampify_options.ceilings {
+        rampifier.generate_ramps(false)
+    } else {
+        vec![]
+    };
+
+    // Sets the voxels occupied by ramps to empty.
+    rampifier.remove_occupied_voxels();
+
+    // Move grid back out of the rampifier to do further processing.
+    let mut ramp_grid = rampifier.move_grid();
+
+    // Walls are rampified on what's left, so they never take cells from floors and ceilings.
+    let walls = [(rampify_options.walls_x, WallAxis::X), (rampify_options.walls_y, WallAxis::Y)];
+    let wall_ramps = &mut vec![];
+
+    for (_, axis) in walls.into_iter().filter(|(enabled, _)| *enabled) {
+        wall_ramps.append(&mut ramps::wall_ramps(&mut ramp_grid, grid_size, axis, rampifier_config.clone()));
+    }
+
+    ramps.append(ramps2);
+    ramps.append(wall_ramps);
+
+    stats.ramp_count += ramps.len();
+
+    let elapsed = now.elapsed();
+    stats.timings.rampify += elapsed;
+
+    progress(ProgressEvent::Rampified { ramps: ramps.len(), elapsed });
+
+    *grid = ramp_grid.into_iter().map(|cell| cell.map(u16::from)).collect();
+
+    Ok(ramps)
+}
+
+/// Replaces the save's bricks with the fewest boxes that cover the same cells, along with any
+/// ramps and wedges. The new bricks are positioned in the grid, which starts at the returned
+/// cell, for `offset_bricks` to move back.
+fn simplify_bricks(
+    options: &Vox2BrsOptions,
+    layout: &BrickLayout,
+    brs_save: &mut SaveData,
+    stats: &mut ConversionStats,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<(i32, i32, i32), Vox2BrsError> {
+    let max_box_size = options.max_box_size;
+    let min_brick_volume = options.min_brick_volume;
+    let merge_small_bricks = options.merge_small_bricks;
+    let (rampify, smooth_edges, hollow) = (options.rampify, options.smooth_edges, options.hollow);
+    let cancelled = || is_cancelled(&options.cancel);
+
+    // Move brick vector so we can re-write the optimized version into the save.
+    let bricks = std::mem::take(&mut brs_save.bricks);
+
+    let brick_size = layout.grid_cell(options);
+
+    let fix_brick_pos = |brick: &Brick| -> (i32, i32, i32) {
+        let (mut x, mut  y, mut  z) = brick.position;
+        if let Size::Procedural(w_half, l_half, h_half) = brick.size {
+            x -= w_half as i32;
+            y -= l_half as i32;
+            z -= h_half as i32;
+
+            x /= brick_size.0 * 2;
+            y /= brick_size.1 * 2;
+            z /= brick_size.2 * 2;
+
+            return (x, y, z);
+        }
+
+        (0, 0, 0)
+    };
+
+    // Find bounds for bricks.
+    let mut min_bounds = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max_bounds = (i32::MIN, i32::MIN, i32::MIN);
+
+    for brick in &bricks {
+        if let Size::Procedural(w_half, l_half, h_half) = brick.size {
+            let w = w_half as i32 / brick_size.0;
+            let l = l_half as i32 / brick_size.1;
+            let h = h_half as i32 / brick_size.2;
+
+            let pos = fix_brick_pos(&brick);
+
+            min_bounds.0 = min_bounds.0.min(pos.0);
+            min_bounds.1 = min_bounds.1.min(pos.1);
+            min_bounds.2 = min_bounds.2.min(pos.2);
+
+            let pos = (
+                pos.0 + w + 1,
+                pos.1 + l + 1,
+                pos.2 + h + 1,
+            );
+
+            max_bounds.0 = max_bounds.0.max(pos.0);
+            max_bounds.1 = max_bounds.1.max(pos.1);
+            max_bounds.2 = max_bounds.2.max(pos.2);
+        }
+    }
+
+    // Bounds can be further apart than fits in an i32.
+    let span = |min: i32, max: i32| usize::try_from(max as i64 - min as i64).map_err(|_| Vox2BrsError::GridTooLarge);
+
+    let grid_size = (
+        span(min_bounds.0, max_bounds.0)?,
+        span(min_bounds.1, max_bounds.1)?,
+        span(min_bounds.2, max_bounds.2)?,
+    );
+
+    // Each voxel as the cells it covers and its color.
+    let mut voxels = vec![];
+
+    for brick in &bricks {
+        if let (&Size::Procedural(w_half, l_half, h_half), &BrickColor::Index(index)) = (&brick.size, &brick.color) {
+            let value = u16::try_from(index).map_err(|_| Vox2BrsError::TooManyColors(index))?;
+            let pos = fix_brick_pos(brick);
+
+            voxels.push((
+                ((pos.0 - min_bounds.0) as usize, (pos.1 - min_bounds.1) as usize, (pos.2 - min_bounds.2) as usize),
+                (w_half as usize / brick_size.0 as usize, l_half as usize / brick_size.1 as usize, h_half as usize / brick_size.2 as usize),
+                value,
+                brick.owner_index,
+            ));
+        }
+    }
+
+    drop(bricks);
+
+    #[cfg(feature = "debug-slices")]
+    if let Some(directory) = &options.debug_slices {
+        let mut cells = vec![];
+
+        for &(pos, (w, l, h), value, _) in &voxels {
+            for i in 0..w {
+                for j in 0..l {
+                    for k in 0..h {
+                        cells.push(((pos.0 + i, pos.1 + j, pos.2 + k), value));
+                    }
+                }
+            }
+        }
+
+        if let Err(error) = debug::write_slices(directory, grid_size, cells, &brs_save.header2.colors) {
+            progress(ProgressEvent::Warning(format!("couldn't write debug slices to {}: {}", directory.display(), error)));
+        }
+    }
+
+    // Similar colors are simplified as one, remembering each cell's own color to pick each box's color from.
+    let original_colors = match options.color_merge_tolerance {
+        Some(tolerance) if !options.merge_colors => {
+            let mut originals: HashMap<(u32, GridPos), u16> = HashMap::new();
+            let mut cells_by_color: BTreeMap<u16, usize> = BTreeMap::new();
+
+            for &(pos, (w, l, h), value, owner) in &voxels {
+                *cells_by_color.entry(value).or_default() += w * l * h;
+
+                for i in 0..w {
+                    for j in 0..l {
+                        for k in 0..h {
+                            originals.insert((owner, (pos.0 + i, pos.1 + j, pos.2 + k)), value);
+                        }
+                    }
+                }
+            }
+
+            // Most used colors first, so groups form around them.
+            let mut order: Vec<(u16, usize)> = cells_by_color.into_iter().collect();
+            order.sort_by_key(|&(value, cells)| (std::cmp::Reverse(cells), value));
+            let order: Vec<usize> = order.into_iter().map(|(value, _)| value as usize).collect();
+
+            let groups = palette::group_similar_colors(&brs_save.header2.colors, &order, tolerance);
+
+            for voxel in &mut voxels {
+                voxel.2 = groups[voxel.2 as usize] as u16;
+            }
+
+            Some(originals)
+        },
+        _ => None,
+    };
+
+    let merged_color = if options.merge_colors {
+        let color = match options.merged_color {
+            Some(color) => color,
+            None => {
+                // Most common color by cells covered, with ties going to the lowest index.
+                let mut cells_by_color: BTreeMap<u16, usize> = BTreeMap::new();
+
+                for &(_, (w, l, h), value, _) in &voxels {
+                    *cells_by_color.entry(value).or_default() += w * l * h;
+                }
+
+                cells_by_color.into_iter().rev().max_by_key(|&(_, cells)| cells).map_or(0, |(value, _)| value as u32)
+            },
+        };
+
+        Some(u16::try_from(color).map_err(|_| Vox2BrsError::TooManyColors(color))?)
+    } else {
+        None
+    };
+
+    // Without a chunk size, the whole grid is one chunk.
+    let chunk_size = options.chunk_size.map_or(grid_size, |size| (size.max(1), size.max(1), size.max(1)));
+    let chunk_of = |cell: GridPos| (cell.0 / chunk_size.0, cell.1 / chunk_size.1, cell.2 / chunk_size.2);
+
+    // Voxels in each chunk of each owner's bricks, by index into `voxels`. Owners are simplified
+    // apart to keep models in their own groups. Voxels scaled up can straddle chunks.
+    let mut chunks: BTreeMap<(u32, GridPos), Vec<usize>> = BTreeMap::new();
+
+    for (i, &(pos, extent, _, owner)) in voxels.iter().enumerate() {
+        let last = |p: usize, e: usize| p + e.max(1) - 1;
+        let (first, last) = (chunk_of(pos), chunk_of((last(pos.0, extent.0), last(pos.1, extent.1), last(pos.2, extent.2))));
+
+        for x in first.0..=last.0 {
+            for y in first.1..=last.1 {
+                for z in first.2..=last.2 {
+                    chunks.entry((owner, (x, y, z))).or_default().push(i);
+                }
+            }
+        }
+    }
+
+    let chunked = chunk_size.0 < grid_size.0 || chunk_size.1 < grid_size.1 || chunk_size.2 < grid_size.2;
+
+    let largest_brick = [
+        max_box_size.0 as u64 * brick_size.0 as u64,
+        max_box_size.1 as u64 * brick_size.1 as u64,
+        max_box_size.2 as u64 * brick_size.2 as u64,
+    ];
+
+    if largest_brick.iter().any(|&size| size > MAX_PROCEDURAL_SIZE as u64) {
+        progress(ProgressEvent::Warning(format!(
+            "the largest simplified brick would be {}x{}x{} half units, over Brickadia's limit of {}",
+            largest_brick[0], largest_brick[1], largest_brick[2], MAX_PROCEDURAL_SIZE,
+        )));
+    }
+
+    // Boxes from every chunk, positioned in the whole grid, by owner.
+    let mut filled_boxes: BTreeMap<u32, Vec<simplify::FilledBox>> = BTreeMap::new();
+
+    for ((owner, chunk), members) in chunks {
+        if cancelled() {
+            return Err(Vox2BrsError::Cancelled);
+        }
+
+        let origin = (chunk.0 * chunk_size.0, chunk.1 * chunk_size.1, chunk.2 * chunk_size.2);
+        let full_size = grid_size;
+
+        let grid_size = (
+            chunk_size.0.min(full_size.0 - origin.0),
+            chunk_size.1.min(full_size.1 - origin.1),
+            chunk_size.2.min(full_size.2 - origin.2),
+        );
+
+        let grid_len = grid_size.0
+            .checked_mul(grid_size.1)
+            .and_then(|len| len.checked_mul(grid_size.2))
+            .ok_or(Vox2BrsError::GridTooLarge)?;
+
+        // Writes each cell of this chunk's voxels, positioned in the chunk.
+        let write_cells = |set: &mut dyn FnMut(GridPos, u16)| {
+            for &voxel in &members {
+                let (pos, (w, l, h), value, _) = voxels[voxel];
+
+                for i in 0..w {
+                    for j in 0..l {
+                        for k in 0..h {
+                            let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
+
+                            let in_chunk = (origin.0..origin.0 + grid_size.0).contains(&pos.0)
+                                && (origin.1..origin.1 + grid_size.1).contains(&pos.1)
+                                && (origin.2..origin.2 + grid_size.2).contains(&pos.2);
+
+                            if in_chunk {
+                                set((pos.0 - origin.0, pos.1 - origin.1, pos.2 - origin.2), value);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        let filled_cells: usize = members
+            .iter()
+            .map(|&voxel| {
+                let (_, (w, l, h), _, _) = voxels[voxel];
+                w * l * h
+            })
+            .sum();
+
+        let fill_strategy = if options.optimize_axis_order {
+            let mut sample = SparseGrid::new(grid_size);
+            write_cells(&mut |pos, value| sample.set(pos, Some(value)));
+
+            let order = sample.best_grow_order(max_box_size);
+            progress(ProgressEvent::GrowOrderChosen { order });
+            FillStrategy::Order(order)
+        } else {
+            options.fill_strategy
+        };
+
+        // Ramps, smoothing and filling pockets look at every cell, but everything else can
+        // skip the empty space of a mostly empty chunk.
+        let sparse = !rampify && !smooth_edges && hollow != HollowMode::FillInterior && filled_cells * 16 < grid_len;
+
+        // Ramps and wedges made in this chunk, positioned within it.
+        let mut chunk_bricks = vec![];
+
+        let boxes = if sparse {
+            let mut grid = SparseGrid::new(grid_size);
+            write_cells(&mut |pos, value| grid.set(pos, Some(value)));
+
+            if hollow == HollowMode::RemoveInterior {
+                grid.remove_interior();
+            }
+
+            progress(ProgressEvent::FillingGaps);
+
+            let mut color_aware_boxes = 0;
+
+            if let Some(color) = merged_color {
+                color_aware_boxes = grid.fill_until(fill_strategy, max_box_size, &cancelled)
+                    .ok_or(Vox2BrsError::Cancelled)?
+                    .len();
+
+                grid.paint(color);
+            }
+
+            let mut boxes = grid.fill_until(fill_strategy, max_box_size, &cancelled)
+                .ok_or(Vox2BrsError::Cancelled)?;
+
+            stats.merged_color_savings += color_aware_boxes.saturating_sub(boxes.len());
+
+            if min_brick_volume > 1 && merge_small_bricks {
+                let merged = grid.merge_small_boxes(&boxes, min_brick_volume);
+                stats.merged_voxel_count += merged;
+
+                if merged > 0 {
+                    boxes = grid.fill_until(fill_strategy, max_box_size, &cancelled)
+                        .ok_or(Vox2BrsError::Cancelled)?;
+                }
+            }
+
+            boxes
+        } else {
+            let get_index = |pos: (usize, usize, usize)| -> usize {
+                pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
+            };
+
+            stats.largest_dense_grid = stats.largest_dense_grid.max(grid_len);
+
+            let mut grid: Vec<Cell> = vec![None; grid_len];
+            write_cells(&mut |pos, value| grid[get_index(pos)] = Some(value));
+
+            simplify::hollow(&mut grid, grid_size, hollow);
+
+            if rampify {
+                chunk_bricks.append(&mut rampify_chunk(options, layout, &mut grid, grid_size, stats, progress)?);
+            }
+
+            // Wedges only go in empty cells, so the grid is filled with bricks as usual afterwards.
+            if smooth_edges && !rampify {
+                let mut wedges = smooth::step_wedges(&grid, grid_size, brick_size, layout.wedge_asset);
+                stats.wedge_count += wedges.len();
+                chunk_bricks.append(&mut wedges);
+            }
+
+            progress(ProgressEvent::FillingGaps);
+
+            let mut color_aware_boxes = 0;
+
+            if let Some(color) = merged_color {
+                color_aware_boxes = simplify::fill_until(&grid, grid_size, fill_strategy, max_box_size, &cancelled)
+                    .ok_or(Vox2BrsError::Cancelled)?
+                    .len();
+
+                simplify::paint(&mut grid, color);
+            }
+
+            let mut boxes = simplify::fill_until(&grid, grid_size, fill_strategy, max_box_size, &cancelled)
+                .ok_or(Vox2BrsError::Cancelled)?;
+
+            stats.merged_color_savings += color_aware_boxes.saturating_sub(boxes.len());
+
+            if min_brick_volume > 1 && merge_small_bricks {
+                let merged = simplify::merge_small_boxes(&mut grid, grid_size, &boxes, min_brick_volume);
+                stats.merged_voxel_count += merged;
+
+                if merged > 0 {
+                    boxes = simplify::fill_until(&grid, grid_size, fill_strategy, max_box_size, &cancelled)
+                        .ok_or(Vox2BrsError::Cancelled)?;
+                }
+            }
+
+            boxes
+        };
+
+        for brick in &mut chunk_bricks {
+            brick.owner_index = owner;
+
+            if let Some(color) = merged_color {
+                brick.color = BrickColor::Index(color as u32);
+            }
+        }
+
+        filled_boxes.entry(owner).or_default().extend(boxes.into_iter().map(|filled| simplify::FilledBox {
+            pos: (filled.pos.0 + origin.0, filled.pos.1 + origin.1, filled.pos.2 + origin.2),
+            ..filled
+        }));
+
+        let offset = |cell: usize, unit: i32| {
+            i32::try_from(cell).ok().and_then(|cell| cell.checked_mul(unit * 2)).ok_or(Vox2BrsError::GridTooLarge)
+        };
+        let offset = (
+            offset(origin.0, brick_size.0)?,
+            offset(origin.1, brick_size.1)?,
+            offset(origin.2, brick_size.2)?,
+        );
+
+        for mut brick in chunk_bricks {
+            let (x, y, z) = brick.position;
+
+            brick.position = (
+                x.checked_add(offset.0).ok_or(Vox2BrsError::GridTooLarge)?,
+                y.checked_add(offset.1).ok_or(Vox2BrsError::GridTooLarge)?,
+                z.checked_add(offset.2).ok_or(Vox2BrsError::GridTooLarge)?,
+            );
+
+            brs_save.bricks.push(brick);
+        }
+    }
+
+    for (owner, mut boxes) in filled_boxes {
+        // Boxes cut off at chunk edges are joined back up where they line up.
+        if chunked {
+            boxes = simplify::stitch(boxes, max_box_size);
+        }
+
+        if options.weld {
+            let before = boxes.len();
+            boxes = simplify::weld(boxes, grid_size, max_box_size);
+            stats.weld_savings += before - boxes.len();
+        }
+
+        // Dropped after stitching, so pieces of larger boxes cut off by chunk edges are kept.
+        if min_brick_volume > 1 {
+            stats.dropped_voxel_count += simplify::drop_small_boxes(&mut boxes, min_brick_volume);
+        }
+
+        if let Some(originals) = &original_colors {
+            for filled in &mut boxes {
+                filled.value = dominant_color(originals, owner, filled);
+            }
+        }
+
+        for filled in boxes {
+            let (x, y, z) = filled.pos;
+            let (w, l, h) = filled.size;
+
+            let mut brick = Brick::default();
+
+            let size = (w as u32 * brick_size.0 as u32, l as u32 * brick_size.1 as u32, h as u32 * brick_size.2 as u32);
+            {
+                let (x, y, z) = (x as i32 * brick_size.0 * 2, y as i32 * brick_size.1 * 2, z as i32 * brick_size.2 * 2);
+
+                brick.position = (
+                    x + size.0 as i32,
+                    y + size.1 as i32,
+                    z + size.2 as i32
+                );
+
+                brick.size = Size::Procedural(size.0, size.1, size.2);
+            }
+
+            brick.color = BrickColor::Index(filled.value as u32);
+            brick.asset_name_index = layout.brick_asset;
+            brick.owner_index = owner;
+            brs_save.bricks.push(brick);
+        }
+    }
+
+    Ok(min_bounds)
+}
+
+/// Moves simplified bricks from the grid, whose first cell is `min_bounds`, back to where
+/// the model was.
+fn offset_bricks(bricks: &mut [Brick], min_bounds: (i32, i32, i32), cell: (i32, i32, i32)) -> Result<(), Vox2BrsError> {
+    let offset = |min: i32, unit: i32| min.checked_mul(unit * 2).ok_or(Vox2BrsError::GridTooLarge);
+    let offset = (
+        offset(min_bounds.0, cell.0)?,
+        offset(min_bounds.1, cell.1)?,
+        offset(min_bounds.2, cell.2)?,
+    );
+
+    for brick in bricks {
+        let (x, y, z) = brick.position;
+
+        brick.position = (
+            x.checked_add(offset.0).ok_or(Vox2BrsError::GridTooLarge)?,
+            y.checked_add(offset.1).ok_or(Vox2BrsError::GridTooLarge)?,
+            z.checked_add(offset.2).ok_or(Vox2BrsError::GridTooLarge)?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Adds studs and the baseplate, turns and aligns the build, and sets the bricks' materials,
+/// colors, collision and owners, checking the result fits the options' limits.
+fn finish_bricks(
+    options: &Vox2BrsOptions,
+    colors: &SaveColors,
+    layout: &BrickLayout,
+    brs_save: &mut SaveData,
+) -> Result<(), Vox2BrsError> {
+    // Before the baseplate is added, which is never covered and has its own asset.
+    if let Some((studded, smooth)) = layout.stud_assets {
+        stud_top_faces(&mut brs_save.bricks, layout.brick_asset, studded, smooth);
+    }
+
+    if let Some((width, length)) = options.baseplate {
+        let footprint = (layout.brick_size.0, layout.brick_size.1);
+
+        if let Some(plate) = baseplate_brick(&brs_save.bricks, (width, length), footprint, layout.baseplate_asset) {
+            let owner_index = author_owner_index(brs_save);
+
+            brs_save.bricks.push(Brick {
+                color: BrickColor::Index(options.baseplate_color_index),
+                owner_index,
+                ..plate
+            });
+        }
+    }
+
+    rotate_bricks(&mut brs_save.bricks, options.world_rotation);
+
+    if options.grid_align {
+        align_bricks(&mut brs_save.bricks);
+    }
+
+    if colors.translucent.contains(&true) {
+        let glass_index = material_index(brs_save, BrickMaterial::Glass.asset_name());
+
+        for brick in &mut brs_save.bricks {
+            if let BrickColor::Index(index) = brick.color {
+                if colors.translucent.get(index as usize) == Some(&true) {
+                    brick.material_index = glass_index;
+                }
+            }
+        }
+    }
+
+    if colors.glow.iter().any(Option::is_some) {
+        let glow_index = material_index(brs_save, BrickMaterial::Glow.asset_name());
+
+        for brick in &mut brs_save.bricks {
+            if let BrickColor::Index(index) = brick.color {
+                if let Some(Some(intensity)) = colors.glow.get(index as usize) {
+                    brick.material_index = glow_index;
+                    brick.material_intensity = *intensity;
+                }
+            }
+        }
+    }
+
+    if !colors.materials.is_empty() {
+        let material_indices: BTreeMap<u32, u32> = colors.materials
+            .iter()
+            .map(|(&color_index, material)| (color_index, material_index(brs_save, material.asset_name())))
+            .collect();
+
+        for brick in &mut brs_save.bricks {
+            if let BrickColor::Index(index) = brick.color {
+                if let Some(&material) = material_indices.get(&index) {
+                    brick.material_index = material;
+                }
+            }
+        }
+    }
+
+    // Every pass above works with palette indices, so they're only swapped for the colors themselves at the end.
+    if options.use_unique_colors {
+        let colors = std::mem::take(&mut brs_save.header2.colors);
+
+        for brick in &mut brs_save.bricks {
+            if let BrickColor::Index(index) = brick.color {
+                if let Some(color) = colors.get(index as usize) {
+                    brick.color = BrickColor::Unique(color.clone());
+                }
+            }
+        }
+    }
+
+    if options.collision != BrickCollision::All {
+        for brick in &mut brs_save.bricks {
+            brick.collision = options.collision.flags();
+        }
+    }
+
+    if !options.extra_owners.is_empty() && !options.preserve_model_grouping {
+        share_bricks(brs_save, options.extra_owners.clone());
+    }
+
+    // Owners are added before their bricks exist, so their brick counts are filled in now.
+    count_owner_bricks(brs_save);
+
+    check_brick_count(options, brs_save.bricks.len())?;
+
+    if let Some(version) = options.save_version {
+        check_save_version(brs_save, version)?;
+        brs_save.version = version;
+    }
+
+    Ok(())
+}
+
+/// Same as `vox2brs`, reporting progress to `progress` as it goes.
+pub fn vox2brs_with_progress(
+    in_vox_data: impl VoxelSource,
+    mut brs_save: SaveData,
+    options: Vox2BrsOptions,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<(SaveData, ConversionStats), Vox2BrsError> {
+    let now = Instant::now();
+    let mut stats = ConversionStats::default();
+
+    if let Some(brick_assets) = &options.brick_assets {
+        brs_save.header2.brick_assets = brick_assets.clone();
+    }
+
+    let mut stage = Instant::now();
+
+    progress(ProgressEvent::LoadingColors);
+
+    let (models, copies) = load_models(&in_vox_data, &options, &mut stats, progress)?;
+    let colors = load_colors(&in_vox_data, &options, &models, &mut brs_save, &mut stats);
+
+    stats.timings.colors = stage.elapsed();
+
+    progress(ProgressEvent::ColorsLoaded { count: stats.color_count });
+
+    let layout = brick_layout(&options, &brs_save.header2.brick_assets)?;
+
+    let models_len = models.len() + copies.len();
+
+    stage = Instant::now();
+
+    progress(ProgressEvent::ReadingModels { count: models_len });
+
+    place_models(&in_vox_data, &options, &models, &copies, &colors, &layout, &mut brs_save, &mut stats)?;
+
+    stats.voxel_brick_count = brs_save.bricks.len();
+
+    stats.timings.models = stage.elapsed();
+
+    progress(ProgressEvent::ModelsRead { count: models_len, bricks: stats.voxel_brick_count });
+
+    // The simplifier's bounds are meaningless without bricks, so stop before making a grid for nothing.
+    if brs_save.bricks.is_empty() {
+        return Err(Vox2BrsError::EmptyModel);
+    }
+
+    if uses_grid(&options) {
+        stage = Instant::now();
+
+        progress(ProgressEvent::Simplifying);
+
+        let min_bounds = simplify_bricks(&options, &layout, &mut brs_save, &mut stats, progress)?;
+
+        stats.timings.simplify = stage.elapsed().saturating_sub(stats.timings.rampify);
+        stage = Instant::now();
+
+        offset_bricks(&mut brs_save.bricks, min_bounds, layout.grid_cell(&options))?;
+
+        stats.timings.offset = stage.elapsed();
+
+        progress(ProgressEvent::GapsFilled { bricks: brs_save.bricks.len() });
+    } else {
+        // Simplifying can only lower the count, so only fail this early when there's no simplifying to do.
+        check_brick_count(&options, stats.voxel_brick_count)?;
+    }
+
+    stage = Instant::now();
+
+    finish_bricks(&options, &colors, &layout, &mut brs_save)?;
+
+    stats.brick_count = brs_save.bricks.len();
+    stats.timings.finish = stage.elapsed();
+    stats.elapsed = now.elapsed();
+
+    progress(ProgressEvent::Finished { bricks: stats.brick_count, elapsed: stats.elapsed });
+
+    Ok((brs_save, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestSource {
+        models: Vec<SourceModel>,
+        palette: Vec<Color>,
+
+        /// Rotation bytes of every model's voxels, in the order of its voxels.
+        rotations: Option<Vec<u8>>,
+    }
+
+    impl TestSource {
+        /// A source of `models` whose palette has 4 colors, all red, so color indices stay apart.
+        fn new(models: Vec<SourceModel>) -> Self {
+            Self {
+                models,
+                palette: vec![Color { r: 255, g: 0, b: 0, a: 255 }; 4],
+                rotations: None,
+            }
+        }
+
+        fn with_palette(mut self, palette: Vec<Color>) -> Self {
+            self.palette = palette;
+            self
+        }
+
+        fn with_rotations(mut self, rotations: Vec<u8>) -> Self {
+            self.rotations = Some(rotations);
+            self
+        }
+    }
+
+    impl VoxelSource for TestSource {
+        fn palette(&self) -> Vec<Color> {
+            self.palette.clone()
+        }
+
+        fn models(&self) -> Vec<SourceModel> {
+            self.models.clone()
+        }
+
+        fn copies(&self) -> Vec<source::SourceCopy> {
+            vec![]
+        }
+
+        fn voxel_rotations(&self, _model_index: usize) -> Option<Vec<u8>> {
+            self.rotations.clone()
+        }
+    }
+
+    fn test_save() -> SaveData {
+        let mut save = SaveData::default();
+        save.header2.brick_assets = vec![
+            "PB_DefaultBrick".into(),
+            "PB_DefaultMicroBrick".into(),
+            "PB_DefaultTile".into(),
+            "PB_DefaultRamp".into(),
+            "PB_DefaultWedge".into(),
+        ];
+        save.header2.materials = vec!["BMC_Plastic".into()];
+        save
+    }
+
+    fn micro_options() -> Vox2BrsOptions {
+        Vox2BrsOptions::default()
+            .mode(BrickOutputMode::MicroBrick)
+            .width(1)
+            .height(1)
+    }
+
+    fn convert_micro(source: TestSource) -> SaveData {
+        vox2brs(source, test_save(), micro_options()).unwrap().0
+    }
+
+    fn convert_micro_sized(source: TestSource, width: u32, height: u32) -> SaveData {
+        vox2brs(source, test_save(), micro_options().width(width).height(height)).unwrap().0
+    }
+
+    /// Every rotation byte whose rows point along different axes, with their column and sign per row.
+    fn orientation_bytes() -> Vec<(u8, [(usize, i32); 3])> {
+        let mut bytes = vec![];
+
+        for r1 in 0..3u8 {
+            for r2 in (0..3u8).filter(|&r2| r2 != r1) {
+                let r3 = 3 - r1 - r2;
+
+                for signs in 0..8u8 {
+                    let sign = |bit: u8| if (signs >> bit) & 1 == 1 { -1 } else { 1 };
+                    let byte = r1 | r2 << 2 | signs << 4;
+                    bytes.push((byte, [(r1 as usize, sign(0)), (r2 as usize, sign(1)), (r3 as usize, sign(2))]));
+                }
+            }
+        }
+
+        bytes
+    }
+
+    fn determinant(rows: &[(usize, i32); 3]) -> i32 {
+        let mut m = [[0; 3]; 3];
+        for (i, &(column, sign)) in rows.iter().enumerate() {
+            m[i][column] = sign;
+        }
+
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    #[test]
+    fn rotation_maps_basis_vectors() {
+        let basis = [(1, 0, 0), (0, 1, 0), (0, 0, 1)];
+
+        for (byte, rows) in orientation_bytes() {
+            for (row, &(column, sign)) in rows.iter().enumerate() {
+                let rotated = row_major_rotation(basis[column], byte);
+                let rotated = [rotated.0, rotated.1, rotated.2];
+
+                // The basis vector along the row's column lands on the row's axis, with the row's sign.
+                for (axis, &value) in rotated.iter().enumerate() {
+                    let expected = if axis == row { sign } else { 0 };
+                    assert_eq!(value, expected, "byte {} column {} axis {}", byte, column, axis);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn there_are_24_proper_rotations() {
+        let proper: Vec<u8> = orientation_bytes()
+            .into_iter()
+            .filter(|(_, rows)| determinant(rows) == 1)
+            .map(|(byte, _)| byte)
+            .collect();
+
+        assert_eq!(proper.len(), 24);
+
+        // Proper rotations keep handedness: x cross y still gives z.
+        for byte in proper {
+            let x = row_major_rotation((1, 0, 0), byte);
+            let y = row_major_rotation((0, 1, 0), byte);
+            let z = row_major_rotation((0, 0, 1), byte);
+            let cross = (x.1 * y.2 - x.2 * y.1, x.2 * y.0 - x.0 * y.2, x.0 * y.1 - x.1 * y.0);
+            assert_eq!(cross, z, "byte {}", byte);
+        }
+    }
+
+    #[test]
+    fn rotation_identity_and_known_turns() {
+        // Rows (1, 0, 0), (0, 1, 0), (0, 0, 1).
+        assert_eq!(row_major_rotation((1, 2, 3), 4), (1, 2, 3));
+        // Rows (0, -1, 0), (1, 0, 0), (0, 0, 1): a quarter turn around Z.
+        assert_eq!(row_major_rotation((1, 2, 3), 17), (-2, 1, 3));
+        // Both first rows negated: a half turn around Z.
+        assert_eq!(row_major_rotation((1, 2, 3), 4 | 1 << 4 | 1 << 5), (-1, -2, 3));
+    }
+
+    #[test]
+    fn rotation_ignores_invalid_axis_indices() {
+        // Column 3 doesn't exist; this used to index out of bounds.
+        row_major_rotation((1, 2, 3), 0b1111);
+    }
+
+    #[test]
+    fn rotated_offset_model_is_placed_in_world() {
+        let model = SourceModel {
+            size: (3, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (2, 0, 0), color_index: 0 }],
+            position: Some((10, 0, 0)),
+            // Row 0 = (0, -1, 0), row 1 = (1, 0, 0): x' = -y, y' = x.
+            rotation: Some(17),
+        };
+
+        let save = convert_micro(TestSource::new(vec![model]));
+
+        // The voxel is 1 right of the model's center, which rotates to 1 forward of it.
+        // Y is flipped in Brickadia and bricks are positioned by their center.
+        assert_eq!(save.bricks.len(), 1);
+        assert_eq!(save.bricks[0].position, (10 * 2 + 1, -2 + 1, 1));
+    }
+
+    #[test]
+    fn micro_height_is_independent_of_width() {
+        let model = SourceModel {
+            size: (1, 1, 2),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (0, 0, 1), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let save = convert_micro_sized(TestSource::new(vec![model]), 1, 2);
+
+        assert!(save.bricks.iter().all(|b| matches!(b.size, Size::Procedural(1, 1, 2))));
+
+        let mut positions: Vec<_> = save.bricks.iter().map(|b| b.position).collect();
+        positions.sort();
+        assert_eq!(positions, vec![(1, 1, -2), (1, 1, 2)]);
+    }
+
+    #[test]
+    fn depth_stretches_along_y() {
+        let model = SourceModel {
+            size: (2, 2, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (0, 1, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 1, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let options = || micro_options().width(1).depth(3).height(2);
+
+        let (save, _) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), options()).unwrap();
+        assert!(save.bricks.iter().all(|b| matches!(b.size, Size::Procedural(1, 3, 2))));
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), options().simplify(true)).unwrap();
+        assert_eq!(save.bricks.len(), 1);
+        assert!(matches!(save.bricks[0].size, Size::Procedural(2, 6, 2)));
+    }
+
+    #[test]
+    fn up_axis_remaps_positions() {
+        let model = SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: Some((1, 2, 3)),
+            rotation: None,
+        };
+
+        let position = |up_axis| {
+            let source = TestSource::new(vec![model.clone()]);
+            vox2brs(source, test_save(), micro_options().up_axis(up_axis)).unwrap().0.bricks[0].position
+        };
+
+        // Bricks are centered on their cell, at twice the cell position, with Y flipped.
+        assert_eq!(position(Axis::Z), (3, -3, 7));
+        assert_eq!(position(Axis::Y), (7, -1, 5));
+        assert_eq!(position(Axis::X), (5, -5, 3));
+    }
+
+    #[test]
+    fn mirror_flips_within_model_bounds() {
+        let model = |x| SourceModel {
+            size: (3, 2, 1),
+            voxels: vec![source::SourceVoxel { position: (x, 1, 0), color_index: 0 }],
+            position: None,
+            rotation: None,
+        };
+
+        let mirrored = vox2brs(TestSource::new(vec![model(0)]), test_save(), micro_options().mirror(true, true, false))
+            .unwrap().0;
+
+        // Mirrored in X and Y, the voxel at (0, 1) lands where (2, 0) would.
+        assert_eq!(mirrored.bricks[0].position, convert_micro(TestSource::new(vec![SourceModel {
+            voxels: vec![source::SourceVoxel { position: (2, 0, 0), color_index: 0 }],
+            ..model(0)
+        }])).bricks[0].position);
+    }
+
+    #[test]
+    fn scaled_voxels_simplify_into_one_brick() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().voxel_scale(3).simplify(true))
+            .unwrap();
+
+        assert_eq!(save.bricks.len(), 1);
+        assert!(matches!(save.bricks[0].size, Size::Procedural(6, 3, 3)));
+    }
+
+    #[test]
+    fn skipped_colors_are_left_out() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 2 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().skip_color_indices(vec![2]))
+            .unwrap();
+
+        assert_eq!(save.bricks.len(), 1);
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(0)));
+    }
+
+    #[test]
+    fn center_moves_base_to_origin() {
+        let model = SourceModel {
+            size: (2, 2, 2),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 1, 1), color_index: 0 },
+            ],
+            position: Some((20, -7, 5)),
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().center(true))
+            .unwrap();
+
+        let mut positions: Vec<_> = save.bricks.iter().map(|b| b.position).collect();
+        positions.sort();
+        assert_eq!(positions, vec![(-1, 1, 1), (1, -1, 3)]);
+    }
+
+    #[test]
+    fn stats_count_voxels_and_bricks() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 3 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true).skip_color_indices(vec![3]))
+            .unwrap();
+
+        assert_eq!(stats.voxel_brick_count, 2);
+        assert_eq!(stats.brick_count, 1);
+        assert_eq!(stats.brick_count, save.bricks.len());
+        assert_eq!(stats.ramp_count, 0);
+        assert_eq!(stats.color_count, 4);
+    }
+
+    #[test]
+    fn min_brick_volume_drops_lone_voxels() {
+        let model = SourceModel {
+            size: (5, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (4, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let options = || micro_options().simplify(true);
+
+        let (_, stats) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), options()).unwrap();
+        assert_eq!(stats.brick_count, 2);
+        assert_eq!(stats.dropped_voxel_count, 0);
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), options().min_brick_volume(2)).unwrap();
+        assert_eq!(stats.brick_count, 1);
+        assert_eq!(stats.dropped_voxel_count, 1);
+        assert_eq!(save.bricks[0].size, Size::Procedural(2, 1, 1));
+    }
+
+    #[test]
+    fn model_grouping_gives_models_their_own_owners() {
+        let model = |x| SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: Some((x, 0, 0)),
+            rotation: None,
+        };
+        let source = || TestSource::new(vec![model(0), model(1)]);
+
+        // The two models touch, so without grouping they simplify into one brick.
+        let (_, stats) = vox2brs(source(), test_save(), micro_options().simplify(true)).unwrap();
+        assert_eq!(stats.brick_count, 1);
+
+        let options = micro_options().simplify(true).preserve_model_grouping(true);
+        let (save, stats) = vox2brs(source(), test_save(), options).unwrap();
+        assert_eq!(stats.brick_count, 2);
+        assert_ne!(save.bricks[0].owner_index, save.bricks[1].owner_index);
+        assert_eq!(save.header2.brick_owners.len(), 2);
+        assert!(save.header2.brick_owners.iter().all(|owner| owner.bricks == 1));
+    }
+
+    #[test]
+    fn voxels_outside_the_model_are_left_out() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (7, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let mut warnings = vec![];
+        let (save, stats) = vox2brs_with_progress(TestSource::new(vec![model]), test_save(), micro_options(), &mut |event| {
+            if let ProgressEvent::Warning(message) = event {
+                warnings.push(message);
+            }
+        }).unwrap();
+
+        assert_eq!(stats.voxel_brick_count, 2);
+        assert_eq!(stats.out_of_range_voxel_count, 1);
+        assert_eq!(save.bricks.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert!(save.bricks.iter().all(|brick| brick.position.0.abs() <= 2));
+    }
+
+    #[test]
+    fn z_range_keeps_only_its_layers() {
+        let column = || TestSource::new(vec![SourceModel {
+            size: (1, 1, 5),
+            voxels: (0..5).map(|z| source::SourceVoxel { position: (0, 0, z), color_index: 0 }).collect(),
+            position: None,
+            rotation: None,
+        }]);
+
+        let whole = convert_micro(column());
+        let (band, stats) = vox2brs(column(), test_save(), micro_options().z_range((1, 3))).unwrap();
+
+        assert_eq!(stats.voxel_brick_count, 2);
+        assert_eq!(band.bricks.len(), 2);
+
+        let positions: Vec<_> = band.bricks.iter().map(|brick| brick.position).collect();
+        assert_eq!(positions, vec![whole.bricks[1].position, whole.bricks[2].position]);
+        assert_eq!(estimate_brick_count(&column(), &micro_options().z_range((1, 3))), 2);
+    }
+
+    #[test]
+    fn owner_brick_counts_match_the_bricks() {
+        let model = SourceModel {
+            size: (4, 1, 1),
+            voxels: (0..4).map(|x| source::SourceVoxel { position: (x, 0, 0), color_index: 0 }).collect(),
+            position: None,
+            rotation: None,
+        };
+        let author = Vox2BrsOptions::default().author;
+        let save = || {
+            let mut save = new_save(author.clone(), "owners".into());
+            save.header2.brick_assets = test_save().header2.brick_assets;
+            save
+        };
+
+        let (save_data, stats) = vox2brs(TestSource::new(vec![model.clone()]), save(), micro_options()).unwrap();
+        assert_eq!(stats.brick_count, 4);
+        assert_eq!(save_data.header2.brick_owners.len(), 1);
+        assert_eq!(save_data.header2.brick_owners[0].bricks, 4);
+
+        let extra = User { name: "second".into(), id: Uuid::from_u128(2) };
+        let options = micro_options().extra_owners(vec![extra]);
+        let (save_data, _) = vox2brs(TestSource::new(vec![model.clone()]), save(), options).unwrap();
+        let owners = &save_data.header2.brick_owners;
+        assert_eq!(owners.len(), 2);
+        assert_eq!(owners[1].name, "second");
+        assert_eq!((owners[0].bricks, owners[1].bricks), (2, 2));
+        assert_eq!(save_data.bricks.iter().filter(|brick| brick.owner_index == 2).count(), 2);
+
+        // Bricks are shared from the author, wherever they are among the owners.
+        let mut save = save();
+        let other = User { name: "other".into(), id: Uuid::from_u128(3) };
+        save.header2.brick_owners.insert(0, BrickOwner::from_user_bricks(other, 0));
+
+        let extra = User { name: "second".into(), id: Uuid::from_u128(2) };
+        let (save_data, _) = vox2brs(TestSource::new(vec![model]), save, micro_options().extra_owners(vec![extra])).unwrap();
+        let owners = &save_data.header2.brick_owners;
+        assert_eq!(owners.iter().map(|owner| owner.bricks).collect::<Vec<_>>(), vec![0, 2, 2]);
+    }
+
+    #[test]
+    fn optimized_axis_order_is_reported_and_never_worse() {
+        let mut voxels = vec![(0, 0, 1), (0, 0, 2)];
+        for x in 0..3 {
+            for y in 0..3 {
+                voxels.push((x, y, 0));
+            }
+        }
+        let source = || TestSource::new(vec![SourceModel {
+            size: (3, 3, 3),
+            voxels: voxels.iter().map(|&position| source::SourceVoxel { position, color_index: 0 }).collect(),
+            position: None,
+            rotation: None,
+        }]);
+
+        let mut orders = vec![];
+        let (_, optimized) = vox2brs_with_progress(source(), test_save(), micro_options().simplify(true).optimize_axis_order(true), &mut |event| {
+            if let ProgressEvent::GrowOrderChosen { order } = event {
+                orders.push(order);
+            }
+        }).unwrap();
+        let (_, default) = vox2brs(source(), test_save(), micro_options().simplify(true)).unwrap();
+
+        assert_eq!(orders.len(), 1);
+        assert!(optimized.brick_count <= default.brick_count);
+    }
+
+    #[test]
+    fn similar_colors_merge_within_tolerance() {
+        let voxel = |x, color_index| source::SourceVoxel { position: (x, 0, 0), color_index };
+        let gradient = || {
+            TestSource::new(vec![SourceModel { size: (4, 1, 1), voxels: vec![voxel(0, 1), voxel(1, 0), voxel(2, 1), voxel(3, 2)], position: None, rotation: None }])
+                .with_palette(vec![
+                    Color { r: 200, g: 0, b: 0, a: 255 },
+                    Color { r: 202, g: 2, b: 0, a: 255 },
+                    Color { r: 0, g: 0, b: 200, a: 255 },
+                ])
+        };
+
+        let convert = |tolerance: Option<f32>| vox2brs(gradient(), test_save(), micro_options().simplify(true).color_merge_tolerance(tolerance)).unwrap().0;
+        let color = |save: &SaveData, brick: &Brick| match brick.color {
+            BrickColor::Index(index) => { let c = &save.header2.colors[index as usize]; (c.r, c.g, c.b) },
+            BrickColor::Unique(_) => panic!("expected a palette color"),
+        };
+
+        assert_eq!(convert(None).bricks.len(), 4);
+        assert_eq!(convert(Some(1.0)).bricks.len(), 4);
+
+        // The two reds become one brick with the color most of its voxels had.
+        let save = convert(Some(3.0));
+        let mut colors: Vec<_> = save.bricks.iter().map(|brick| color(&save, brick)).collect();
+        colors.sort();
+        assert_eq!(colors, vec![(0, 0, 200), (202, 2, 0)]);
+    }
+
+    #[test]
+    fn dithering_mixes_quantized_colors() {
+        // A layer going from black to light gray along X.
+        let ramp = || {
+            let voxels = (0..16)
+                .flat_map(|y| (0..16).map(move |x| source::SourceVoxel { position: (x, y, 0), color_index: x }))
+                .collect();
+
+            TestSource::new(vec![SourceModel { size: (16, 16, 1), voxels, position: None, rotation: None }])
+                .with_palette((0..16).map(|i| Color { r: i * 16, g: i * 16, b: i * 16, a: 255 }).collect())
+        };
+
+        let colors = |dither: bool| -> BTreeMap<(i32, i32, i32), u32> {
+            let (save, _) = vox2brs(ramp(), test_save(), micro_options().quantize(2).dither(dither)).unwrap();
+            assert_eq!(save.header2.colors.len(), 2);
+
+            save.bricks
+                .iter()
+                .map(|brick| match brick.color {
+                    BrickColor::Index(index) => (brick.position, index),
+                    BrickColor::Unique(_) => panic!("expected a palette color"),
+                })
+                .collect()
+        };
+
+        let (closest, dithered) = (colors(false), colors(true));
+        assert_eq!(closest.len(), 256);
+        assert_eq!(closest.keys().collect::<Vec<_>>(), dithered.keys().collect::<Vec<_>>());
+
+        // Some voxels take the other color, but each color covers about as much of the layer.
+        let changed = closest.iter().filter(|(position, index)| dithered[position] != **index).count();
+        assert!(changed > 0);
+
+        let lights = |colors: &BTreeMap<(i32, i32, i32), u32>| colors.values().filter(|&&index| index == 1).count() as i32;
+        assert!((lights(&closest) - lights(&dithered)).abs() <= 16);
+    }
+
+    #[test]
+    fn split_saves_add_up_to_the_whole() {
+        // Two colors in a checkered 4x4x2 block.
+        let voxels = (0..32)
+            .map(|i| source::SourceVoxel { position: (i % 4, i / 4 % 4, i / 16), color_index: (i / 2 + i / 4) % 2 })
+            .collect();
+        let source = TestSource::new(vec![SourceModel { size: (4, 4, 2), voxels, position: None, rotation: None }]);
+
+        let (save, _) = vox2brs(source, test_save(), micro_options().simplify(true).center(true)).unwrap();
+        let bricks = |save: &SaveData| -> Vec<_> {
+            let mut bricks: Vec<_> = save.bricks
+                .iter()
+                .map(|brick| (brick.position, format!("{:?} {:?}", brick.size, brick.color)))
+                .collect();
+            bricks.sort();
+            bricks
+        };
+
+        let parts = split_by_color(save.clone());
+        assert_eq!(parts.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![Some(0), Some(1)]);
+
+        for (index, part) in &parts {
+            assert!(part.bricks.iter().all(|brick| matches!(brick.color, BrickColor::Index(i) if Some(i) == *index)));
+            assert_eq!(part.header2.colors.len(), save.header2.colors.len());
+        }
+
+        let mut union: Vec<_> = parts.iter().flat_map(|(_, part)| bricks(part)).collect();
+        union.sort();
+        assert_eq!(union, bricks(&save));
+
+        // Bricks with their own color get a save of their own instead of being lost.
+        let mut with_unique = save.clone();
+        with_unique.bricks[0].color = BrickColor::Unique(Color { r: 1, g: 2, b: 3, a: 255 });
+
+        let parts = split_by_color(with_unique.clone());
+        assert_eq!(parts.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![Some(0), Some(1), None]);
+        assert_eq!(parts[2].1.bricks.len(), 1);
+        assert_eq!(parts.iter().map(|(_, part)| part.bricks.len()).sum::<usize>(), with_unique.bricks.len());
+    }
+
+    #[test]
+    fn studs_go_on_uncovered_tops() {
+        // A column of two voxels, with a single voxel next to it.
+        let voxels: Vec<_> = [(0, 0, 0), (0, 0, 1), (1, 0, 0)]
+            .into_iter()
+            .map(|position| source::SourceVoxel { position, color_index: 0 })
+            .collect();
+        let source = || TestSource::new(vec![SourceModel { size: (2, 1, 2), voxels: voxels.clone(), position: None, rotation: None }]);
+
+        let plate_options = || Vox2BrsOptions::default().mode(BrickOutputMode::Plate).width(1).height(1);
+
+        let (save, _) = vox2brs(source(), test_save(), plate_options()).unwrap();
+        assert!(save.bricks.iter().all(|brick| brick.asset_name_index == 0));
+
+        let (save, _) = vox2brs(source(), test_save(), plate_options().studs(true)).unwrap();
+        let covered: Vec<&Brick> = save.bricks.iter().filter(|brick| brick.asset_name_index == 2).collect();
+
+        assert_eq!(save.bricks.iter().filter(|brick| brick.asset_name_index == 0).count(), 2);
+        assert_eq!(covered.len(), 1);
+
+        // Only the bottom of the column has a plate right on top of it.
+        let (x, y, z) = covered[0].position;
+        assert!(save.bricks.iter().any(|brick| brick.position == (x, y, z + 4)));
+
+        // Microbricks have no studs, so they stay microbricks.
+        let (save, _) = vox2brs(source(), test_save(), micro_options().studs(true)).unwrap();
+        assert!(save.bricks.iter().all(|brick| brick.asset_name_index == 1));
+    }
+
+    #[test]
+    fn max_dimension_fails_or_downsamples() {
+        // An 8 voxel line, with every fourth voxel in another color.
+        let voxels: Vec<_> = (0..8)
+            .map(|x| source::SourceVoxel { position: (x, 0, 0), color_index: if x % 4 == 3 { 1 } else { 0 } })
+            .collect();
+        let source = || TestSource::new(vec![SourceModel { size: (8, 1, 1), voxels: voxels.clone(), position: None, rotation: None }]);
+
+        let error = vox2brs(source(), test_save(), micro_options().max_dimension(4, false)).unwrap_err();
+        assert_eq!(error, Vox2BrsError::ModelTooLarge { size: 8, limit: 4 });
+
+        let (save, stats) = vox2brs(source(), test_save(), micro_options().max_dimension(8, false)).unwrap();
+        assert_eq!((save.bricks.len(), stats.downsample_factor), (8, None));
+
+        let (save, stats) = vox2brs(source(), test_save(), micro_options().max_dimension(3, true)).unwrap();
+        assert_eq!(stats.downsample_factor, Some(3));
+        assert_eq!(save.bricks.len(), 3);
+
+        let (save, _) = vox2brs(source(), test_save(), micro_options().max_dimension(2, true)).unwrap();
+        assert_eq!(save.bricks.len(), 2);
+        assert!(save.bricks.iter().all(|brick| matches!(brick.color, BrickColor::Index(0))));
+    }
+
+    #[test]
+    fn estimate_counts_runs_when_simplifying() {
+        let model = SourceModel {
+            size: (4, 2, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (0, 1, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 1, 0), color_index: 0 },
+                source::SourceVoxel { position: (3, 0, 0), color_index: 1 },
+                source::SourceVoxel { position: (3, 1, 0), color_index: 2 },
+            ],
+            position: None,
+            rotation: None,
+        };
+        let source = TestSource::new(vec![model]);
+
+        assert_eq!(estimate_brick_count(&source, &micro_options()), 6);
+        assert_eq!(estimate_brick_count(&source, &micro_options().voxel_scale(2)), 6 * 8);
+        assert_eq!(estimate_brick_count(&source, &micro_options().simplify(true)), 3);
+        assert_eq!(estimate_brick_count(&source, &micro_options().simplify(true).skip_color_indices(vec![0])), 2);
+
+        let (_, stats) = vox2brs(source, test_save(), micro_options().simplify(true)).unwrap();
+        assert_eq!(stats.brick_count, 3);
+    }
+
+    #[test]
+    fn flatten_keeps_the_top_of_each_column() {
+        let model = SourceModel {
+            size: (2, 1, 3),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (0, 0, 2), color_index: 1 },
+                source::SourceVoxel { position: (1, 0, 1), color_index: 2 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().flatten(Axis::Z)).unwrap();
+
+        assert_eq!(save.bricks.len(), 2);
+        assert!(save.bricks.iter().all(|b| b.position.2 == save.bricks[0].position.2));
+
+        let mut colors: Vec<u32> = save.bricks
+            .iter()
+            .filter_map(|b| match b.color {
+                BrickColor::Index(index) => Some(index),
+                _ => None,
+            })
+            .collect();
+        colors.sort_unstable();
+        assert_eq!(colors, vec![1, 2]);
+    }
+
+    #[test]
+    fn flattened_layer_simplifies() {
+        let model = SourceModel {
+            size: (3, 3, 3),
+            voxels: (0..27)
+                .map(|i| source::SourceVoxel { position: (i % 3, i / 3 % 3, i / 9), color_index: i / 9 })
+                .collect(),
+            position: None,
+            rotation: None,
+        };
+
+        let options = micro_options().flatten(Axis::Z).simplify(true);
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), options).unwrap();
+
+        assert_eq!(stats.brick_count, 1);
+        assert_eq!(save.bricks[0].size, Size::Procedural(3, 3, 1));
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(2)));
+    }
+
+    #[test]
+    fn voxels_face_up_by_default() {
+        let voxels = [(0, 0, 0), (1, 0, 0), (0, 0, 1)]
+            .iter()
+            .map(|&position| source::SourceVoxel { position, color_index: 0 })
+            .collect();
+        let save = convert_micro(TestSource::new(vec![SourceModel { size: (2, 1, 2), voxels, position: None, rotation: None }]));
+
+        assert_eq!(save.bricks.len(), 3);
+        for brick in &save.bricks {
+            assert_eq!(brick.direction, Direction::ZPositive);
+            assert_eq!(brick.rotation, Rotation::Deg0);
+        }
+    }
+
+    #[test]
+    fn voxel_rotations_turn_their_bricks() {
+        let voxel = |x| source::SourceVoxel { position: (x, 0, 0), color_index: 0 };
+        let source = TestSource::new(vec![SourceModel { size: (3, 1, 1), voxels: vec![voxel(0), voxel(1), voxel(2)], position: None, rotation: None }])
+            // Unturned, a quarter turn around Z, and turned so X points up.
+            .with_rotations(vec![4, 17, 9]);
+
+        let (save, _) = vox2brs(source, test_save(), micro_options().depth(2).height(3)).unwrap();
+        let bricks = &save.bricks;
+
+        assert_eq!((&bricks[0].direction, &bricks[0].rotation), (&Direction::ZPositive, &Rotation::Deg0));
+        assert!(matches!(bricks[0].size, Size::Procedural(1, 2, 3)));
+
+        // Turned bricks keep the voxel's footprint.
+        assert_eq!(bricks[1].direction, Direction::ZPositive);
+        assert!(matches!(bricks[1].rotation, Rotation::Deg90 | Rotation::Deg270));
+        assert!(matches!(bricks[1].size, Size::Procedural(2, 1, 3)));
+
+        assert!(matches!(bricks[2].direction, Direction::YPositive | Direction::YNegative));
+        assert!(matches!(bricks[2].size, Size::Procedural(3, 1, 2)));
+
+        for brick in bricks {
+            assert_eq!(brick.position.1, bricks[0].position.1);
+            assert_eq!(brick.position.2, bricks[0].position.2);
+        }
+
+        // Turned bricks still span the voxels' footprint.
+        let (min, max) = brick_bounds(bricks).unwrap();
+        assert_eq!((max.0 - min.0, max.1 - min.1, max.2 - min.2), (6, 4, 6));
+    }
+
+    #[test]
+    fn simplified_voxels_face_up() {
+        let voxel = |x| source::SourceVoxel { position: (x, 0, 0), color_index: 0 };
+        let source = || {
+            TestSource::new(vec![SourceModel { size: (3, 1, 1), voxels: vec![voxel(0), voxel(1), voxel(2)], position: None, rotation: None }])
+                .with_rotations(vec![4, 17, 9])
+        };
+
+        let options = || micro_options().depth(2).height(3);
+        let (turned, _) = vox2brs(source(), test_save(), options()).unwrap();
+        let (simplified, _) = vox2brs(source(), test_save(), options().simplify(true)).unwrap();
+
+        // The three voxels merge into one upright brick over the same space.
+        assert_eq!(simplified.bricks.len(), 1);
+        assert_eq!(simplified.bricks[0].direction, Direction::ZPositive);
+        assert!(matches!(simplified.bricks[0].size, Size::Procedural(3, 2, 3)));
+        assert_eq!(brick_bounds(&simplified.bricks), brick_bounds(&turned.bricks));
+    }
+
+    #[test]
+    fn brick_sizes_are_checked_at_their_limits() {
+        let source = || TestSource::new(vec![SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: None,
+            rotation: None,
+        }]);
+        let convert = |options: Vox2BrsOptions| vox2brs(source(), test_save(), options).map(|(save, _)| save);
+
+        assert_eq!(BrickOutputMode::Brick.max_size(), (200, 166));
+
+        let save = convert(Vox2BrsOptions::default().width(200).height(166)).unwrap();
+        assert_eq!(save.bricks[0].size, Size::Procedural(1000, 1000, 996));
+
+        let too_wide = convert(Vox2BrsOptions::default().width(201)).unwrap_err();
+        assert_eq!(too_wide, Vox2BrsError::InvalidSize { width: 201, depth: 201, height: 3 });
+
+        // Would overflow a u32 when multiplied out.
+        assert!(matches!(convert(Vox2BrsOptions::default().width(u32::MAX)), Err(Vox2BrsError::InvalidSize { .. })));
+        assert!(matches!(convert(micro_options().height(0)), Err(Vox2BrsError::InvalidSize { .. })));
+
+        assert!(convert(micro_options().width(500).voxel_scale(2)).is_ok());
+        assert!(matches!(convert(micro_options().width(501).voxel_scale(2)), Err(Vox2BrsError::InvalidSize { .. })));
+    }
+
+    #[test]
+    fn grid_align_puts_the_corner_on_a_stud() {
+        let model = |x, z| SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: Some((x, 3, z)),
+            rotation: None,
+        };
+        let source = || TestSource::new(vec![model(7, 5), model(12, 6)]);
+        let on_grid = |(x, y, z): (i32, i32, i32)| (x.rem_euclid(10), y.rem_euclid(10), z.rem_euclid(4));
+
+        // Microbricks are a fifth of a stud, so the build starts off the grid.
+        let (save, _) = vox2brs(source(), test_save(), micro_options()).unwrap();
+        assert_ne!(on_grid(brick_bounds(&save.bricks).unwrap().0), (0, 0, 0));
+
+        let (save, _) = vox2brs(source(), test_save(), micro_options().grid_align(true)).unwrap();
+        assert_eq!(on_grid(brick_bounds(&save.bricks).unwrap().0), (0, 0, 0));
+
+        // Bricks stay on the microbrick grid, where a 1x1x1 microbrick's center is odd.
+        for brick in &save.bricks {
+            let (x, y, z) = brick.position;
+            assert_eq!((x.rem_euclid(2), y.rem_euclid(2), z.rem_euclid(2)), (1, 1, 1));
+        }
+    }
+
+    #[test]
+    fn global_offset_moves_every_model() {
+        let model = |x| SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: Some((x, 0, 0)),
+            rotation: None,
+        };
+        let source = || TestSource::new(vec![model(0), model(4)]);
+
+        let positions = |options: Vox2BrsOptions| -> Vec<(i32, i32, i32)> {
+            vox2brs(source(), test_save(), options).unwrap().0.bricks.iter().map(|b| b.position).collect()
+        };
+
+        let moved = positions(micro_options().global_offset((3, -2, 5)));
+        let expected: Vec<_> = positions(micro_options())
+            .into_iter()
+            .map(|(x, y, z)| (x + 3 * 2, y + 2 * 2, z + 5 * 2))
+            .collect();
+
+        // Y is flipped in Brickadia.
+        assert_eq!(moved, expected);
+
+        let centered = positions(micro_options().center(true));
+        assert_eq!(positions(micro_options().global_offset((3, -2, 5)).center(true)), centered);
+    }
+
+    #[test]
+    fn collision_applies_to_every_brick() {
+        let model = SourceModel {
+            size: (3, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (2, 0, 0), color_index: 1 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), micro_options()).unwrap();
+        assert!(save.bricks.iter().all(|b| b.collision.player && b.collision.weapon));
+
+        let options = micro_options().collision(BrickCollision::PassThrough).baseplate((1, 1), 0);
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), options).unwrap();
+        assert_eq!(save.bricks.len(), 3);
+
+        for brick in &save.bricks {
+            assert!(!brick.collision.player && !brick.collision.weapon);
+            assert!(brick.collision.interaction && brick.collision.tool);
+        }
+    }
+
+    #[test]
+    fn merge_colors_ignores_color_boundaries() {
+        let model = SourceModel {
+            size: (4, 1, 1),
+            voxels: (0..4).map(|x| source::SourceVoxel { position: (x, 0, 0), color_index: x % 2 + 1 }).collect(),
+            position: None,
+            rotation: None,
+        };
+
+        let options = || micro_options().simplify(true);
+
+        let (_, stats) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), options()).unwrap();
+        assert_eq!(stats.brick_count, 4);
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), options().merge_colors(true)).unwrap();
+        assert_eq!(stats.brick_count, 1);
+        assert_eq!(stats.merged_color_savings, 3);
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(1)));
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), options().merge_colors(true).merged_color(3)).unwrap();
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(3)));
+    }
+
+    #[test]
+    fn chunks_are_stitched_back_together() {
+        let mut voxels = vec![];
+        for x in 0..10 {
+            for y in 0..3 {
+                for z in 0..2 {
+                    voxels.push(source::SourceVoxel { position: (x, y, z), color_index: 0 });
+                }
+            }
+        }
+
+        let model = SourceModel { size: (10, 3, 2), voxels, position: None, rotation: None };
+
+        let (whole, _) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), micro_options().simplify(true)).unwrap();
+        let (chunked, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true).chunk_size(4)).unwrap();
+
+        assert_eq!(chunked.bricks.len(), 1);
+        assert_eq!(chunked.bricks[0].position, whole.bricks[0].position);
+        assert_eq!(chunked.bricks[0].size, whole.bricks[0].size);
+    }
+
+    #[test]
+    fn sparse_models_skip_empty_space() {
+        // Three 500 voxel arms meeting at a corner, filling a tiny part of their 500^3 bounding box.
+        // A dense grid would take half a gigabyte.
+        let mut voxels = vec![];
+        for i in 0..500 {
+            voxels.push(source::SourceVoxel { position: (0, 0, i), color_index: 0 });
+            if i > 0 {
+                voxels.push(source::SourceVoxel { position: (i, 0, 0), color_index: 1 });
+                voxels.push(source::SourceVoxel { position: (0, i, 0), color_index: 2 });
+            }
+        }
+
+        let model = SourceModel { size: (500, 500, 500), voxels, position: None, rotation: None };
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true))
+            .unwrap();
+
+        let volume: u32 = save.bricks.iter().map(|b| match b.size {
+            Size::Procedural(x, y, z) => x * y * z,
+            _ => 0,
+        }).sum();
+
+        assert_eq!(stats.voxel_brick_count, 1498);
+        assert_eq!(volume, 1498);
+        assert!(save.bricks.len() < 30);
+        assert_eq!(stats.largest_dense_grid, 0);
+    }
+
+    #[test]
+    fn chunks_bound_memory_on_sparse_models() {
+        // Two voxels at opposite corners. The whole bounding box would be a grid of
+        // about 8 billion cells, while each chunk only needs 64^3.
+        let model = SourceModel {
+            size: (2000, 2000, 2000),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1999, 1999, 1999), color_index: 1 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true).chunk_size(64))
+            .unwrap();
+
+        assert_eq!(stats.brick_count, 2);
+        assert_eq!(save.bricks.len(), 2);
+        assert!(stats.largest_dense_grid <= 64 * 64 * 64);
+
+        // Rampifying needs dense grids, which the chunks keep small.
+        let model = SourceModel {
+            size: (2000, 1, 2000),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1999, 0, 1999), color_index: 1 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let options = Vox2BrsOptions::default().mode(BrickOutputMode::Plate).rampify(true).chunk_size(64);
+        let (_, stats) = vox2brs(TestSource::new(vec![model]), test_save(), options).unwrap();
+
+        assert!(stats.largest_dense_grid > 0);
+        assert!(stats.largest_dense_grid <= 64 * 64 * 64);
+    }
+
+    #[test]
+    fn surface_only_drops_interior_voxels() {
+        let mut voxels = vec![];
+        for x in 0..5 {
+            for y in 0..5 {
+                for z in 0..5 {
+                    voxels.push(source::SourceVoxel { position: (x, y, z), color_index: 0 });
+                }
+            }
+        }
+
+        let model = SourceModel { size: (5, 5, 5), voxels, position: None, rotation: None };
+        let source = TestSource::new(vec![model]);
+
+        assert_eq!(estimate_brick_count(&source, &micro_options().surface_only(true)), 125 - 27);
+
+        let (save, stats) = vox2brs(source, test_save(), micro_options().surface_only(true)).unwrap();
+
+        assert_eq!(stats.interior_voxel_count, 3 * 3 * 3);
+        assert_eq!(stats.voxel_brick_count, 125 - 27);
+        assert_eq!(save.bricks.len(), 125 - 27);
+    }
+
+    #[test]
+    fn reference_palette_replaces_source_colors() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 3 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let reference = vec![
+            Color { r: 255, g: 255, b: 255, a: 255 },
+            Color { r: 200, g: 20, b: 20, a: 255 },
+            Color { r: 20, g: 20, b: 200, a: 255 },
+        ];
+
+        let (save, stats) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().reference_palette(reference))
+            .unwrap();
+
+        // Every test color is red, so both voxels use the reference's red.
+        assert_eq!(save.header2.colors.len(), 3);
+        assert_eq!(stats.color_count, 3);
+        assert!(save.bricks.iter().all(|brick| matches!(brick.color, BrickColor::Index(1))));
+    }
+
+    #[test]
+    fn gamma_correction_applies_to_the_reference_palette() {
+        let gray = TestSource::new(vec![SourceModel { size: (1, 1, 1), voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }], position: None, rotation: None }])
+            .with_palette(vec![Color { r: 128, g: 128, b: 128, a: 255 }]);
+
+        let reference = vec![Color { r: 0, g: 0, b: 0, a: 255 }, Color { r: 128, g: 128, b: 128, a: 255 }];
+        let (save, _) = vox2brs(gray, test_save(), micro_options().gamma_correct(true).reference_palette(reference)).unwrap();
+
+        // The corrected gray is closer to black than to the uncorrected gray, but matches the corrected one.
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(1)));
+        assert_eq!(save.header2.colors[1].r, gamma_correction(128, 128, 128).0);
+    }
+
+    #[test]
+    fn quantize_reduces_palette() {
+        let convert = || vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), micro_options().quantize(4)).unwrap();
+
+        let (save, stats) = convert();
+        assert!(save.header2.colors.len() <= 4);
+        assert_eq!(stats.color_count, save.header2.colors.len());
+        assert!(save.bricks.iter().all(|brick| matches!(brick.color, BrickColor::Index(index) if (index as usize) < stats.color_count)));
+
+        // The same model always quantizes to the same palette and colors.
+        let (again, _) = convert();
+        assert_eq!(again.header2.colors, save.header2.colors);
+        assert!(again.bricks.iter().zip(&save.bricks).all(|(a, b)| a.color == b.color));
+    }
+
+    #[test]
+    fn unique_colors_survive_simplifying() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 2 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 2 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true).use_unique_colors(true))
+            .unwrap();
+
+        assert!(save.header2.colors.is_empty());
+        assert_eq!(save.bricks.len(), 1);
+
+        match &save.bricks[0].color {
+            BrickColor::Unique(color) => assert_eq!((color.r, color.g, color.b, color.a), (255, 0, 0, 255)),
+            other => panic!("expected a unique color, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn simplify_keeps_colors_past_255() {
+        let model = SourceModel {
+            size: (3, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 300 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 300 },
+                // 300 wrapped into a byte.
+                source::SourceVoxel { position: (2, 0, 0), color_index: 44 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true))
+            .unwrap();
+
+        let mut colors: Vec<u32> = save.bricks
+            .iter()
+            .filter_map(|brick| match brick.color {
+                BrickColor::Index(index) => Some(index),
+                _ => None,
+            })
+            .collect();
+        colors.sort_unstable();
+
+        assert_eq!(colors, vec![44, 300]);
+    }
+
+    #[test]
+    fn smooth_edges_adds_wedges_on_steps() {
+        let model = SourceModel {
+            size: (3, 1, 2),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (0, 0, 1), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (2, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let convert = |options: Vox2BrsOptions| vox2brs(TestSource::new(vec![model.clone()]), test_save(), options).unwrap().1;
+
+        let simplified = convert(micro_options().simplify(true));
+        let smoothed = convert(micro_options().smooth_edges(true));
+
+        assert_eq!(smoothed.wedge_count, 1);
+        assert_eq!(smoothed.brick_count - smoothed.wedge_count, simplified.brick_count);
+    }
+
+    #[test]
+    fn welding_after_rampify_saves_bricks() {
+        let heights = [3, 4, 5, 6, 6, 6, 5, 4, 3];
+
+        // An S of another color buried under the slope, which filling height first covers in three
+        // bricks and growing length first in two once Y is flipped. The ramps on top never reach it.
+        let buried = [(3, 1), (3, 2), (4, 2), (4, 3)];
+        let mut voxels = vec![];
+
+        for (run, &height) in heights.iter().enumerate() {
+            for across in 0..5 {
+                for up in 0..height {
+                    let color_index = if up == 1 && buried.contains(&(run as u32, across)) { 1 } else { 0 };
+                    voxels.push(source::SourceVoxel { position: (run as u32, across, up), color_index });
+                }
+            }
+        }
+
+        let model = SourceModel { size: (9, 5, 6), voxels, position: None, rotation: None };
+        let convert = |weld: bool| {
+            let options = Vox2BrsOptions::default().mode(BrickOutputMode::Plate).width(1).height(1).rampify(true).weld(weld);
+            vox2brs(TestSource::new(vec![model.clone()]), test_save(), options).unwrap()
+        };
+
+        let (_, plain) = convert(false);
+        let (save, welded) = convert(true);
+
+        assert_eq!(plain.weld_savings, 0);
+        assert!(welded.weld_savings > 0);
+        assert_eq!(welded.ramp_count, plain.ramp_count);
+        assert_eq!(welded.brick_count + welded.weld_savings, plain.brick_count);
+
+        // Welding refills bricks without covering any more or less of the model.
+        let volume = |save: &SaveData| -> u32 {
+            save.bricks
+                .iter()
+                .filter(|brick| brick.asset_name_index == 0)
+                .map(|brick| match brick.size {
+                    Size::Procedural(w, l, h) => w * l * h,
+                    _ => 0,
+                })
+                .sum()
+        };
+        assert_eq!(volume(&save), volume(&convert(false).0));
+    }
+
+    /// Ramp count when rampifying `voxels` as 1x1 plates.
+    fn rampified_count(size: (u32, u32, u32), voxels: Vec<source::SourceVoxel>, rampify_options: RampifyOptions) -> usize {
+        let model = SourceModel { size, voxels, position: None, rotation: None };
+
+        let options = Vox2BrsOptions::default()
+            .mode(BrickOutputMode::Plate)
+            .width(1)
+            .height(1)
+            .rampify(true)
+            .rampify_options(rampify_options);
+
+        vox2brs(TestSource::new(vec![model]), test_save(), options).unwrap().1.ramp_count
+    }
+
+    #[test]
+    fn wall_ramps_match_floor_ramps_on_their_side() {
+        // Steps up and back down along the run, so flipping Y doesn't change the shape.
+        let heights = [1, 2, 3, 4, 3, 2, 1];
+        let width = 3;
+
+        let mut floor = vec![];
+        let mut wall = vec![];
+
+        for (run, &height) in heights.iter().enumerate() {
+            for across in 0..width {
+                for up in 0..height {
+                    floor.push(source::SourceVoxel { position: (run as u32, across, up), color_index: 0 });
+                    // The same L-shaped steps, rising along X instead of Z.
+                    wall.push(source::SourceVoxel { position: (up, run as u32, across), color_index: 0 });
+                }
+            }
+        }
+
+        let floors_only = RampifyOptions { walls_x: false, walls_y: false, ..Default::default() };
+        let walls_only = RampifyOptions { floors: false, ceilings: false, walls_x: true, walls_y: false, ..Default::default() };
+        let nothing = RampifyOptions { floors: false, ceilings: false, walls_x: false, walls_y: false, ..Default::default() };
+
+        let floor_ramps = rampified_count((7, width, 4), floor, floors_only);
+        assert!(floor_ramps > 0);
+
+        assert_eq!(rampified_count((4, 7, width), wall.clone(), walls_only), floor_ramps);
+        assert_eq!(rampified_count((4, 7, width), wall, nothing), 0);
+    }
+
+    #[test]
+    fn baseplate_sits_under_the_build() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().baseplate((1, 1), 2))
+            .unwrap();
+
+        assert_eq!(save.bricks.len(), 3);
+
+        let plate = &save.bricks[2];
+        assert!(matches!(plate.color, BrickColor::Index(2)));
+        assert_eq!(plate.asset_name_index, 0);
+
+        // The voxels span -2..2 by 0..2 units, with their base at Z 0.
+        assert_eq!(plate.position, (0, 1, -2));
+        assert!(matches!(plate.size, Size::Procedural(3, 2, 2)));
+    }
+
+    #[test]
+    fn color_materials_are_applied() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 3 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().color_material(3, BrickMaterial::Metallic))
+            .unwrap();
+
+        let metallic = save.header2.materials.iter().position(|m| m == "BMC_Metallic").unwrap() as u32;
+        assert_ne!(save.bricks[0].material_index, metallic);
+        assert_eq!(save.bricks[1].material_index, metallic);
+    }
+
+    #[test]
+    fn repeated_conversions_are_identical() {
+        let options = || micro_options()
+            .simplify(true)
+            .quantize(8)
+            .auto_materials(true)
+            .alpha_threshold(128)
+            .color_material(2, BrickMaterial::Metallic)
+            .color_material(5, BrickMaterial::Glow)
+            .color_material(7, BrickMaterial::Glass);
+
+        let convert = || vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), options()).unwrap().0;
+        let (first, second) = (convert(), convert());
+
+        assert_eq!(first.header2.colors, second.header2.colors);
+        assert_eq!(first.header2.materials, second.header2.materials);
+
+        let bricks = |save: &SaveData| -> Vec<_> {
+            save.bricks.iter().map(|b| (b.position, b.color.clone(), b.material_index)).collect()
+        };
+        assert_eq!(bricks(&first), bricks(&second));
+    }
+
+    fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(children);
+    }
+
+    /// A .vox file with two voxels, the second of which uses a metal material.
+    fn metal_vox() -> Vec<u8> {
+        let mut size = vec![];
+        for value in [2i32, 1, 1] {
+            size.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut xyzi = 2i32.to_le_bytes().to_vec();
+        xyzi.extend_from_slice(&[0, 0, 0, 1]);
+        xyzi.extend_from_slice(&[1, 0, 0, 2]);
+
+        let mut matl = 2i32.to_le_bytes().to_vec();
+        matl.extend_from_slice(&1i32.to_le_bytes());
+        for string in ["_type", "_metal"] {
+            matl.extend_from_slice(&(string.len() as i32).to_le_bytes());
+            matl.extend_from_slice(string.as_bytes());
+        }
+
+        let mut children = vec![];
+        push_chunk(&mut children, b"SIZE", &size, &[]);
+        push_chunk(&mut children, b"XYZI", &xyzi, &[]);
+        push_chunk(&mut children, b"MATL", &matl, &[]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[], &children);
+        bytes
+    }
+
+    /// Three voxels in a row. The second color's palette alpha is low, and the third is opaque
+    /// but has see-through glass.
+    fn translucent_vox() -> Vec<u8> {
+        let mut size = vec![];
+        for value in [3i32, 1, 1] {
+            size.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut xyzi = 3i32.to_le_bytes().to_vec();
+        for x in 0..3u8 {
+            xyzi.extend_from_slice(&[x, 0, 0, x + 1]);
+        }
+
+        let mut rgba = vec![];
+        for alpha in [255u8, 20, 255] {
+            rgba.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+        rgba.resize(256 * 4, 255);
+
+        let mut matl = 3i32.to_le_bytes().to_vec();
+        matl.extend_from_slice(&2i32.to_le_bytes());
+        for string in ["_type", "_glass", "_trans", "0.9"] {
+            matl.extend_from_slice(&(string.len() as i32).to_le_bytes());
+            matl.extend_from_slice(string.as_bytes());
+        }
+
+        let mut children = vec![];
+        push_chunk(&mut children, b"SIZE", &size, &[]);
+        push_chunk(&mut children, b"XYZI", &xyzi, &[]);
+        push_chunk(&mut children, b"RGBA", &rgba, &[]);
+        push_chunk(&mut children, b"MATL", &matl, &[]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[], &children);
+        bytes
+    }
+
+    #[test]
+    fn palette_alpha_threshold_skips_transparent_voxels() {
+        let bytes = translucent_vox();
+
+        let (save, _) = vox2brs_from_bytes(&bytes, test_save(), micro_options()).unwrap();
+        assert_eq!(save.bricks.len(), 3);
+
+        let (save, _) = vox2brs_from_bytes(&bytes, test_save(), micro_options().palette_alpha_threshold(64)).unwrap();
+        assert_eq!(save.bricks.len(), 1);
+        assert!(matches!(save.bricks[0].color, BrickColor::Index(0)));
+    }
+
+    #[test]
+    fn alpha_threshold_reads_material_alpha() {
+        let (save, _) = vox2brs_from_bytes(&translucent_vox(), test_save(), micro_options().alpha_threshold(200)).unwrap();
+        let glass = save.header2.materials.iter().position(|material| material == "BMC_Glass").unwrap() as u32;
+        let alpha = |brick: &Brick| match brick.color {
+            BrickColor::Index(index) => save.header2.colors[index as usize].a,
+            BrickColor::Unique(_) => panic!("expected a palette color"),
+        };
+
+        // The glass color is see-through even though its palette alpha isn't.
+        assert_eq!(alpha(&save.bricks[0]), 255);
+        assert!(alpha(&save.bricks[2]) < 200);
+        assert_ne!(save.bricks[0].material_index, glass);
+        assert_eq!(save.bricks[1].material_index, glass);
+        assert_eq!(save.bricks[2].material_index, glass);
+    }
+
+    #[test]
+    fn auto_materials_make_metal_metallic() {
+        let (save, _) = vox2brs_from_bytes(&metal_vox(), test_save(), micro_options().auto_materials(true))
+            .unwrap();
+
+        let metallic = save.header2.materials.iter().position(|m| m == BrickMaterial::Metallic.asset_name()).unwrap() as u32;
+
+        for brick in &save.bricks {
+            let is_metal = matches!(brick.color, BrickColor::Index(1));
+            assert_eq!(brick.material_index == metallic, is_metal);
+        }
+        assert!(save.bricks.iter().any(|brick| brick.material_index == metallic));
+    }
+
+    #[test]
+    fn assets_resolve_by_name() {
+        let model = SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: None,
+            rotation: None,
+        };
+
+        let assets = vec!["PB_DefaultWedge".to_string(), "PB_CustomMicro".to_string()];
+        let options = || micro_options().brick_assets(assets.clone());
+
+        let (save, _) = vox2brs(TestSource::new(vec![model.clone()]), test_save(), options().assets(0, "PB_CustomMicro", 0, 0, 0))
+            .unwrap();
+        assert_eq!(save.header2.brick_assets, assets);
+        assert_eq!(save.bricks[0].asset_name_index, 1);
+
+        let result = vox2brs(TestSource::new(vec![model]), test_save(), options().assets(0, "PB_Missing", 0, 0, 0));
+        assert_eq!(result.err(), Some(Vox2BrsError::UnknownAsset("PB_Missing".into())));
+    }
+
+    #[test]
+    fn cancel_flag_stops_conversion() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = || micro_options().simplify(true).cancel(cancel.clone());
+
+        assert!(vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), options()).is_ok());
+
+        cancel.store(true, Ordering::Relaxed);
+        let result = vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), options());
+        assert_eq!(result.err(), Some(Vox2BrsError::Cancelled));
+    }
+
+    #[test]
+    fn converts_from_bytes() {
+        let (save, stats) = vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), micro_options()).unwrap();
+        assert!(stats.voxel_brick_count > 0);
+        assert_eq!(save.bricks.len(), stats.voxel_brick_count);
+
+        let result = vox2brs_from_bytes(b"VOX \x96\0\0\0MAIN", test_save(), micro_options());
+        assert!(matches!(result, Err(Vox2BrsError::InvalidFile(_))));
+    }
+
+    #[test]
+    fn stage_timings_fit_in_elapsed() {
+        let options = micro_options().simplify(true).rampify(true);
+        let (_, stats) = vox2brs_from_bytes(include_bytes!("../../test.vox"), test_save(), options).unwrap();
+        let timings = stats.timings;
+
+        let total = timings.colors + timings.models + timings.simplify + timings.rampify + timings.offset + timings.finish;
+        assert!(total <= stats.elapsed);
+        assert!(timings.simplify > Duration::ZERO);
+        assert!(timings.rampify > Duration::ZERO);
+    }
+
+    #[test]
+    fn converts_file_to_file() {
+        let input = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test.vox"));
+        let output = std::env::temp_dir().join(format!("vox2brs_convert_file_{}.brs", std::process::id()));
+
+        let stats = convert_file(input, &output, &micro_options()).unwrap();
+        let written = brickadia::read::SaveReader::new(File::open(&output).unwrap()).unwrap().read_all().unwrap();
+        let _ = std::fs::remove_file(&output);
+
+        assert_eq!(written.bricks.len(), stats.brick_count);
+        assert_eq!(written.header1.author.name, "vox2brs");
+        assert!(written.header1.description.starts_with("Converted from test.vox"));
+
+        let missing = convert_file(&input.with_extension("qb"), &output, &micro_options());
+        assert!(matches!(missing, Err(Vox2BrsError::InvalidFile(_))));
+    }
+
+    #[test]
+    fn empty_models_are_rejected() {
+        let mut size = vec![];
+        for value in [4i32, 4, 4] {
+            size.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut children = vec![];
+        push_chunk(&mut children, b"SIZE", &size, &[]);
+        push_chunk(&mut children, b"XYZI", &0i32.to_le_bytes(), &[]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[], &children);
+
+        for options in [micro_options(), micro_options().simplify(true), micro_options().rampify(true)] {
+            let result = vox2brs_from_bytes(&bytes, test_save(), options);
+            assert_eq!(result.err(), Some(Vox2BrsError::EmptyModel));
+        }
+
+        // Skipping every color leaves nothing too.
+        let result = vox2brs_from_bytes(&metal_vox(), test_save(), micro_options().simplify(true).skip_color_indices(vec![0, 1]));
+        assert_eq!(result.err(), Some(Vox2BrsError::EmptyModel));
+    }
+
+    #[test]
+    fn max_bricks_limits_output() {
+        let model = SourceModel {
+            size: (2, 1, 1),
+            voxels: vec![
+                source::SourceVoxel { position: (0, 0, 0), color_index: 0 },
+                source::SourceVoxel { position: (1, 0, 0), color_index: 0 },
+            ],
+            position: None,
+            rotation: None,
+        };
+
+        let result = vox2brs(TestSource::new(vec![model.clone()]), test_save(), micro_options().max_bricks(1));
+        assert_eq!(result.err(), Some(Vox2BrsError::TooManyBricks { produced: 2, limit: 1 }));
+
+        // Simplifying merges both voxels into one brick, which fits.
+        let result = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true).max_bricks(1));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn far_offset_model_is_rejected() {
+        let model = SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            // Doubled into Brickadia units, this wraps around to a negative position.
+            position: Some((1 << 30, 0, 0)),
+            rotation: None,
+        };
+
+        let result = vox2brs(TestSource::new(vec![model.clone()]), test_save(), micro_options());
+        assert_eq!(result.err(), Some(Vox2BrsError::GridTooLarge));
+
+        let result = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().simplify(true));
+        assert_eq!(result.err(), Some(Vox2BrsError::GridTooLarge));
+    }
+
+    #[test]
+    fn world_rotation_turns_bricks_around_z() {
+        let model = SourceModel {
+            size: (1, 1, 1),
+            voxels: vec![source::SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+            position: Some((3, 0, 0)),
+            rotation: None,
+        };
+
+        let (save, _) = vox2brs(TestSource::new(vec![model]), test_save(), micro_options().world_rotation(1))
+            .unwrap();
+
+        // (7, 1) turns a quarter to (-1, 7).
+        assert_eq!(save.bricks[0].position, (-1, 7, 1));
+        assert!(matches!(save.bricks[0].rotation, Rotation::Deg90));
+
+        let mut bricks = save.bricks.clone();
+        rotate_bricks(&mut bricks, 3);
+        assert_eq!(bricks[0].position, (7, 1, 1));
+        assert!(matches!(bricks[0].rotation, Rotation::Deg0));
+    }
+
+    #[test]
+    fn old_save_versions_reject_glow() {
+        let mut save = test_save();
+        assert_eq!(check_save_version(&save, 8), Ok(()));
+
+        save.bricks.push(Brick { material_intensity: 10, ..Brick::default() });
+
+        assert_eq!(check_save_version(&save, 9), Ok(()));
+        assert!(matches!(
+            check_save_version(&save, 8),
+            Err(Vox2BrsError::UnsupportedBySaveVersion { version: 8, required: 9, .. })
+        ));
+        assert_eq!(check_save_version(&save, 11), Err(Vox2BrsError::UnknownSaveVersion(11)));
+    }
+
+    #[test]
+    fn exempt_colors_skip_gamma_correction() {
+        let voxel = |x, color_index| source::SourceVoxel { position: (x, 0, 0), color_index };
+        let gray = TestSource::new(vec![SourceModel { size: (2, 1, 1), voxels: vec![voxel(0, 0), voxel(1, 1)], position: None, rotation: None }])
+            .with_palette(vec![Color { r: 128, g: 128, b: 128, a: 255 }; 2]);
+
+        let options = micro_options().gamma_correct(true).no_gamma_for_indices(vec![1]);
+        let (save, _) = vox2brs(gray, test_save(), options).unwrap();
+        let colors = &save.header2.colors;
+
+        let corrected = gamma_correction(128, 128, 128);
+        assert_ne!(corrected, (128, 128, 128));
+        assert_eq!((colors[0].r, colors[0].g, colors[0].b), corrected);
+        assert_eq!((colors[1].r, colors[1].g, colors[1].b), (128, 128, 128));
+    }
+
+    #[test]
+    fn gamma_correction_keeps_extremes() {
+        assert_eq!(gamma_correction(255, 0, 0), (255, 0, 0));
+        assert_eq!(gamma_correction(0, 255, 0), (0, 255, 0));
+        assert_eq!(gamma_correction(255, 255, 255), (255, 255, 255));
+        assert_eq!(gamma_correction(0, 0, 0), (0, 0, 0));
+    }
+
     #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+    fn gamma_correction_darkens_midtones() {
+        let (r, g, b) = gamma_correction(128, 64, 200);
+        assert!(r < 128);
+        assert!(g < 64);
+        assert!(b < 200);
     }
 }
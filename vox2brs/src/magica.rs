@@ -0,0 +1,643 @@
+//! Reads MagicaVoxel `.vox` files from memory, including the chunks `create_vox` skips over.
+
+use std::collections::HashMap;
+use std::io;
+use brickadia::save::Color;
+use crate::source::{MaterialKind, SourceMaterial, SourceModel, SourceVoxel};
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct ChunkReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ChunkReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.offset.checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid_data("Unexpected end of .vox file."))?;
+
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Err(invalid_data("Negative length in .vox file."));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let count = self.len()?;
+        let mut dict = HashMap::new();
+
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+
+        Ok(dict)
+    }
+}
+
+/// Calls `f` with the id and content of every chunk in the file, children included.
+fn for_each_chunk(bytes: &[u8], mut f: impl FnMut(&[u8], &[u8]) -> io::Result<()>) -> io::Result<()> {
+    let mut reader = ChunkReader { bytes, offset: 0 };
+
+    if reader.take(4)? != b"VOX " {
+        return Err(invalid_data("Not a .vox file."));
+    }
+
+    let _version = reader.i32()?;
+
+    while reader.offset < bytes.len() {
+        let id = reader.take(4)?;
+        let content_len = reader.len()?;
+        let _children_len = reader.len()?;
+        let content = reader.take(content_len)?;
+
+        // Children directly follow their parent's content, so reading them in sequence
+        // visits the whole tree.
+        f(id, content)?;
+    }
+
+    Ok(())
+}
+
+fn parse_f32(dict: &HashMap<String, String>, key: &str) -> f32 {
+    dict.get(key).and_then(|v| v.parse().ok()).unwrap_or(0.0)
+}
+
+/// Reads the material of every palette entry that has one.
+pub fn read_materials(bytes: &[u8]) -> io::Result<Vec<SourceMaterial>> {
+    let mut materials = vec![];
+
+    for_each_chunk(bytes, |id, content| {
+        if id != b"MATL" {
+            return Ok(());
+        }
+
+        let mut reader = ChunkReader { bytes: content, offset: 0 };
+        let material_id = reader.i32()?;
+        let dict = reader.dict()?;
+
+        // Material ids match MagicaVoxel's 1-based color indices.
+        if material_id < 1 {
+            return Ok(());
+        }
+
+        let kind = match dict.get("_type").map(|t| t.as_str()) {
+            Some("_metal") => MaterialKind::Metal,
+            Some("_glass") => MaterialKind::Glass,
+            Some("_emit") => MaterialKind::Emit,
+            Some("_blend") => MaterialKind::Blend,
+            Some("_media") => MaterialKind::Media,
+            _ => MaterialKind::Diffuse,
+        };
+
+        materials.push(SourceMaterial {
+            color_index: material_id as u32 - 1,
+            kind,
+            emit: parse_f32(&dict, "_emit"),
+            flux: parse_f32(&dict, "_flux"),
+            // Glass stores how see-through it is as `_trans`, newer files also save `_alpha`.
+            alpha: match dict.get("_alpha") {
+                Some(_) => parse_f32(&dict, "_alpha"),
+                None => 1.0 - parse_f32(&dict, "_trans"),
+            },
+        });
+
+        Ok(())
+    })?;
+
+    Ok(materials)
+}
+
+/// MagicaVoxel's palette for files without an `RGBA` chunk, in the same order as a saved palette.
+///
+/// It's a 6x6x6 color cube without black, followed by ramps of red, green, blue and gray.
+pub fn default_palette() -> Vec<Color> {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = vec![];
+
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                if (r, g, b) != (0, 0, 0) {
+                    palette.push(Color { r, g, b, a: 255 });
+                }
+            }
+        }
+    }
+
+    for (r, g, b) in [(1, 0, 0), (0, 1, 0), (0, 0, 1), (1, 1, 1)] {
+        for v in RAMP {
+            palette.push(Color { r: v * r, g: v * g, b: v * b, a: 255 });
+        }
+    }
+
+    // The last saved entry is color index 0, which voxels never use.
+    palette.push(Color { r: 0, g: 0, b: 0, a: 0 });
+    palette
+}
+
+/// Reads the palette, where entry `i` is the color of MagicaVoxel's color index `i + 1`.
+pub fn read_palette(bytes: &[u8]) -> io::Result<Vec<Color>> {
+    let mut palette = None;
+
+    for_each_chunk(bytes, |id, content| {
+        if id == b"RGBA" {
+            palette = Some(
+                content
+                    .chunks_exact(4)
+                    .map(|c| Color { r: c[0], g: c[1], b: c[2], a: c[3] })
+                    .collect(),
+            );
+        }
+
+        Ok(())
+    })?;
+
+    Ok(palette.unwrap_or_else(default_palette))
+}
+
+/// Reads every model's size and voxels, in the order they appear in the file.
+///
+/// Models aren't placed; their positions and rotations come from `read_instances`.
+pub fn read_models(bytes: &[u8]) -> io::Result<Vec<SourceModel>> {
+    let mut models = vec![];
+    let mut size = None;
+
+    for_each_chunk(bytes, |id, content| {
+        let mut reader = ChunkReader { bytes: content, offset: 0 };
+
+        match id {
+            b"SIZE" => {
+                let mut dimension = || -> io::Result<u32> { Ok(reader.len()? as u32) };
+                size = Some((dimension()?, dimension()?, dimension()?));
+            }
+            b"XYZI" => {
+                let size = size.take().ok_or_else(|| invalid_data("XYZI chunk without a SIZE chunk."))?;
+                let count = reader.len()?;
+
+                let voxels = reader
+                    .take(count.checked_mul(4).ok_or_else(|| invalid_data("Too many voxels."))?)?
+                    .chunks_exact(4)
+                    // Color index 0 is empty space.
+                    .filter(|v| v[3] != 0)
+                    .map(|v| SourceVoxel {
+                        position: (v[0] as u32, v[1] as u32, v[2] as u32),
+                        color_index: v[3] as u32 - 1,
+                    })
+                    .collect();
+
+                models.push(SourceModel { size, voxels, position: None, rotation: None });
+            }
+            _ => {}
+        }
+
+        Ok(())
+    })?;
+
+    Ok(models)
+}
+
+/// A 3x3 row-major rotation matrix, made up of only 0, 1 and -1.
+pub type Rotation = [[i32; 3]; 3];
+
+pub const IDENTITY: Rotation = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// Decodes MagicaVoxel's packed rotation byte into a matrix.
+pub fn decode_rotation(rotation_byte: u8) -> Rotation {
+    let r1_i = (rotation_byte & 0b11) as usize;
+    let r2_i = ((rotation_byte >> 2) & 0b11) as usize;
+    let r3_i = 3usize.saturating_sub(r1_i + r2_i).min(2);
+
+    let sign = |bit: u8| if (rotation_byte >> bit) & 0b1 == 1 { -1 } else { 1 };
+
+    let mut m = [[0; 3]; 3];
+    m[0][r1_i.min(2)] = sign(4);
+    m[1][r2_i.min(2)] = sign(5);
+    m[2][r3_i] = sign(6);
+    m
+}
+
+/// Packs a rotation matrix back into MagicaVoxel's rotation byte.
+pub fn encode_rotation(m: &Rotation) -> u8 {
+    let index = |row: &[i32; 3]| row.iter().position(|&v| v != 0).unwrap_or(0) as u8;
+    let sign = |row: &[i32; 3]| if row.iter().any(|&v| v < 0) { 1 } else { 0 };
+
+    index(&m[0])
+        | index(&m[1]) << 2
+        | sign(&m[0]) << 4
+        | sign(&m[1]) << 5
+        | sign(&m[2]) << 6
+}
+
+pub fn multiply_rotation(a: &Rotation, b: &Rotation) -> Rotation {
+    let mut m = [[0; 3]; 3];
+
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    m
+}
+
+pub fn rotate(m: &Rotation, v: (i32, i32, i32)) -> (i32, i32, i32) {
+    let v = [v.0, v.1, v.2];
+    let row = |r: &[i32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+    (row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+/// A placement of a model in the scene, with every parent transform applied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ModelInstance {
+    /// Index of the model, in the order the models appear in the file.
+    pub model_id: usize,
+    pub position: (i32, i32, i32),
+    pub rotation: u8,
+}
+
+enum SceneNode {
+    Transform { child: i32, translation: (i32, i32, i32), rotation: Rotation },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+fn parse_translation(value: &str) -> (i32, i32, i32) {
+    let mut parts = value.split_whitespace().map(|v| v.parse().unwrap_or(0));
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+fn read_scene(bytes: &[u8]) -> io::Result<HashMap<i32, SceneNode>> {
+    let mut nodes = HashMap::new();
+
+    for_each_chunk(bytes, |id, content| {
+        let mut reader = ChunkReader { bytes: content, offset: 0 };
+
+        let node = match id {
+            b"nTRN" => {
+                let node_id = reader.i32()?;
+                let _attributes = reader.dict()?;
+                let child = reader.i32()?;
+                let _reserved = reader.i32()?;
+                let _layer = reader.i32()?;
+                let frame_count = reader.len()?;
+
+                let mut translation = (0, 0, 0);
+                let mut rotation = IDENTITY;
+
+                // Only the first animation frame is used.
+                for frame in 0..frame_count {
+                    let dict = reader.dict()?;
+                    if frame == 0 {
+                        if let Some(t) = dict.get("_t") {
+                            translation = parse_translation(t);
+                        }
+                        if let Some(r) = dict.get("_r").and_then(|r| r.parse().ok()) {
+                            rotation = decode_rotation(r);
+                        }
+                    }
+                }
+
+                (node_id, SceneNode::Transform { child, translation, rotation })
+            },
+            b"nGRP" => {
+                let node_id = reader.i32()?;
+                let _attributes = reader.dict()?;
+                let child_count = reader.len()?;
+                let children = (0..child_count).map(|_| reader.i32()).collect::<io::Result<_>>()?;
+
+                (node_id, SceneNode::Group { children })
+            },
+            b"nSHP" => {
+                let node_id = reader.i32()?;
+                let _attributes = reader.dict()?;
+                let model_count = reader.len()?;
+
+                let mut models = vec![];
+                for _ in 0..model_count {
+                    models.push(reader.i32()?);
+                    let _model_attributes = reader.dict()?;
+                }
+
+                (node_id, SceneNode::Shape { models })
+            },
+            _ => return Ok(()),
+        };
+
+        nodes.insert(node.0, node.1);
+        Ok(())
+    })?;
+
+    Ok(nodes)
+}
+
+/// Reads the names given to nodes in MagicaVoxel's outliner, in file order.
+pub fn read_names(bytes: &[u8]) -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = vec![];
+
+    for_each_chunk(bytes, |id, content| {
+        if id != b"nTRN" && id != b"nGRP" && id != b"nSHP" {
+            return Ok(());
+        }
+
+        let mut reader = ChunkReader { bytes: content, offset: 0 };
+        let _node_id = reader.i32()?;
+        let attributes = reader.dict()?;
+
+        if let Some(name) = attributes.get("_name") {
+            if !name.is_empty() && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(names)
+}
+
+/// Reads the notes written beside palette rows, in row order, leaving out empty ones.
+///
+/// Camera (`rCAM`) and render (`rOBJ`) chunks only hold renderer settings, so they're not read.
+pub fn read_notes(bytes: &[u8]) -> io::Result<Vec<String>> {
+    let mut notes = vec![];
+
+    for_each_chunk(bytes, |id, content| {
+        if id != b"NOTE" {
+            return Ok(());
+        }
+
+        let mut reader = ChunkReader { bytes: content, offset: 0 };
+        let count = reader.len()?;
+
+        for _ in 0..count {
+            let note = reader.string()?;
+
+            if !note.trim().is_empty() {
+                notes.push(note.trim().to_string());
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(notes)
+}
+
+/// Walks the scene graph, accumulating the transforms of every model instance.
+pub fn read_instances(bytes: &[u8]) -> io::Result<Vec<ModelInstance>> {
+    let nodes = read_scene(bytes)?;
+    let mut instances = vec![];
+
+    // (node, translation, rotation, depth)
+    let mut stack = vec![(0, (0, 0, 0), IDENTITY, 0)];
+
+    while let Some((node_id, translation, rotation, depth)) = stack.pop() {
+        // Guard against cycles in malformed files.
+        if depth > nodes.len() {
+            return Err(invalid_data("Cyclic scene graph in .vox file."));
+        }
+
+        match nodes.get(&node_id) {
+            Some(SceneNode::Transform { child, translation: t, rotation: r }) => {
+                let t = rotate(&rotation, *t);
+                let translation = (translation.0 + t.0, translation.1 + t.1, translation.2 + t.2);
+                stack.push((*child, translation, multiply_rotation(&rotation, r), depth + 1));
+            },
+            Some(SceneNode::Group { children }) => {
+                for child in children.iter().rev() {
+                    stack.push((*child, translation, rotation, depth + 1));
+                }
+            },
+            Some(SceneNode::Shape { models }) => {
+                for &model_id in models {
+                    instances.push(ModelInstance {
+                        model_id: model_id.max(0) as usize,
+                        position: translation,
+                        rotation: encode_rotation(&rotation),
+                    });
+                }
+            },
+            None => {},
+        }
+    }
+
+    Ok(instances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_string(bytes: &mut Vec<u8>, s: &str) {
+        bytes.extend_from_slice(&(s.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.extend_from_slice(content);
+    }
+
+    #[test]
+    fn reads_emissive_material() {
+        let mut matl = vec![];
+        matl.extend_from_slice(&5i32.to_le_bytes());
+        matl.extend_from_slice(&2i32.to_le_bytes());
+        push_string(&mut matl, "_type");
+        push_string(&mut matl, "_emit");
+        push_string(&mut matl, "_emit");
+        push_string(&mut matl, "0.5");
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[]);
+        push_chunk(&mut bytes, b"MATL", &matl);
+
+        let materials = read_materials(&bytes).unwrap();
+
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].color_index, 4);
+        assert_eq!(materials[0].kind, MaterialKind::Emit);
+        assert_eq!(materials[0].emit, 0.5);
+        assert_eq!(materials[0].alpha, 1.0);
+    }
+
+    #[test]
+    fn reads_palette_notes() {
+        let mut note = vec![];
+        note.extend_from_slice(&3i32.to_le_bytes());
+        push_string(&mut note, "walls");
+        push_string(&mut note, "");
+        push_string(&mut note, "roof tiles ");
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[]);
+        push_chunk(&mut bytes, b"rCAM", &0i32.to_le_bytes());
+        push_chunk(&mut bytes, b"NOTE", &note);
+
+        assert_eq!(read_notes(&bytes).unwrap(), vec!["walls", "roof tiles"]);
+
+        let file = crate::source::MagicaVoxelFile::read(&bytes).unwrap();
+        let description = crate::source::default_description(std::path::Path::new("house.vox"), &file);
+        assert_eq!(description, "Converted from house.vox. Notes: walls; roof tiles.");
+    }
+
+    #[test]
+    fn reads_test_file() {
+        let bytes = include_bytes!("../../test.vox");
+        assert!(read_materials(bytes).is_ok());
+        assert_eq!(read_instances(bytes).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reads_models_and_palette_like_create_vox() {
+        let bytes = include_bytes!("../../test.vox");
+        let vox = create_vox::VoxFile::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../test.vox"));
+
+        let rgba = |palette: Vec<Color>| -> Vec<_> { palette.iter().map(|c| (c.r, c.g, c.b, c.a)).collect() };
+        assert_eq!(rgba(read_palette(bytes).unwrap()), rgba(crate::source::VoxelSource::palette(&vox)));
+
+        let models = read_models(bytes).unwrap();
+        let expected = crate::source::VoxelSource::models(&vox);
+        assert_eq!(models.len(), expected.len());
+
+        for (model, expected) in models.iter().zip(&expected) {
+            assert_eq!(model.size, expected.size);
+            assert_eq!(model.voxels, expected.voxels);
+        }
+    }
+
+    #[test]
+    fn default_palette_has_every_entry() {
+        let palette: Vec<_> = default_palette().iter().map(|c| (c.r, c.g, c.b, c.a)).collect();
+
+        assert_eq!(palette.len(), 256);
+        assert_eq!(palette[0], (255, 255, 255, 255));
+        // The red ramp starts after the 215 cube colors.
+        assert_eq!(palette[215], (0xee, 0, 0, 255));
+        assert_eq!(palette[254], (0x11, 0x11, 0x11, 255));
+    }
+
+    #[test]
+    fn rotation_round_trips() {
+        for byte in 0..128u8 {
+            let m = decode_rotation(byte);
+            let is_permutation = (0..3).all(|j| m.iter().filter(|row| row[j] != 0).count() == 1);
+            if is_permutation {
+                assert_eq!(decode_rotation(encode_rotation(&m)), m);
+            }
+        }
+        assert_eq!(decode_rotation(4), IDENTITY);
+    }
+
+    fn push_dict(bytes: &mut Vec<u8>, entries: &[(&str, &str)]) {
+        bytes.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+        for (key, value) in entries {
+            push_string(bytes, key);
+            push_string(bytes, value);
+        }
+    }
+
+    fn push_i32s(bytes: &mut Vec<u8>, values: &[i32]) {
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn transform(node_id: i32, child: i32, frame: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = vec![];
+        push_i32s(&mut bytes, &[node_id]);
+        push_dict(&mut bytes, &[]);
+        push_i32s(&mut bytes, &[child, -1, 0, 1]);
+        push_dict(&mut bytes, frame);
+        bytes
+    }
+
+    fn group(node_id: i32, children: &[i32]) -> Vec<u8> {
+        let mut bytes = vec![];
+        push_i32s(&mut bytes, &[node_id]);
+        push_dict(&mut bytes, &[]);
+        push_i32s(&mut bytes, &[children.len() as i32]);
+        push_i32s(&mut bytes, children);
+        bytes
+    }
+
+    #[test]
+    fn reads_node_names() {
+        let mut named = vec![];
+        push_i32s(&mut named, &[1]);
+        push_dict(&mut named, &[("_name", "castle")]);
+        push_i32s(&mut named, &[2, -1, 0, 1]);
+        push_dict(&mut named, &[]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[]);
+        push_chunk(&mut bytes, b"nTRN", &transform(0, 1, &[]));
+        push_chunk(&mut bytes, b"nTRN", &named);
+
+        assert_eq!(read_names(&bytes).unwrap(), vec!["castle".to_string()]);
+    }
+
+    #[test]
+    fn accumulates_nested_transforms() {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        push_chunk(&mut bytes, b"MAIN", &[]);
+
+        // root -> group -> transform (rotated 90 degrees around Z, moved) -> group -> transform -> shape
+        push_chunk(&mut bytes, b"nTRN", &transform(0, 1, &[]));
+        push_chunk(&mut bytes, b"nGRP", &group(1, &[2]));
+        // Row 0 = (0, -1, 0), row 1 = (1, 0, 0): x' = -y, y' = x.
+        push_chunk(&mut bytes, b"nTRN", &transform(2, 3, &[("_t", "10 0 0"), ("_r", "17")]));
+        push_chunk(&mut bytes, b"nGRP", &group(3, &[4]));
+        push_chunk(&mut bytes, b"nTRN", &transform(4, 5, &[("_t", "3 0 2")]));
+
+        let mut shape = vec![];
+        push_i32s(&mut shape, &[5]);
+        push_dict(&mut shape, &[]);
+        push_i32s(&mut shape, &[1, 0]);
+        push_dict(&mut shape, &[]);
+        push_chunk(&mut bytes, b"nSHP", &shape);
+
+        let instances = read_instances(&bytes).unwrap();
+
+        assert_eq!(instances, vec![ModelInstance {
+            model_id: 0,
+            position: (10, 3, 2),
+            rotation: 17,
+        }]);
+    }
+}
@@ -0,0 +1,433 @@
+//! Matching colors against a fixed palette, and reducing palettes to fewer colors.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use brickadia::save::Color;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+/// Converts an sRGB channel into linear space.
+fn to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts an sRGB color into CIELAB, where distances are closer to how different colors look.
+pub fn to_lab(color: &Color) -> [f32; 3] {
+    let (r, g, b) = (to_linear(color.r), to_linear(color.g), to_linear(color.b));
+
+    // Linear sRGB to XYZ, relative to the D65 white point.
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Finds the index of the color in `palette` that looks closest to `color`, ignoring alpha.
+///
+/// Returns `None` if the palette is empty. Ties go to the earliest color.
+pub fn nearest_color(palette: &[Color], color: &Color) -> Option<usize> {
+    let target = to_lab(color);
+
+    let distance = |candidate: &Color| -> f32 {
+        let lab = to_lab(candidate);
+        (0..3).map(|i| (lab[i] - target[i]).powi(2)).sum()
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| (index, distance(candidate)))
+        .fold(None, |best: Option<(usize, f32)>, (index, d)| match best {
+            Some((_, best_d)) if best_d <= d => best,
+            _ => Some((index, d)),
+        })
+        .map(|(index, _)| index)
+}
+
+/// Value of `color`'s red, green or blue channel.
+fn channel(color: &Color, channel: usize) -> u8 {
+    match channel {
+        0 => color.r,
+        1 => color.g,
+        _ => color.b,
+    }
+}
+
+/// The red, green or blue channel that spans the widest range in `colors`, with its range.
+fn widest_channel(colors: &[Color]) -> (usize, u8) {
+    (0..3)
+        .map(|c| {
+            let values = colors.iter().map(|color| channel(color, c));
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (c, range)
+        })
+        .fold((0, 0), |widest, (c, range)| if range > widest.1 { (c, range) } else { widest })
+}
+
+fn average(colors: &[Color]) -> Color {
+    let len = colors.len().max(1) as u32;
+    let sum = |f: fn(&Color) -> u8| (colors.iter().map(|color| f(color) as u32).sum::<u32>() + len / 2) / len;
+
+    Color {
+        r: sum(|c| c.r) as u8,
+        g: sum(|c| c.g) as u8,
+        b: sum(|c| c.b) as u8,
+        a: sum(|c| c.a) as u8,
+    }
+}
+
+/// Reduces `colors` to at most `count` colors with median cut.
+///
+/// The colors are split into boxes, halving the box with the widest channel at its median
+/// until there are `count` boxes, and each box becomes the average of its colors.
+/// Fewer colors are returned when there aren't enough different colors to split.
+pub fn median_cut(colors: &[Color], count: usize) -> Vec<Color> {
+    if colors.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    let mut boxes = vec![colors.to_vec()];
+
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| (index, widest_channel(colors)))
+            .filter(|(_, (_, range))| *range > 0)
+            .fold(None, |widest: Option<(usize, (usize, u8))>, candidate| match widest {
+                Some((_, (_, range))) if range >= (candidate.1).1 => widest,
+                _ => Some(candidate),
+            });
+
+        let (index, (c, _)) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+
+        let mut lower = boxes.remove(index);
+        lower.sort_by_key(|color| channel(color, c));
+        let upper = lower.split_off(lower.len() / 2);
+
+        boxes.insert(index, upper);
+        boxes.insert(index, lower);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+/// Picks a color in `palette` for each voxel with Floyd–Steinberg dithering, so gradients
+/// reduced to a few colors don't turn into flat bands.
+///
+/// Each Z layer is dithered on its own, going along X and then Y. The difference between a
+/// voxel's color and the one it gets is spread over its neighbours later in the layer, so
+/// on average an area keeps its color. Empty cells take none of it. Returns an index into
+/// `palette` for each voxel, or an empty list if the palette is empty.
+pub fn dither(voxels: &[((u32, u32, u32), Color)], palette: &[Color]) -> Vec<usize> {
+    if palette.is_empty() {
+        return vec![];
+    }
+
+    let mut order: Vec<usize> = (0..voxels.len()).collect();
+    order.sort_by_key(|&index| {
+        let (x, y, z) = voxels[index].0;
+        (z, y, x)
+    });
+
+    let cells: HashMap<(u32, u32, u32), usize> = voxels
+        .iter()
+        .enumerate()
+        .map(|(index, (position, _))| (*position, index))
+        .collect();
+
+    let mut errors = vec![[0.0f32; 3]; voxels.len()];
+    let mut picks = vec![0; voxels.len()];
+
+    for index in order {
+        let ((x, y, z), color) = &voxels[index];
+        let error = errors[index];
+        let clamp = |value: u8, error: f32| (value as f32 + error).round().clamp(0.0, 255.0) as u8;
+
+        let wanted = Color {
+            r: clamp(color.r, error[0]),
+            g: clamp(color.g, error[1]),
+            b: clamp(color.b, error[2]),
+            a: color.a,
+        };
+
+        let pick = nearest_color(palette, &wanted).unwrap_or(0);
+        picks[index] = pick;
+
+        let picked = &palette[pick];
+        let remaining = [
+            wanted.r as f32 - picked.r as f32,
+            wanted.g as f32 - picked.g as f32,
+            wanted.b as f32 - picked.b as f32,
+        ];
+
+        let neighbours = [((1, 0), 7.0), ((-1, 1), 3.0), ((0, 1), 5.0), ((1, 1), 1.0)];
+
+        for ((dx, dy), weight) in neighbours {
+            let position = match (u32::try_from(*x as i64 + dx), u32::try_from(*y as i64 + dy)) {
+                (Ok(x), Ok(y)) => (x, y, *z),
+                _ => continue,
+            };
+
+            if let Some(&neighbour) = cells.get(&position) {
+                for c in 0..3 {
+                    errors[neighbour][c] += remaining[c] * weight / 16.0;
+                }
+            }
+        }
+    }
+
+    picks
+}
+
+/// Straight line distance between two colors' red, green and blue channels.
+pub fn rgb_distance(a: &Color, b: &Color) -> f32 {
+    let difference = |a: u8, b: u8| (a as f32 - b as f32).powi(2);
+    (difference(a.r, b.r) + difference(a.g, b.g) + difference(a.b, b.b)).sqrt()
+}
+
+/// Groups colors within `tolerance` RGB distance of each other, returning the index of each
+/// color's group representative, or the color's own index if it's alone.
+///
+/// Colors are grouped in the order of `order`, usually most used first, each joining the first
+/// representative close enough or becoming one, so no color strays further than `tolerance`
+/// from its representative. Colors of different alpha are never grouped, and colors missing
+/// from `order` are left alone.
+pub fn group_similar_colors(colors: &[Color], order: &[usize], tolerance: f32) -> Vec<usize> {
+    let mut groups: Vec<usize> = (0..colors.len()).collect();
+    let mut representatives: Vec<usize> = vec![];
+
+    for &index in order {
+        let color = match colors.get(index) {
+            Some(color) => color,
+            None => continue,
+        };
+
+        let group = representatives
+            .iter()
+            .copied()
+            .find(|&representative| {
+                let other = &colors[representative];
+                other.a == color.a && rgb_distance(other, color) <= tolerance
+            });
+
+        match group {
+            Some(representative) => groups[index] = representative,
+            None => representatives.push(index),
+        }
+    }
+
+    groups
+}
+
+/// Parses a palette with one hex color per line, as `RRGGBB` or `RRGGBBAA` with an optional `#`.
+///
+/// Blank lines and lines starting with `//` are skipped, as is anything after a color's `//`.
+pub fn parse_hex_palette(text: &str) -> Result<Vec<Color>, String> {
+    let mut palette = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let line = match line.find("//") {
+            Some(comment) if comment > 0 => line[..comment].trim_end(),
+            _ => line,
+        };
+
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        let color = parse_hex_color(line)
+            .ok_or_else(|| format!("Line {}: expected a hex color like #FF8800, got {}", number + 1, line))?;
+
+        palette.push(color);
+    }
+
+    Ok(palette)
+}
+
+/// Parses a hex color, as `RRGGBB` or `RRGGBBAA` with an optional `#`.
+pub fn parse_hex_color(text: &str) -> Option<Color> {
+    let hex = text.trim_start_matches('#');
+
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+
+    Some(Color {
+        r: channel(0)?,
+        g: channel(1)?,
+        b: channel(2)?,
+        a: if hex.len() == 8 { channel(3)? } else { 255 },
+    })
+}
+
+/// Formats `color` as `#RRGGBBAA`.
+pub fn hex_color(color: &Color) -> String {
+    format!("#{:02X}{:02X}{:02X}{:02X}", color.r, color.g, color.b, color.a)
+}
+
+/// Lists `colors` one per line as `#RRGGBBAA // index`, which `parse_hex_palette` reads back.
+pub fn palette_text(colors: &[Color]) -> String {
+    colors
+        .iter()
+        .enumerate()
+        .map(|(index, color)| format!("{} // {}\n", hex_color(color), index))
+        .collect()
+}
+
+/// Writes `colors` as a PNG strip one pixel high, with each color at the x of its index.
+pub fn write_palette_png(colors: &[Color], mut writer: impl Write) -> io::Result<()> {
+    if colors.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the palette has no colors"));
+    }
+
+    writer.write_all(&[137, 80, 78, 71, 13, 10, 26, 10])?;
+
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| -> io::Result<()> {
+        let mut crc = Crc::new();
+        crc.update(kind);
+        crc.update(data);
+
+        writer.write_all(&(data.len() as u32).to_be_bytes())?;
+        writer.write_all(kind)?;
+        writer.write_all(data)?;
+        writer.write_all(&crc.sum().to_be_bytes())
+    };
+
+    // Width, height, 8 bits per channel, RGBA, and the only compression, filter and interlace methods.
+    let mut header = (colors.len() as u32).to_be_bytes().to_vec();
+    header.extend_from_slice(&[0, 0, 0, 1, 8, 6, 0, 0, 0]);
+
+    // The single row starts with its filter type, none.
+    let mut pixels = ZlibEncoder::new(vec![], Compression::default());
+    pixels.write_all(&[0])?;
+    for color in colors {
+        pixels.write_all(&[color.r, color.g, color.b, color.a])?;
+    }
+
+    chunk(b"IHDR", &header)?;
+    chunk(b"IDAT", &pixels.finish()?)?;
+    chunk(b"IEND", &[])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    #[test]
+    fn picks_nearest_color() {
+        let palette = [rgb(0, 0, 0), rgb(255, 255, 255), rgb(200, 30, 30), rgb(30, 30, 200)];
+
+        assert_eq!(nearest_color(&palette, &rgb(250, 0, 0)), Some(2));
+        assert_eq!(nearest_color(&palette, &rgb(0, 0, 120)), Some(3));
+        assert_eq!(nearest_color(&palette, &rgb(230, 230, 230)), Some(1));
+        assert_eq!(nearest_color(&palette, &rgb(20, 20, 20)), Some(0));
+        assert_eq!(nearest_color(&[], &rgb(20, 20, 20)), None);
+    }
+
+    #[test]
+    fn lab_lightness_spans_black_to_white() {
+        assert!((to_lab(&rgb(255, 255, 255))[0] - 100.0).abs() < 0.1);
+        assert!(to_lab(&rgb(0, 0, 0))[0].abs() < 0.1);
+    }
+
+    #[test]
+    fn dithering_mixes_colors_across_a_gradient() {
+        let palette = [rgb(0, 0, 0), rgb(255, 255, 255)];
+        let voxels: Vec<((u32, u32, u32), Color)> = (0..16)
+            .flat_map(|y| (0..16).map(move |x| ((x, y, 0), rgb(x as u8 * 16, x as u8 * 16, x as u8 * 16))))
+            .collect();
+
+        let nearest: Vec<usize> = voxels.iter().map(|(_, color)| nearest_color(&palette, color).unwrap()).collect();
+        let dithered = dither(&voxels, &palette);
+
+        assert_eq!(dithered.len(), voxels.len());
+        assert_ne!(dithered, nearest);
+
+        // Nearest colors split the layer into a black and a white half, while dithering mixes
+        // them, keeping about as much of each overall.
+        let middle_column = |picks: &[usize]| (0..16).map(|y| picks[y * 16 + 8]).collect::<HashSet<_>>().len();
+        assert_eq!(middle_column(&nearest), 1);
+        assert_eq!(middle_column(&dithered), 2);
+
+        let whites = |picks: &[usize]| picks.iter().filter(|&&pick| pick == 1).count() as i32;
+        assert!((whites(&dithered) - whites(&nearest)).abs() <= 16);
+        assert!(dither(&voxels, &[]).is_empty());
+    }
+
+    #[test]
+    fn groups_colors_within_tolerance() {
+        let colors = [rgb(200, 0, 0), rgb(0, 0, 200), rgb(203, 4, 0), rgb(206, 8, 0), Color { r: 200, g: 0, b: 0, a: 100 }];
+        let order = [1, 0, 2, 3, 4];
+
+        // 203,4,0 is 5 away from 200,0,0, and 206,8,0 is 10 away, so it starts a group of its own.
+        assert_eq!(group_similar_colors(&colors, &order, 5.0), vec![0, 1, 0, 3, 4]);
+        assert_eq!(group_similar_colors(&colors, &order, 0.0), vec![0, 1, 2, 3, 4]);
+        assert_eq!(group_similar_colors(&colors, &[3, 2, 0], 5.0), vec![0, 1, 3, 3, 4]);
+    }
+
+    #[test]
+    fn median_cut_reduces_to_count() {
+        let colors: Vec<_> = (0..64).map(|i| rgb(i * 4, 255 - i * 4, (i % 8) * 32)).collect();
+
+        let reduced = median_cut(&colors, 8);
+        assert_eq!(reduced.len(), 8);
+        assert_eq!(median_cut(&colors, 8), reduced);
+
+        // Two distinct colors can't be split into more than two boxes.
+        let two = [rgb(255, 0, 0), rgb(255, 0, 0), rgb(0, 0, 255)];
+        assert_eq!(median_cut(&two, 4), vec![rgb(0, 0, 255), rgb(255, 0, 0)]);
+        assert!(median_cut(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn parses_hex_palette() {
+        let palette = parse_hex_palette("// Reds\n#FF0000\n\n80000080\n").unwrap();
+        let palette: Vec<_> = palette.iter().map(|c| (c.r, c.g, c.b, c.a)).collect();
+
+        assert_eq!(palette, vec![(255, 0, 0, 255), (128, 0, 0, 128)]);
+        assert!(parse_hex_palette("#FF00").is_err());
+        assert!(parse_hex_palette("#GG0000").is_err());
+    }
+
+    #[test]
+    fn written_palettes_read_back() {
+        let colors = [rgb(255, 136, 0), Color { r: 1, g: 2, b: 3, a: 100 }];
+        let text = palette_text(&colors);
+
+        assert_eq!(text, "#FF8800FF // 0\n#01020364 // 1\n");
+        assert_eq!(parse_hex_palette(&text).unwrap(), colors.to_vec());
+
+        let mut png = vec![];
+        write_palette_png(&colors, &mut png).unwrap();
+
+        assert_eq!(png[..8], [137, 80, 78, 71, 13, 10, 26, 10]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        assert!(write_palette_png(&[], io::sink()).is_err());
+    }
+}
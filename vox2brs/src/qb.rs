@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use brickadia::save::Color;
+use crate::source::{SourceCopy, SourceModel, SourceVoxel, VoxelSource};
+
+const CODE_FLAG: u32 = 2;
+const NEXT_SLICE_FLAG: u32 = 6;
+
+/// A Qubicle Binary (`.qb`) file.
+///
+/// Qubicle stores true colors per voxel, so a palette is built from the unique colors.
+/// Matrices are Y-up, and are converted to MagicaVoxel's Z-up coordinates.
+#[derive(Debug, Clone)]
+pub struct QbFile {
+    pub palette: Vec<Color>,
+    pub models: Vec<SourceModel>,
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl QbFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let _version = read_u32(reader)?;
+        let color_format = read_u32(reader)?;
+        let _z_axis_orientation = read_u32(reader)?;
+        let compressed = read_u32(reader)? != 0;
+        let _visibility_mask_encoded = read_u32(reader)?;
+        let matrix_count = read_u32(reader)?;
+
+        let mut palette = vec![];
+        let mut color_indices: HashMap<u32, u32> = HashMap::new();
+        let mut models = vec![];
+
+        for _ in 0..matrix_count {
+            let mut name_len = [0; 1];
+            reader.read_exact(&mut name_len)?;
+            let mut name = vec![0; name_len[0] as usize];
+            reader.read_exact(&mut name)?;
+
+            let size = (read_u32(reader)?, read_u32(reader)?, read_u32(reader)?);
+            let position = (read_i32(reader)?, read_i32(reader)?, read_i32(reader)?);
+
+            if size.0 == 0 {
+                return Err(invalid_data("Matrix has no width."));
+            }
+
+            let slice_len = size.0
+                .checked_mul(size.1)
+                .ok_or_else(|| invalid_data("Matrix is too large."))?;
+
+            let mut voxels = vec![];
+
+            let mut push_voxel = |index: u32, z: u32, data: u32| {
+                let [c0, c1, c2, a] = data.to_le_bytes();
+
+                // Zero alpha (or a zero visibility mask) is an empty voxel.
+                if a == 0 {
+                    return;
+                }
+
+                let color_index = *color_indices.entry(data | 0xFF000000).or_insert_with(|| {
+                    let (r, b) = if color_format == 0 { (c0, c2) } else { (c2, c0) };
+                    palette.push(Color { r, g: c1, b, a: 255 });
+                    palette.len() as u32 - 1
+                });
+
+                let x = index % size.0;
+                let y = index / size.0;
+
+                voxels.push(SourceVoxel {
+                    position: (x, z, y),
+                    color_index,
+                });
+            };
+
+            for z in 0..size.2 {
+                if compressed {
+                    let mut index = 0;
+
+                    loop {
+                        let data = read_u32(reader)?;
+
+                        if data == NEXT_SLICE_FLAG {
+                            break;
+                        }
+
+                        if data == CODE_FLAG {
+                            let count = read_u32(reader)?;
+                            let data = read_u32(reader)?;
+
+                            if index as u64 + count as u64 > slice_len as u64 {
+                                return Err(invalid_data("Voxels run past the end of the slice."));
+                            }
+
+                            for _ in 0..count {
+                                push_voxel(index, z, data);
+                                index += 1;
+                            }
+                        } else {
+                            if index >= slice_len {
+                                return Err(invalid_data("Voxels run past the end of the slice."));
+                            }
+
+                            push_voxel(index, z, data);
+                            index += 1;
+                        }
+                    }
+                } else {
+                    for index in 0..slice_len {
+                        let data = read_u32(reader)?;
+                        push_voxel(index, z, data);
+                    }
+                }
+            }
+
+            // Matrices are placed by their corner, and models by their center.
+            let center = |corner: i32, len: u32| {
+                corner.checked_add((len / 2) as i32).ok_or_else(|| invalid_data("Matrix is too far from the origin."))
+            };
+
+            models.push(SourceModel {
+                size: (size.0, size.2, size.1),
+                voxels,
+                position: Some((
+                    center(position.0, size.0)?,
+                    center(position.2, size.2)?,
+                    center(position.1, size.1)?,
+                )),
+                rotation: None,
+            });
+        }
+
+        Ok(Self { palette, models })
+    }
+}
+
+impl VoxelSource for QbFile {
+    fn palette(&self) -> Vec<Color> {
+        self.palette.clone()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        self.models.clone()
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Bytes of a compressed file with one matrix of the given size at `x`, before its voxel data.
+    fn compressed_header(size: (u32, u32, u32), x: i32) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Header: version, RGBA, left handed, compressed, no visibility mask, 1 matrix.
+        for value in [0x0101_0000, 0, 0, 1, 0, 1] {
+            push_u32(&mut bytes, value);
+        }
+
+        bytes.push(1);
+        bytes.push(b'm');
+
+        for value in [size.0, size.1, size.2, x as u32, 0, 0] {
+            push_u32(&mut bytes, value);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn rejects_malformed_matrices() {
+        let read = |bytes: Vec<u8>| QbFile::read(&mut bytes.as_slice()).map(|_| ()).map_err(|e| e.kind());
+
+        // No width to wrap voxel indices around.
+        let mut bytes = compressed_header((0, 1, 1), 0);
+        push_u32(&mut bytes, NEXT_SLICE_FLAG);
+        assert_eq!(read(bytes), Err(io::ErrorKind::InvalidData));
+
+        // A run of billions of voxels in a 2x1 slice.
+        let mut bytes = compressed_header((2, 1, 1), 0);
+        for value in [CODE_FLAG, u32::MAX, 0xFF00_00FF] {
+            push_u32(&mut bytes, value);
+        }
+        assert_eq!(read(bytes), Err(io::ErrorKind::InvalidData));
+
+        // One voxel too many for a 1x1 slice.
+        let mut bytes = compressed_header((1, 1, 1), 0);
+        for value in [0xFF00_00FF, 0xFF00_00FF, NEXT_SLICE_FLAG] {
+            push_u32(&mut bytes, value);
+        }
+        assert_eq!(read(bytes), Err(io::ErrorKind::InvalidData));
+
+        // A center past the largest position.
+        let mut bytes = compressed_header((4, 1, 1), i32::MAX);
+        push_u32(&mut bytes, NEXT_SLICE_FLAG);
+        assert_eq!(read(bytes), Err(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn reads_uncompressed_matrix() {
+        let mut bytes = vec![];
+
+        // Header: version, RGBA, left handed, uncompressed, no visibility mask, 1 matrix.
+        for value in [0x0101_0000, 0, 0, 0, 0, 1] {
+            push_u32(&mut bytes, value);
+        }
+
+        bytes.push(1);
+        bytes.push(b'm');
+
+        // 2x1x1 matrix at the origin.
+        for value in [2, 1, 1, 0, 0, 0] {
+            push_u32(&mut bytes, value);
+        }
+
+        bytes.extend_from_slice(&[255, 0, 0, 255]);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+
+        let qb = QbFile::read(&mut bytes.as_slice()).unwrap();
+
+        let palette: Vec<_> = qb.palette.iter().map(|c| (c.r, c.g, c.b, c.a)).collect();
+        assert_eq!(palette, vec![(255, 0, 0, 255)]);
+        assert_eq!(qb.models.len(), 1);
+        assert_eq!(qb.models[0].voxels, vec![SourceVoxel { position: (0, 0, 0), color_index: 0 }]);
+    }
+}
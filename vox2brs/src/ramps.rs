@@ -0,0 +1,237 @@
+//! Ramps on walls, made by running the rampifier over the grid turned on its side.
+//!
+//! The rampifier only makes ramps on floors and ceilings, so the grid is turned until the
+//! walls face up and down, and the ramps it makes are turned back into place.
+
+use brickadia::save::{Brick, Direction, Rotation, Size};
+use rampifier::{Rampifier, RampifierConfig};
+use crate::magica::{self, Rotation as Matrix, IDENTITY};
+use crate::simplify::GridPos;
+
+/// Half size of a rampifier cell along each axis: a stud wide and a plate tall.
+const CELL: [i32; 3] = [5, 5, 2];
+
+/// Horizontal axes whose walls can be rampified.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WallAxis {
+    /// Walls facing +X and -X.
+    X,
+
+    /// Walls facing +Y and -Y.
+    Y,
+}
+
+impl WallAxis {
+    /// Maps the turned grid's axes onto the real grid's, so the turned grid's Z is this axis.
+    fn turn(self) -> Matrix {
+        match self {
+            WallAxis::X => [[0, 0, 1], [1, 0, 0], [0, 1, 0]],
+            WallAxis::Y => [[0, 1, 0], [0, 0, 1], [1, 0, 0]],
+        }
+    }
+}
+
+/// A quarter turn around Z.
+const QUARTER_TURN: Matrix = [[0, -1, 0], [1, 0, 0], [0, 0, 1]];
+
+const DIRECTIONS: [Direction; 6] = [
+    Direction::XPositive,
+    Direction::XNegative,
+    Direction::YPositive,
+    Direction::YNegative,
+    Direction::ZPositive,
+    Direction::ZNegative,
+];
+
+const ROTATIONS: [Rotation; 4] = [Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270];
+
+/// Turns a brick's up axis from Z to its direction.
+fn direction_matrix(direction: &Direction) -> Matrix {
+    match direction {
+        Direction::XPositive => [[0, 0, 1], [0, 1, 0], [-1, 0, 0]],
+        Direction::XNegative => [[0, 0, -1], [0, 1, 0], [1, 0, 0]],
+        Direction::YPositive => [[1, 0, 0], [0, 0, 1], [0, -1, 0]],
+        Direction::YNegative => [[1, 0, 0], [0, 0, -1], [0, 1, 0]],
+        Direction::ZPositive => IDENTITY,
+        Direction::ZNegative => [[1, 0, 0], [0, -1, 0], [0, 0, -1]],
+    }
+}
+
+/// Orientation of a brick as a matrix: turned around its up axis, then pointed in its direction.
+fn orientation_matrix(direction: &Direction, rotation: &Rotation) -> Matrix {
+    let turns = ROTATIONS.iter().position(|r| r == rotation).unwrap_or(0);
+
+    (0..turns).fold(direction_matrix(direction), |m, _| magica::multiply_rotation(&m, &QUARTER_TURN))
+}
+
+/// Finds the direction and rotation with the given orientation matrix.
+pub(crate) fn orientation(m: &Matrix) -> Option<(Direction, Rotation)> {
+    DIRECTIONS
+        .iter()
+        .flat_map(|direction| ROTATIONS.iter().map(move |rotation| (direction, rotation)))
+        .find(|(direction, rotation)| orientation_matrix(direction, rotation) == *m)
+        .map(|(direction, rotation)| (direction.clone(), rotation.clone()))
+}
+
+/// Half size of `brick` along each world axis, with its direction and rotation applied.
+pub(crate) fn world_size(brick: &Brick) -> Option<(u32, u32, u32)> {
+    let local = match brick.size {
+        Size::Procedural(x, y, z) => [x, y, z],
+        _ => return None,
+    };
+
+    let m = orientation_matrix(&brick.direction, &brick.rotation);
+
+    Some((local[column_of(&m, 0)], local[column_of(&m, 1)], local[column_of(&m, 2)]))
+}
+
+/// Axis of `m`'s row `row` that holds its non-zero entry.
+fn column_of(m: &Matrix, row: usize) -> usize {
+    m[row].iter().position(|&v| v != 0).unwrap_or(row)
+}
+
+/// Size or position in the turned grid, from one in the real grid.
+fn to_turned(turn: &Matrix, real: GridPos) -> GridPos {
+    let real = [real.0, real.1, real.2];
+    let mut turned = [0; 3];
+
+    for (axis, &value) in real.iter().enumerate() {
+        turned[column_of(turn, axis)] = value;
+    }
+
+    (turned[0], turned[1], turned[2])
+}
+
+fn get_index(grid_size: GridPos, pos: GridPos) -> usize {
+    pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
+}
+
+fn for_each_cell(grid_size: GridPos, mut f: impl FnMut(GridPos)) {
+    for x in 0..grid_size.0 {
+        for y in 0..grid_size.1 {
+            for z in 0..grid_size.2 {
+                f((x, y, z));
+            }
+        }
+    }
+}
+
+/// Moves a brick the rampifier made in the turned grid into the real grid.
+///
+/// Cells are wider than they are tall, so the brick is scaled along each axis as it's turned.
+fn turn_back(mut brick: Brick, turn: &Matrix) -> Brick {
+    let (local, orientation_then) = match brick.size {
+        Size::Procedural(x, y, z) => ([x as i32, y as i32, z as i32], orientation_matrix(&brick.direction, &brick.rotation)),
+        _ => return brick,
+    };
+
+    let new_orientation = magica::multiply_rotation(turn, &orientation_then);
+
+    let (direction, rotation) = match orientation(&new_orientation) {
+        Some(orientation) => orientation,
+        None => return brick,
+    };
+
+    // Half extents along the turned grid's axes, in cells.
+    let mut cells = [0; 3];
+    let mut min_cell = [0; 3];
+    let position = [brick.position.0, brick.position.1, brick.position.2];
+
+    for axis in 0..3 {
+        let extent = local[column_of(&orientation_then, axis)];
+        cells[axis] = extent / CELL[axis];
+        min_cell[axis] = (position[axis] - extent) / (CELL[axis] * 2);
+    }
+
+    let mut real_extent = [0; 3];
+    let mut real_position = [0; 3];
+
+    for axis in 0..3 {
+        let from = column_of(turn, axis);
+        real_extent[axis] = cells[from] * CELL[axis];
+        real_position[axis] = min_cell[from] * CELL[axis] * 2 + real_extent[axis];
+    }
+
+    let mut size = [0; 3];
+    for (axis, &extent) in real_extent.iter().enumerate() {
+        size[column_of(&new_orientation, axis)] = extent as u32;
+    }
+
+    brick.position = (real_position[0], real_position[1], real_position[2]);
+    brick.size = Size::Procedural(size[0], size[1], size[2]);
+    brick.direction = direction;
+    brick.rotation = rotation;
+    brick
+}
+
+/// Generates ramps on walls facing along `axis`, clearing the cells they cover from `grid`.
+pub fn wall_ramps(grid: &mut [Option<u8>], grid_size: GridPos, axis: WallAxis, config: RampifierConfig) -> Vec<Brick> {
+    let turn = axis.turn();
+    let turned_size = to_turned(&turn, grid_size);
+
+    let mut turned = vec![None; grid.len()];
+    for_each_cell(grid_size, |pos| {
+        turned[get_index(turned_size, to_turned(&turn, pos))] = grid[get_index(grid_size, pos)];
+    });
+
+    let mut rampifier = Rampifier::new(turned_size, turned, config);
+
+    let mut bricks = rampifier.generate_ramps(true);
+    bricks.append(&mut rampifier.generate_ramps(false));
+
+    rampifier.remove_occupied_voxels();
+    let turned = rampifier.move_grid();
+
+    for_each_cell(grid_size, |pos| {
+        grid[get_index(grid_size, pos)] = turned[get_index(turned_size, to_turned(&turn, pos))];
+    });
+
+    bricks.into_iter().map(|brick| turn_back(brick, &turn)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientations_are_distinct() {
+        let mut matrices = vec![];
+
+        for direction in &DIRECTIONS {
+            for rotation in &ROTATIONS {
+                let m = orientation_matrix(direction, rotation);
+                assert_eq!(orientation(&m), Some((direction.clone(), rotation.clone())));
+                matrices.push(m);
+            }
+        }
+
+        matrices.sort();
+        matrices.dedup();
+        assert_eq!(matrices.len(), 24);
+    }
+
+    #[test]
+    fn floor_ramp_becomes_x_wall_ramp() {
+        // A ramp 2 studs long, 1 wide and 1 plate tall, in the cell at the turned grid's origin.
+        let brick = Brick {
+            position: (10, 5, 2),
+            size: Size::Procedural(10, 5, 2),
+            direction: Direction::ZPositive,
+            rotation: Rotation::Deg0,
+            ..Default::default()
+        };
+
+        let brick = turn_back(brick, &WallAxis::X.turn());
+
+        assert_eq!(brick.direction, Direction::XPositive);
+        // Its plate of height becomes a stud along X, its length runs along Y, and its width is a plate along Z.
+        assert_eq!(brick.position, (5, 10, 2));
+        assert_eq!(brick.size, Size::Procedural(10, 2, 5));
+    }
+
+    #[test]
+    fn turned_grid_puts_axis_up() {
+        assert_eq!(to_turned(&WallAxis::X.turn(), (3, 4, 5)), (4, 5, 3));
+        assert_eq!(to_turned(&WallAxis::Y.turn(), (3, 4, 5)), (5, 3, 4));
+    }
+}
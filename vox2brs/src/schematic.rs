@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use brickadia::save::Color;
+use flate2::read::GzDecoder;
+use crate::palette::parse_hex_color;
+use crate::source::{SourceCopy, SourceModel, SourceVoxel, VoxelSource};
+
+/// Block colors used when no other table is given, in the format `parse_block_colors` reads.
+pub const DEFAULT_BLOCK_COLORS: &str = include_str!("block_colors.txt");
+
+/// NBT nesting deeper than this is rejected, rather than overflowing the stack on a broken file.
+const MAX_DEPTH: usize = 64;
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// Colors of Minecraft blocks, by numeric block id and optionally data value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockColors {
+    colors: HashMap<(u16, Option<u8>), Color>,
+}
+
+impl BlockColors {
+    /// Color of a block, preferring a color for its data value over one for the whole id.
+    pub fn get(&self, id: u16, data: u8) -> Option<&Color> {
+        self.colors.get(&(id, Some(data))).or_else(|| self.colors.get(&(id, None)))
+    }
+}
+
+/// Parses a block color table, with one `id` or `id:data` and a hex color per line.
+///
+/// Blank lines and lines starting with `//` are skipped, as is anything after a `//` on a line.
+pub fn parse_block_colors(text: &str) -> Result<BlockColors, String> {
+    let mut colors = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || format!("Line {}: expected a block id and a hex color like 35:14 #A02722, got {}", number + 1, line);
+
+        let (block, color) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+
+        let key = match block.split_once(':') {
+            Some((id, data)) => (id.parse().map_err(|_| invalid())?, Some(data.parse().map_err(|_| invalid())?)),
+            None => (block.parse().map_err(|_| invalid())?, None),
+        };
+
+        colors.insert(key, parse_hex_color(color.trim()).ok_or_else(invalid)?);
+    }
+
+    Ok(BlockColors { colors })
+}
+
+/// A Minecraft `.schematic` file, as written by MCEdit, Schematica and WorldEdit before 1.13.
+///
+/// The file is gzipped NBT, with a `Schematic` compound holding:
+/// - `Width`, `Height` and `Length`, the size along X, Y and Z, with Y up;
+/// - `Blocks`, the low 8 bits of each block id, ordered by Y, then Z, then X;
+/// - `Data`, each block's data value, which picks wool colors, wood types and such;
+/// - optionally `AddBlocks`, the high 4 bits of block ids above 255, two to a byte.
+///
+/// Newer `.schem` and `.litematic` files name blocks instead of numbering them, and aren't read.
+/// Blocks become voxels colored by a `BlockColors` table, and the structure is converted to
+/// MagicaVoxel's Z-up coordinates.
+#[derive(Debug, Clone)]
+pub struct SchematicFile {
+    pub palette: Vec<Color>,
+    pub models: Vec<SourceModel>,
+}
+
+/// The parts of a schematic's NBT that become voxels.
+#[derive(Default)]
+struct SchematicTags {
+    width: Option<i16>,
+    height: Option<i16>,
+    length: Option<i16>,
+    blocks: Option<Vec<u8>>,
+    data: Option<Vec<u8>>,
+    add_blocks: Option<Vec<u8>>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_len(reader: &mut impl Read) -> io::Result<usize> {
+    let len = i32::from_be_bytes(read_bytes(reader)?);
+    usize::try_from(len).map_err(|_| invalid_data("Negative NBT length."))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let len = u16::from_be_bytes(read_bytes(reader)?);
+    let mut bytes = vec![];
+    reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid_data("NBT name isn't UTF-8."))
+}
+
+/// Reads `len` bytes, growing the buffer as they arrive so a bogus length can't allocate it all up front.
+fn read_byte_array(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+/// Reads the length of an array of `item_size` byte items, in bytes.
+fn read_array_len(reader: &mut impl Read, item_size: usize) -> io::Result<usize> {
+    read_len(reader)?.checked_mul(item_size).ok_or_else(|| invalid_data("NBT array is too large."))
+}
+
+fn skip(reader: &mut impl Read, len: usize) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(len as u64), &mut io::sink())?;
+
+    if skipped != len as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+/// Reads past the payload of a tag of type `tag`.
+fn skip_payload(reader: &mut impl Read, tag: u8, depth: usize) -> io::Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("NBT is nested too deeply."));
+    }
+
+    match tag {
+        TAG_BYTE => skip(reader, 1),
+        TAG_SHORT => skip(reader, 2),
+        TAG_INT | TAG_FLOAT => skip(reader, 4),
+        TAG_LONG | TAG_DOUBLE => skip(reader, 8),
+        TAG_BYTE_ARRAY => {
+            let len = read_array_len(reader, 1)?;
+            skip(reader, len)
+        },
+        TAG_STRING => read_string(reader).map(|_| ()),
+        TAG_LIST => {
+            let [item_tag] = read_bytes(reader)?;
+            let len = read_len(reader)?;
+
+            for _ in 0..len {
+                skip_payload(reader, item_tag, depth + 1)?;
+            }
+
+            Ok(())
+        },
+        TAG_COMPOUND => loop {
+            let [tag] = read_bytes(reader)?;
+
+            if tag == TAG_END {
+                return Ok(());
+            }
+
+            read_string(reader)?;
+            skip_payload(reader, tag, depth + 1)?;
+        },
+        TAG_INT_ARRAY => {
+            let len = read_array_len(reader, 4)?;
+            skip(reader, len)
+        },
+        TAG_LONG_ARRAY => {
+            let len = read_array_len(reader, 8)?;
+            skip(reader, len)
+        },
+        _ => Err(invalid_data("Unknown NBT tag.")),
+    }
+}
+
+/// Reads the root compound, keeping the tags a schematic's blocks are made of.
+fn read_tags(reader: &mut impl Read) -> io::Result<SchematicTags> {
+    let [root] = read_bytes(reader)?;
+
+    if root != TAG_COMPOUND {
+        return Err(invalid_data("Schematic doesn't start with an NBT compound."));
+    }
+
+    read_string(reader)?;
+
+    let mut tags = SchematicTags::default();
+
+    loop {
+        let [tag] = read_bytes(reader)?;
+
+        if tag == TAG_END {
+            return Ok(tags);
+        }
+
+        let name = read_string(reader)?;
+
+        match (tag, name.as_str()) {
+            (TAG_SHORT, "Width") => tags.width = Some(i16::from_be_bytes(read_bytes(reader)?)),
+            (TAG_SHORT, "Height") => tags.height = Some(i16::from_be_bytes(read_bytes(reader)?)),
+            (TAG_SHORT, "Length") => tags.length = Some(i16::from_be_bytes(read_bytes(reader)?)),
+            (TAG_BYTE_ARRAY, "Blocks" | "Data" | "AddBlocks") => {
+                let len = read_len(reader)?;
+                let bytes = Some(read_byte_array(reader, len)?);
+
+                match name.as_str() {
+                    "Blocks" => tags.blocks = bytes,
+                    "Data" => tags.data = bytes,
+                    _ => tags.add_blocks = bytes,
+                }
+            },
+            _ => skip_payload(reader, tag, 1)?,
+        }
+    }
+}
+
+impl SchematicFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&fs::read(path)?)
+    }
+
+    /// Reads a schematic, coloring blocks with `DEFAULT_BLOCK_COLORS`.
+    pub fn read(bytes: &[u8]) -> io::Result<Self> {
+        let colors = parse_block_colors(DEFAULT_BLOCK_COLORS).map_err(|error| invalid_data(&error))?;
+        Self::read_with_colors(bytes, &colors)
+    }
+
+    /// Reads a gzipped or uncompressed schematic, coloring blocks with `colors`.
+    pub fn read_with_colors(bytes: &[u8], colors: &BlockColors) -> io::Result<Self> {
+        let tags = if bytes.starts_with(&[0x1F, 0x8B]) {
+            read_tags(&mut GzDecoder::new(bytes))?
+        } else {
+            read_tags(&mut &bytes[..])?
+        };
+
+        let size = |value: Option<i16>, name: &str| -> io::Result<u32> {
+            let value = value.ok_or_else(|| invalid_data(&format!("Schematic has no {}.", name)))?;
+            u16::try_from(value).map(u32::from).map_err(|_| invalid_data(&format!("Schematic has a negative {}.", name)))
+        };
+
+        let (width, height, length) = (size(tags.width, "Width")?, size(tags.height, "Height")?, size(tags.length, "Length")?);
+        let blocks = tags.blocks.ok_or_else(|| invalid_data("Schematic has no Blocks."))?;
+        let data = tags.data.unwrap_or_default();
+        let add_blocks = tags.add_blocks.unwrap_or_default();
+
+        if blocks.len() != width as usize * height as usize * length as usize {
+            return Err(invalid_data("Schematic's Blocks don't match its size."));
+        }
+
+        let mut palette = vec![];
+        let mut color_indices: HashMap<(u16, u8), Option<u32>> = HashMap::new();
+        let mut palette_indices: HashMap<(u8, u8, u8, u8), u32> = HashMap::new();
+        let mut voxels = vec![];
+
+        for (index, &low) in blocks.iter().enumerate() {
+            let high = match add_blocks.get(index >> 1) {
+                Some(add) if index & 1 == 0 => add & 0x0F,
+                Some(add) => add >> 4,
+                None => 0,
+            };
+            let id = (high as u16) << 8 | low as u16;
+
+            // Air.
+            if id == 0 {
+                continue;
+            }
+
+            let block_data = data.get(index).map_or(0, |data| data & 0x0F);
+
+            // Blocks of different ids sharing a color share a palette entry.
+            let color_index = *color_indices.entry((id, block_data)).or_insert_with(|| {
+                let color = colors.get(id, block_data)?;
+
+                Some(*palette_indices.entry((color.r, color.g, color.b, color.a)).or_insert_with(|| {
+                    palette.push(color.clone());
+                    palette.len() as u32 - 1
+                }))
+            });
+
+            let color_index = match color_index {
+                Some(color_index) => color_index,
+                None => continue,
+            };
+
+            let index = index as u32;
+            let x = index % width;
+            let z = index / width % length;
+            let y = index / width / length;
+
+            // Minecraft's Z points south, so it's flipped to become Y pointing north.
+            voxels.push(SourceVoxel {
+                position: (x, length - 1 - z, y),
+                color_index,
+            });
+        }
+
+        Ok(Self {
+            palette,
+            models: vec![SourceModel {
+                size: (width, length, height),
+                voxels,
+                position: None,
+                rotation: None,
+            }],
+        })
+    }
+}
+
+impl VoxelSource for SchematicFile {
+    fn palette(&self) -> Vec<Color> {
+        self.palette.clone()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        self.models.clone()
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_name(bytes: &mut Vec<u8>, tag: u8, name: &str) {
+        bytes.push(tag);
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    fn push_byte_array(bytes: &mut Vec<u8>, name: &str, array: &[u8]) {
+        push_name(bytes, TAG_BYTE_ARRAY, name);
+        bytes.extend_from_slice(&(array.len() as i32).to_be_bytes());
+        bytes.extend_from_slice(array);
+    }
+
+    /// A 2x2x1 schematic of red wool, stone, air and an unknown block, with a list to skip.
+    fn tiny_schematic() -> Vec<u8> {
+        let mut bytes = vec![];
+        push_name(&mut bytes, TAG_COMPOUND, "Schematic");
+
+        for (name, value) in [("Width", 2i16), ("Height", 2), ("Length", 1)] {
+            push_name(&mut bytes, TAG_SHORT, name);
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+
+        push_name(&mut bytes, TAG_LIST, "Entities");
+        bytes.push(TAG_COMPOUND);
+        bytes.extend_from_slice(&1i32.to_be_bytes());
+        push_name(&mut bytes, TAG_STRING, "id");
+        bytes.extend_from_slice(&3u16.to_be_bytes());
+        bytes.extend_from_slice(b"Pig");
+        bytes.push(TAG_END);
+
+        push_byte_array(&mut bytes, "Blocks", &[35, 1, 0, 250]);
+        push_byte_array(&mut bytes, "Data", &[14, 0, 0, 0]);
+        bytes.push(TAG_END);
+        bytes
+    }
+
+    #[test]
+    fn reads_tiny_schematic() {
+        let schematic = SchematicFile::read(&tiny_schematic()).unwrap();
+        let model = &schematic.models[0];
+
+        assert_eq!(model.size, (2, 1, 2));
+        assert_eq!(model.voxels.len(), 2);
+
+        let colors: Vec<_> = schematic.palette.iter().map(|c| (c.r, c.g, c.b)).collect();
+        assert_eq!(colors, vec![(0xA0, 0x27, 0x22), (0x7D, 0x7D, 0x7D)]);
+
+        // The wool is at the bottom, the stone next to it along X.
+        assert_eq!(model.voxels[0].position, (0, 0, 0));
+        assert_eq!(model.voxels[1].position, (1, 0, 0));
+    }
+
+    #[test]
+    fn reads_gzipped_schematic() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&tiny_schematic()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(SchematicFile::read(&gzipped).unwrap().models[0].voxels.len(), 2);
+    }
+
+    #[test]
+    fn custom_colors_replace_the_default_table() {
+        let colors = parse_block_colors("// Everything stone is white\n1 #FFFFFF\n35:14 #00FF00 // red wool is green\n").unwrap();
+        let schematic = SchematicFile::read_with_colors(&tiny_schematic(), &colors).unwrap();
+
+        let colors: Vec<_> = schematic.palette.iter().map(|c| (c.r, c.g, c.b)).collect();
+        assert_eq!(colors, vec![(0, 255, 0), (255, 255, 255)]);
+
+        assert!(parse_block_colors("35:x #FFFFFF").is_err());
+        assert!(parse_block_colors("35 red").is_err());
+    }
+
+    #[test]
+    fn default_block_colors_parse() {
+        let colors = parse_block_colors(DEFAULT_BLOCK_COLORS).unwrap();
+
+        // Data values fall back to the block's own color.
+        assert_eq!(colors.get(1, 9), colors.get(1, 0));
+        assert_ne!(colors.get(35, 14), colors.get(35, 0));
+        assert!(colors.get(0, 0).is_none());
+    }
+
+    #[test]
+    fn truncated_schematic_is_an_error() {
+        let bytes = tiny_schematic();
+        assert!(SchematicFile::read(&bytes[..bytes.len() - 6]).is_err());
+    }
+}
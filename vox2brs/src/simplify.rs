@@ -0,0 +1,1000 @@
+use std::collections::{BTreeMap, HashSet};
+use clap::ArgEnum;
+use rayon::prelude::*;
+
+/// Position or size of a box in grid cells.
+pub type GridPos = (usize, usize, usize);
+
+/// Color of a grid cell, as an index into the save's palette.
+pub type Cell = Option<u16>;
+
+/// Default largest box the fill makes along each axis, to stay within Brickadia's procedural brick limits.
+///
+/// Regions larger than the cap are tiled with several boxes, as the fill continues from the
+/// next uncovered cell after each box.
+pub const DEFAULT_MAX_BOX_SIZE: GridPos = (64, 64, 64);
+
+/// Axes to grow boxes along, in order. 0 is width (X), 1 is length (Y) and 2 is height (Z).
+pub type GrowOrder = [usize; 3];
+
+/// Grows height first, then width, then length.
+pub const HEIGHT_FIRST: GrowOrder = [2, 0, 1];
+
+/// Grows width first, then length, then height.
+pub const WIDTH_FIRST: GrowOrder = [0, 1, 2];
+
+/// Grows length first, then height, then width.
+pub const LENGTH_FIRST: GrowOrder = [1, 2, 0];
+
+/// Every order boxes can grow in, starting with the default.
+pub const GROW_ORDERS: [GrowOrder; 6] = [HEIGHT_FIRST, [2, 1, 0], WIDTH_FIRST, [0, 2, 1], LENGTH_FIRST, [1, 0, 2]];
+
+/// Cells along each side of the block `SparseGrid::best_grow_order` tries every order on.
+pub const GROW_ORDER_SAMPLE: usize = 32;
+
+/// How the greedy fill decides which boxes to make.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum FillStrategy {
+    /// Grow every box height first, then width, then length.
+    HeightFirst,
+
+    /// Try growing height, width and length first, keeping whichever makes the fewest bricks.
+    FewestBricks,
+
+    /// Grow every box in this order.
+    #[clap(skip)]
+    Order(GrowOrder),
+}
+
+/// A box of same-colored cells found by the greedy fill.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FilledBox {
+    pub pos: GridPos,
+    pub size: GridPos,
+    pub value: u16,
+}
+
+impl FilledBox {
+    /// Cells the box covers.
+    pub fn volume(&self) -> usize {
+        self.size.0 * self.size.1 * self.size.2
+    }
+
+    fn contains(&self, pos: GridPos) -> bool {
+        (self.pos.0..self.pos.0 + self.size.0).contains(&pos.0)
+            && (self.pos.1..self.pos.1 + self.size.1).contains(&pos.1)
+            && (self.pos.2..self.pos.2 + self.size.2).contains(&pos.2)
+    }
+}
+
+fn get_index(grid_size: GridPos, pos: GridPos) -> usize {
+    pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
+}
+
+fn can_box(occupied: &impl Fn(usize) -> bool, grid_size: GridPos, pos: GridPos, size: GridPos) -> bool {
+    let (w, l, h) = size;
+
+    if pos.0 + w > grid_size.0 {
+        return false;
+    }
+    if pos.1 + l > grid_size.1 {
+        return false;
+    }
+    if pos.2 + h > grid_size.2 {
+        return false;
+    }
+
+    for i in 0..w {
+        for j in 0..l {
+            for k in 0..h {
+                if !occupied(get_index(grid_size, (pos.0 + i, pos.1 + j, pos.2 + k))) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    true
+}
+
+/// Grows a box from `pos` along each axis in `order`, as far as it can go without exceeding `max_size`.
+fn grow_box(occupied: &impl Fn(usize) -> bool, grid_size: GridPos, pos: GridPos, order: GrowOrder, max_size: GridPos) -> GridPos {
+    let mut size = [1, 1, 1];
+
+    // Boxes are always at least one cell, or the fill would never finish.
+    let max_size = [max_size.0.max(1), max_size.1.max(1), max_size.2.max(1)];
+
+    for axis in order {
+        while can_box(occupied, grid_size, pos, (size[0], size[1], size[2])) && size[axis] <= max_size[axis] {
+            size[axis] += 1;
+        }
+
+        size[axis] -= 1;
+    }
+
+    (size[0], size[1], size[2])
+}
+
+fn for_each_cell(grid_size: GridPos, mut f: impl FnMut(GridPos)) {
+    for x in 0..grid_size.0 {
+        for y in 0..grid_size.1 {
+            for z in 0..grid_size.2 {
+                f((x, y, z));
+            }
+        }
+    }
+}
+
+/// Positions of each color's cells, in the order `for_each_cell` visits them.
+fn cells_by_color(grid: &[Cell], grid_size: GridPos) -> BTreeMap<u16, Vec<GridPos>> {
+    let mut cells_by_color: BTreeMap<u16, Vec<GridPos>> = BTreeMap::new();
+
+    for_each_cell(grid_size, |pos| {
+        if let Some(value) = grid[get_index(grid_size, pos)] {
+            cells_by_color.entry(value).or_default().push(pos);
+        }
+    });
+
+    cells_by_color
+}
+
+/// Cells of one color still waiting to be put in a box.
+enum Occupied {
+    Dense(Vec<bool>),
+
+    /// For colors covering a small part of the grid, which would mostly waste a dense array.
+    Sparse(HashSet<usize>),
+}
+
+impl Occupied {
+    fn new(grid_size: GridPos, cells: &[GridPos]) -> Self {
+        let grid_len = grid_size.0 * grid_size.1 * grid_size.2;
+        let indices = cells.iter().map(|&pos| get_index(grid_size, pos));
+
+        if cells.len() * 16 < grid_len {
+            Occupied::Sparse(indices.collect())
+        } else {
+            let mut occupied = vec![false; grid_len];
+            for index in indices {
+                occupied[index] = true;
+            }
+            Occupied::Dense(occupied)
+        }
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        match self {
+            Occupied::Dense(occupied) => occupied[index],
+            Occupied::Sparse(occupied) => occupied.contains(&index),
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        match self {
+            Occupied::Dense(occupied) => occupied[index] = false,
+            Occupied::Sparse(occupied) => {
+                occupied.remove(&index);
+            },
+        }
+    }
+}
+
+/// Fills each color's cells with boxes on its own thread.
+///
+/// Boxes never span more than one color, so every color can be filled independently and
+/// the boxes are the same as filling the whole grid on one thread. A model of a single color
+/// only gets one thread.
+fn fill_colors_until(
+    cells_by_color: &BTreeMap<u16, Vec<GridPos>>,
+    grid_size: GridPos,
+    order: GrowOrder,
+    max_size: GridPos,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Vec<FilledBox>> {
+    let boxes = cells_by_color
+        .iter()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|(&value, cells)| {
+            let mut occupied = Occupied::new(grid_size, cells);
+            let mut boxes = vec![];
+
+            for &pos in cells {
+                if cancelled() {
+                    return None;
+                }
+
+                if !occupied.contains(get_index(grid_size, pos)) {
+                    continue;
+                }
+
+                let size = grow_box(&|i| occupied.contains(i), grid_size, pos, order, max_size);
+
+                for_each_cell(size, |(i, j, k)| {
+                    occupied.remove(get_index(grid_size, (pos.0 + i, pos.1 + j, pos.2 + k)));
+                });
+
+                boxes.push(FilledBox { pos, size, value });
+            }
+
+            Some(boxes)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(boxes.into_iter().flatten().collect())
+}
+
+/// What to do with cells that can't be seen from outside the model.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HollowMode {
+    /// Leave the grid as is.
+    None,
+
+    /// Clear cells whose six neighbors are all occupied.
+    RemoveInterior,
+
+    /// Fill empty pockets that are enclosed on every side.
+    FillInterior,
+}
+
+/// Neighbors of a cell along each axis that are inside the grid.
+fn neighbors(grid_size: GridPos, pos: GridPos) -> impl Iterator<Item = GridPos> {
+    let (x, y, z) = pos;
+
+    [
+        (x.checked_sub(1), Some(y), Some(z)),
+        (Some(x + 1).filter(|&x| x < grid_size.0), Some(y), Some(z)),
+        (Some(x), y.checked_sub(1), Some(z)),
+        (Some(x), Some(y + 1).filter(|&y| y < grid_size.1), Some(z)),
+        (Some(x), Some(y), z.checked_sub(1)),
+        (Some(x), Some(y), Some(z + 1).filter(|&z| z < grid_size.2)),
+    ]
+    .into_iter()
+    .filter_map(|(x, y, z)| Some((x?, y?, z?)))
+}
+
+/// Whether the cell at `pos` has all six neighbors occupied. Cells on the grid's edge never do.
+fn is_enclosed(grid: &[Cell], grid_size: GridPos, pos: GridPos) -> bool {
+    neighbors(grid_size, pos).count() == 6
+        && neighbors(grid_size, pos).all(|neighbor| grid[get_index(grid_size, neighbor)].is_some())
+}
+
+/// Clears occupied cells that have all six neighbors occupied, returning how many were cleared.
+///
+/// Only the outermost layer of cells is kept, as enclosure is decided before anything is cleared.
+pub fn remove_interior(grid: &mut [Cell], grid_size: GridPos) -> usize {
+    let mut interior = vec![];
+
+    for_each_cell(grid_size, |pos| {
+        if grid[get_index(grid_size, pos)].is_some() && is_enclosed(grid, grid_size, pos) {
+            interior.push(get_index(grid_size, pos));
+        }
+    });
+
+    for &index in &interior {
+        grid[index] = None;
+    }
+
+    interior.len()
+}
+
+/// Fills empty cells that can't be reached from the grid's edge, returning how many were filled.
+///
+/// Each pocket takes the color of the first occupied cell found around it.
+pub fn fill_interior(grid: &mut [Cell], grid_size: GridPos) -> usize {
+    let mut outside = vec![false; grid.len()];
+    let mut stack = vec![];
+
+    // Flood the empty cells reachable from the edge of the grid.
+    for_each_cell(grid_size, |pos| {
+        let on_edge = neighbors(grid_size, pos).count() < 6;
+        if on_edge && grid[get_index(grid_size, pos)].is_none() {
+            outside[get_index(grid_size, pos)] = true;
+            stack.push(pos);
+        }
+    });
+
+    while let Some(pos) = stack.pop() {
+        for neighbor in neighbors(grid_size, pos) {
+            let index = get_index(grid_size, neighbor);
+            if grid[index].is_none() && !outside[index] {
+                outside[index] = true;
+                stack.push(neighbor);
+            }
+        }
+    }
+
+    let mut filled = 0;
+
+    for_each_cell(grid_size, |pos| {
+        let index = get_index(grid_size, pos);
+        if grid[index].is_some() || outside[index] {
+            return;
+        }
+
+        // Flood this pocket, remembering its cells and the first color around it.
+        let mut pocket = vec![index];
+        let mut color = None;
+        outside[index] = true;
+        stack.push(pos);
+
+        while let Some(pos) = stack.pop() {
+            for neighbor in neighbors(grid_size, pos) {
+                let index = get_index(grid_size, neighbor);
+                match grid[index] {
+                    Some(value) => {
+                        color = color.or(Some(value));
+                    }
+                    None if !outside[index] => {
+                        outside[index] = true;
+                        pocket.push(index);
+                        stack.push(neighbor);
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        for index in pocket {
+            grid[index] = color;
+            filled += 1;
+        }
+    });
+
+    filled
+}
+
+/// Hollows or fills the grid's interior, returning how many cells were changed.
+pub fn hollow(grid: &mut [Cell], grid_size: GridPos, mode: HollowMode) -> usize {
+    match mode {
+        HollowMode::None => 0,
+        HollowMode::RemoveInterior => remove_interior(grid, grid_size),
+        HollowMode::FillInterior => fill_interior(grid, grid_size),
+    }
+}
+
+/// Gives every filled cell `value`, so the fill merges cells regardless of their color.
+pub fn paint(grid: &mut [Cell], value: u16) {
+    for cell in grid.iter_mut().filter(|cell| cell.is_some()) {
+        *cell = Some(value);
+    }
+}
+
+/// Removes boxes covering fewer than `min_volume` cells, returning how many cells they covered.
+pub fn drop_small_boxes(boxes: &mut Vec<FilledBox>, min_volume: usize) -> usize {
+    let mut dropped = 0;
+
+    boxes.retain(|filled| {
+        let keep = filled.volume() >= min_volume;
+        if !keep {
+            dropped += filled.volume();
+        }
+        keep
+    });
+
+    dropped
+}
+
+/// Recolors the cells of boxes covering fewer than `min_volume` cells to the color touching
+/// them most from outside, so filling the grid again merges them into their neighbors.
+///
+/// Boxes touching no other color keep theirs. Returns how many cells were recolored.
+pub fn merge_small_boxes(grid: &mut [Cell], grid_size: GridPos, boxes: &[FilledBox], min_volume: usize) -> usize {
+    merge_small_boxes_in(&mut DenseCells(grid, grid_size), grid_size, boxes, min_volume)
+}
+
+/// Cells the merge pass reads and recolors, however the grid stores them.
+trait Cells {
+    fn cell(&self, pos: GridPos) -> Cell;
+    fn set_cell(&mut self, pos: GridPos, value: u16);
+}
+
+struct DenseCells<'a>(&'a mut [Cell], GridPos);
+
+impl Cells for DenseCells<'_> {
+    fn cell(&self, pos: GridPos) -> Cell {
+        self.0[get_index(self.1, pos)]
+    }
+
+    fn set_cell(&mut self, pos: GridPos, value: u16) {
+        self.0[get_index(self.1, pos)] = Some(value);
+    }
+}
+
+fn merge_small_boxes_in(grid: &mut impl Cells, grid_size: GridPos, boxes: &[FilledBox], min_volume: usize) -> usize {
+    let mut merged = 0;
+
+    for filled in boxes.iter().filter(|filled| filled.volume() < min_volume) {
+        let mut touching: BTreeMap<u16, usize> = BTreeMap::new();
+
+        for_each_cell(filled.size, |(i, j, k)| {
+            let pos = (filled.pos.0 + i, filled.pos.1 + j, filled.pos.2 + k);
+
+            for neighbor in neighbors(grid_size, pos).filter(|&neighbor| !filled.contains(neighbor)) {
+                match grid.cell(neighbor) {
+                    Some(value) if value != filled.value => *touching.entry(value).or_default() += 1,
+                    _ => {},
+                }
+            }
+        });
+
+        // Ties go to the lowest color index, so the result doesn't depend on map order.
+        let color = touching.into_iter().rev().max_by_key(|&(_, count)| count).map(|(value, _)| value);
+
+        if let Some(color) = color {
+            for_each_cell(filled.size, |(i, j, k)| {
+                grid.set_cell((filled.pos.0 + i, filled.pos.1 + j, filled.pos.2 + k), color);
+            });
+
+            merged += filled.volume();
+        }
+    }
+
+    merged
+}
+
+/// Joins boxes of the same color that meet face to face with matching faces, as long as the
+/// joined box is no larger than `max_size` cells. Mends boxes cut in two by filling a grid in chunks.
+pub fn stitch(mut boxes: Vec<FilledBox>, max_size: GridPos) -> Vec<FilledBox> {
+    let max_size = [max_size.0.max(1), max_size.1.max(1), max_size.2.max(1)];
+
+    for axis in 0..3 {
+        let along = |pos: GridPos| [pos.0, pos.1, pos.2][axis];
+
+        // Where a box continuing `filled` along the axis would start, with the color and size across it both share.
+        let far_face = |filled: &FilledBox| {
+            let mut start = [filled.pos.0, filled.pos.1, filled.pos.2];
+            let mut across = [filled.size.0, filled.size.1, filled.size.2];
+            start[axis] += across[axis];
+            across[axis] = 0;
+            ((start[0], start[1], start[2]), (across[0], across[1], across[2]), filled.value)
+        };
+
+        let near_face = |filled: &FilledBox| {
+            let mut across = [filled.size.0, filled.size.1, filled.size.2];
+            across[axis] = 0;
+            (filled.pos, (across[0], across[1], across[2]), filled.value)
+        };
+
+        // Boxes ending where another starts are always seen first.
+        boxes.sort_by_key(|filled| (along(filled.pos), filled.pos));
+
+        let mut stitched: Vec<FilledBox> = vec![];
+        let mut far_faces = BTreeMap::new();
+
+        for filled in boxes {
+            if let Some(index) = far_faces.remove(&near_face(&filled)) {
+                let joined: &mut FilledBox = &mut stitched[index];
+                let mut size = [joined.size.0, joined.size.1, joined.size.2];
+                size[axis] += along(filled.size);
+
+                if size[axis] <= max_size[axis] {
+                    joined.size = (size[0], size[1], size[2]);
+                    far_faces.insert(far_face(joined), index);
+                    continue;
+                }
+            }
+
+            far_faces.insert(far_face(&filled), stitched.len());
+            stitched.push(filled);
+        }
+
+        boxes = stitched;
+    }
+
+    boxes
+}
+
+/// Refills each color's boxes with whichever grow order covers them in the fewest boxes, no larger
+/// than `max_size` cells, keeping them as they are where no order does better. Boxes filled apart,
+/// like around ramps or in separate chunks, can often be covered by fewer once filled together.
+pub fn weld(boxes: Vec<FilledBox>, grid_size: GridPos, max_size: GridPos) -> Vec<FilledBox> {
+    let mut boxes_by_color: BTreeMap<u16, Vec<FilledBox>> = BTreeMap::new();
+
+    for filled in boxes {
+        boxes_by_color.entry(filled.value).or_default().push(filled);
+    }
+
+    boxes_by_color
+        .into_iter()
+        .flat_map(|(value, boxes)| {
+            let mut cells = vec![];
+
+            for filled in &boxes {
+                for_each_cell(filled.size, |(i, j, k)| cells.push((filled.pos.0 + i, filled.pos.1 + j, filled.pos.2 + k)));
+            }
+
+            // Filled in the order `for_each_cell` visits them, like any other fill.
+            cells.sort_unstable();
+            let cells = BTreeMap::from([(value, cells)]);
+
+            GROW_ORDERS
+                .iter()
+                .filter_map(|&order| fill_colors_until(&cells, grid_size, order, max_size, &|| false))
+                .chain([stitch(boxes, max_size)])
+                .min_by_key(|boxes| boxes.len())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Fills the grid with boxes using the given strategy, no larger than `max_size` cells.
+/// Colors are filled in parallel, see `benches/fill.rs` for how much that helps.
+pub fn fill(grid: &[Cell], grid_size: GridPos, strategy: FillStrategy, max_size: GridPos) -> Vec<FilledBox> {
+    fill_until(grid, grid_size, strategy, max_size, &|| false).unwrap_or_default()
+}
+
+/// `fill`, checking `cancelled` as boxes are grown and returning `None` once it returns true.
+pub fn fill_until(
+    grid: &[Cell],
+    grid_size: GridPos,
+    strategy: FillStrategy,
+    max_size: GridPos,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Vec<FilledBox>> {
+    fill_strategy_until(&cells_by_color(grid, grid_size), grid_size, strategy, max_size, cancelled)
+}
+
+fn fill_strategy_until(
+    cells_by_color: &BTreeMap<u16, Vec<GridPos>>,
+    grid_size: GridPos,
+    strategy: FillStrategy,
+    max_size: GridPos,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Vec<FilledBox>> {
+    match strategy {
+        FillStrategy::HeightFirst => fill_colors_until(cells_by_color, grid_size, HEIGHT_FIRST, max_size, cancelled),
+        FillStrategy::Order(order) => fill_colors_until(cells_by_color, grid_size, order, max_size, cancelled),
+        FillStrategy::FewestBricks => {
+            let mut fills = vec![];
+
+            for order in [HEIGHT_FIRST, WIDTH_FIRST, LENGTH_FIRST] {
+                fills.push(fill_colors_until(cells_by_color, grid_size, order, max_size, cancelled)?);
+            }
+
+            fills.into_iter().min_by_key(|boxes| boxes.len())
+        },
+    }
+}
+
+/// A grid that only stores its filled cells, for models that leave most of their bounding box empty.
+///
+/// Fills, removes interiors and merges small boxes exactly like a dense grid with the same cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseGrid {
+    pub size: GridPos,
+
+    /// Filled cells, ordered like `for_each_cell` visits them.
+    cells: BTreeMap<GridPos, u16>,
+}
+
+impl SparseGrid {
+    pub fn new(size: GridPos) -> Self {
+        Self { size, cells: BTreeMap::new() }
+    }
+
+    pub fn from_dense(grid: &[Cell], size: GridPos) -> Self {
+        let mut sparse = Self::new(size);
+
+        for_each_cell(size, |pos| {
+            sparse.set(pos, grid[get_index(size, pos)]);
+        });
+
+        sparse
+    }
+
+    pub fn get(&self, pos: GridPos) -> Cell {
+        self.cells.get(&pos).copied()
+    }
+
+    pub fn set(&mut self, pos: GridPos, cell: Cell) {
+        match cell {
+            Some(value) => self.cells.insert(pos, value),
+            None => self.cells.remove(&pos),
+        };
+    }
+
+    /// How many cells are filled.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Same as `remove_interior`.
+    pub fn remove_interior(&mut self) -> usize {
+        let interior: Vec<GridPos> = self.cells
+            .keys()
+            .copied()
+            .filter(|&pos| {
+                neighbors(self.size, pos).count() == 6
+                    && neighbors(self.size, pos).all(|neighbor| self.cells.contains_key(&neighbor))
+            })
+            .collect();
+
+        for pos in &interior {
+            self.cells.remove(pos);
+        }
+
+        interior.len()
+    }
+
+    /// Same as `paint`.
+    pub fn paint(&mut self, value: u16) {
+        for cell in self.cells.values_mut() {
+            *cell = value;
+        }
+    }
+
+    /// Same as `fill_until`.
+    pub fn fill_until(&self, strategy: FillStrategy, max_size: GridPos, cancelled: &(dyn Fn() -> bool + Sync)) -> Option<Vec<FilledBox>> {
+        let mut cells_by_color: BTreeMap<u16, Vec<GridPos>> = BTreeMap::new();
+
+        for (&pos, &value) in &self.cells {
+            cells_by_color.entry(value).or_default().push(pos);
+        }
+
+        fill_strategy_until(&cells_by_color, self.size, strategy, max_size, cancelled)
+    }
+
+    /// Order that grows the fewest boxes over a block of at most `GROW_ORDER_SAMPLE` cells a
+    /// side, centered on the middle filled cell. Ties go to the earliest order in `GROW_ORDERS`.
+    ///
+    /// The sample keeps this quick on large grids, at the cost of missing shapes outside it.
+    pub fn best_grow_order(&self, max_size: GridPos) -> GrowOrder {
+        let middle = match self.cells.keys().nth(self.cells.len() / 2) {
+            Some(&middle) => middle,
+            None => return HEIGHT_FIRST,
+        };
+
+        let sample_size = (
+            self.size.0.min(GROW_ORDER_SAMPLE),
+            self.size.1.min(GROW_ORDER_SAMPLE),
+            self.size.2.min(GROW_ORDER_SAMPLE),
+        );
+
+        // Centered on the middle cell, moved back inside the grid where it would cross an edge.
+        let start = |middle: usize, sample: usize, size: usize| middle.saturating_sub(sample / 2).min(size - sample);
+        let start = (
+            start(middle.0, sample_size.0, self.size.0),
+            start(middle.1, sample_size.1, self.size.1),
+            start(middle.2, sample_size.2, self.size.2),
+        );
+
+        let mut sample: BTreeMap<u16, Vec<GridPos>> = BTreeMap::new();
+
+        for (&(x, y, z), &value) in &self.cells {
+            let inside = (start.0..start.0 + sample_size.0).contains(&x)
+                && (start.1..start.1 + sample_size.1).contains(&y)
+                && (start.2..start.2 + sample_size.2).contains(&z);
+
+            if inside {
+                sample.entry(value).or_default().push((x - start.0, y - start.1, z - start.2));
+            }
+        }
+
+        GROW_ORDERS
+            .iter()
+            .copied()
+            .min_by_key(|&order| {
+                fill_colors_until(&sample, sample_size, order, max_size, &|| false).map_or(usize::MAX, |boxes| boxes.len())
+            })
+            .unwrap_or(HEIGHT_FIRST)
+    }
+
+    /// Same as `merge_small_boxes`.
+    pub fn merge_small_boxes(&mut self, boxes: &[FilledBox], min_volume: usize) -> usize {
+        let size = self.size;
+        merge_small_boxes_in(self, size, boxes, min_volume)
+    }
+}
+
+impl Cells for SparseGrid {
+    fn cell(&self, pos: GridPos) -> Cell {
+        self.get(pos)
+    }
+
+    fn set_cell(&mut self, pos: GridPos, value: u16) {
+        self.cells.insert(pos, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Greedily fills the grid with boxes of the same color one cell at a time, clearing it in
+    /// the process. What `fill` does in parallel, kept to check it against.
+    fn fill_gaps(grid: &mut [Cell], grid_size: GridPos, order: GrowOrder, max_size: GridPos) -> Vec<FilledBox> {
+        let mut boxes = vec![];
+
+        for_each_cell(grid_size, |pos| {
+            if let Some(value) = grid[get_index(grid_size, pos)] {
+                let size = grow_box(&|i| grid[i] == Some(value), grid_size, pos, order, max_size);
+
+                for_each_cell(size, |(i, j, k)| {
+                    grid[get_index(grid_size, (pos.0 + i, pos.1 + j, pos.2 + k))] = None;
+                });
+
+                boxes.push(FilledBox { pos, size, value });
+            }
+        });
+
+        boxes
+    }
+
+    /// Deterministic noisy grid with a handful of colors.
+    fn noisy_grid(grid_size: GridPos, colors: u32) -> Vec<Cell> {
+        let mut seed: u32 = 12345;
+        let mut grid = vec![None; grid_size.0 * grid_size.1 * grid_size.2];
+
+        for cell in grid.iter_mut() {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let roll = (seed >> 16) % (colors + 1);
+            if roll > 0 {
+                *cell = Some(roll as u16);
+            }
+        }
+
+        grid
+    }
+
+    #[test]
+    fn parallel_fill_matches_serial() {
+        let grid_size = (24, 17, 31);
+        let mut grid = noisy_grid(grid_size, 3);
+
+        let mut parallel = fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+        let mut serial = fill_gaps(&mut grid, grid_size, HEIGHT_FIRST, DEFAULT_MAX_BOX_SIZE);
+
+        assert_eq!(parallel.len(), serial.len());
+
+        parallel.sort();
+        serial.sort();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn sparse_grid_matches_dense() {
+        let grid_size = (40, 30, 50);
+
+        // Mostly empty, so colors track their cells sparsely while filling too.
+        let mut dense: Vec<Cell> = noisy_grid(grid_size, 3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| cell.filter(|_| i % 20 == 0))
+            .collect();
+
+        let mut sparse = SparseGrid::from_dense(&dense, grid_size);
+
+        for strategy in [FillStrategy::HeightFirst, FillStrategy::FewestBricks] {
+            assert_eq!(
+                sparse.fill_until(strategy, DEFAULT_MAX_BOX_SIZE, &|| false),
+                fill_until(&dense, grid_size, strategy, DEFAULT_MAX_BOX_SIZE, &|| false),
+            );
+        }
+
+        let boxes = fill(&dense, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+        assert_eq!(sparse.merge_small_boxes(&boxes, 2), merge_small_boxes(&mut dense, grid_size, &boxes, 2));
+        assert_eq!(sparse, SparseGrid::from_dense(&dense, grid_size));
+
+        let mut solid = vec![Some(1); 5 * 5 * 5];
+        let mut sparse = SparseGrid::from_dense(&solid, (5, 5, 5));
+
+        assert_eq!(sparse.remove_interior(), remove_interior(&mut solid, (5, 5, 5)));
+        assert_eq!(sparse, SparseGrid::from_dense(&solid, (5, 5, 5)));
+        assert_eq!(sparse.len(), 125 - 27);
+    }
+
+    #[test]
+    fn fill_merges_solid_block() {
+        let grid_size = (4, 3, 2);
+        let mut grid = vec![Some(7); 4 * 3 * 2];
+
+        let boxes = fill_gaps(&mut grid, grid_size, HEIGHT_FIRST, DEFAULT_MAX_BOX_SIZE);
+
+        assert_eq!(boxes, vec![FilledBox { pos: (0, 0, 0), size: (4, 3, 2), value: 7 }]);
+        assert!(grid.iter().all(|cell| cell.is_none()));
+    }
+
+    #[test]
+    fn fewest_bricks_beats_height_first() {
+        // A 3x3 floor with a 3 tall tower in one corner.
+        let grid_size = (3, 3, 3);
+        let mut grid = vec![None; 27];
+        for x in 0..3 {
+            for y in 0..3 {
+                grid[get_index(grid_size, (x, y, 0))] = Some(1);
+            }
+        }
+        grid[get_index(grid_size, (0, 0, 1))] = Some(1);
+        grid[get_index(grid_size, (0, 0, 2))] = Some(1);
+
+        assert_eq!(fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE).len(), 3);
+        assert_eq!(fill(&grid, grid_size, FillStrategy::FewestBricks, DEFAULT_MAX_BOX_SIZE).len(), 2);
+    }
+
+    #[test]
+    fn large_slab_is_tiled_with_max_size_boxes() {
+        let grid_size = (200, 200, 1);
+        let grid = vec![Some(1); 200 * 200];
+
+        let boxes = fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+
+        // ceil(200 / 64) = 4 boxes along each side.
+        assert_eq!(boxes.len(), 16);
+        assert!(boxes.iter().all(|b| b.size.0 <= 64 && b.size.1 <= 64));
+    }
+
+    #[test]
+    fn boxes_respect_max_size() {
+        let grid_size = (10, 10, 10);
+        let grid = vec![Some(1); 1000];
+
+        let boxes = fill(&grid, grid_size, FillStrategy::HeightFirst, (4, 5, 10));
+
+        assert!(boxes.iter().all(|b| b.size.0 <= 4 && b.size.1 <= 5 && b.size.2 <= 10));
+        assert_eq!(boxes.len(), 3 * 2);
+
+        // A zero cap still makes single cell boxes instead of never finishing.
+        assert_eq!(fill(&grid, grid_size, FillStrategy::HeightFirst, (0, 0, 0)).len(), 1000);
+    }
+
+    #[test]
+    fn lone_cells_are_dropped() {
+        let grid_size = (4, 1, 1);
+        let grid = vec![Some(1), Some(1), None, Some(2)];
+
+        let mut boxes = fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+
+        assert_eq!(drop_small_boxes(&mut boxes, 1), 0);
+        assert_eq!(drop_small_boxes(&mut boxes, 2), 1);
+        assert_eq!(boxes, vec![FilledBox { pos: (0, 0, 0), size: (2, 1, 1), value: 1 }]);
+    }
+
+    #[test]
+    fn small_boxes_take_their_neighbors_color() {
+        let grid_size = (4, 1, 1);
+        let mut grid = vec![Some(1), Some(1), Some(2), Some(3)];
+
+        let boxes = fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+
+        // The lone 2 sits between 1 and 3, and the tie goes to 1. The lone 3 then only touches 1.
+        assert_eq!(merge_small_boxes(&mut grid, grid_size, &boxes, 2), 2);
+        assert_eq!(grid, vec![Some(1); 4]);
+        assert_eq!(fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE).len(), 1);
+    }
+
+    #[test]
+    fn stitching_joins_boxes_cut_by_chunks() {
+        let boxes = vec![
+            FilledBox { pos: (0, 0, 0), size: (4, 2, 1), value: 1 },
+            FilledBox { pos: (4, 0, 0), size: (4, 2, 1), value: 1 },
+            FilledBox { pos: (8, 0, 0), size: (2, 2, 1), value: 1 },
+            // Different color, then a different cross section.
+            FilledBox { pos: (0, 2, 0), size: (4, 1, 1), value: 2 },
+            FilledBox { pos: (4, 2, 0), size: (4, 1, 1), value: 3 },
+            FilledBox { pos: (0, 3, 0), size: (4, 1, 1), value: 4 },
+            FilledBox { pos: (4, 3, 0), size: (4, 2, 1), value: 4 },
+        ];
+
+        let mut stitched = stitch(boxes.clone(), DEFAULT_MAX_BOX_SIZE);
+        stitched.sort();
+
+        assert_eq!(stitched.len(), 5);
+        assert!(stitched.contains(&FilledBox { pos: (0, 0, 0), size: (10, 2, 1), value: 1 }));
+
+        // Joined boxes stay within the size cap.
+        assert_eq!(stitch(boxes, (8, 64, 64)).len(), 6);
+    }
+
+    #[test]
+    fn welding_refills_with_the_fewest_boxes() {
+        let boxes = vec![
+            FilledBox { pos: (0, 0, 0), size: (1, 1, 1), value: 1 },
+            FilledBox { pos: (0, 1, 0), size: (1, 1, 1), value: 1 },
+            FilledBox { pos: (1, 0, 0), size: (1, 2, 1), value: 1 },
+        ];
+
+        // Along X the first box doesn't match the third until it's joined with the second along Y.
+        assert_eq!(stitch(boxes.clone(), DEFAULT_MAX_BOX_SIZE).len(), 2);
+        assert_eq!(weld(boxes, (2, 2, 1), DEFAULT_MAX_BOX_SIZE), vec![FilledBox { pos: (0, 0, 0), size: (2, 2, 1), value: 1 }]);
+
+        // Filling height first grows the first box across the S and leaves three, where growing length first leaves two.
+        let mut grid = vec![None; 6];
+        for pos in [(0, 1, 0), (0, 2, 0), (1, 0, 0), (1, 1, 0)] {
+            grid[get_index((2, 3, 1), pos)] = Some(1);
+        }
+
+        let boxes = fill(&grid, (2, 3, 1), FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE);
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(weld(boxes, (2, 3, 1), DEFAULT_MAX_BOX_SIZE).len(), 2);
+
+        // Colors are refilled apart, and boxes no order can improve on are kept.
+        let other = FilledBox { pos: (5, 5, 5), size: (2, 1, 1), value: 2 };
+        let boxes = [fill(&grid, (2, 3, 1), FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE), vec![other]].concat();
+        let welded = weld(boxes, (8, 8, 8), DEFAULT_MAX_BOX_SIZE);
+        assert_eq!(welded.len(), 3);
+        assert!(welded.contains(&other));
+    }
+
+    #[test]
+    fn cancelled_fill_gives_up() {
+        let grid_size = (10, 10, 10);
+        let grid = vec![Some(1); 1000];
+
+        assert!(fill_until(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE, &|| true).is_none());
+        assert!(fill_until(&grid, grid_size, FillStrategy::FewestBricks, DEFAULT_MAX_BOX_SIZE, &|| true).is_none());
+        assert_eq!(fill_until(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE, &|| false).map(|b| b.len()), Some(1));
+    }
+
+    #[test]
+    fn solid_cube_is_never_worse() {
+        let grid_size = (70, 70, 70);
+        let grid = vec![Some(1); 70 * 70 * 70];
+
+        let height_first = fill(&grid, grid_size, FillStrategy::HeightFirst, DEFAULT_MAX_BOX_SIZE).len();
+        let fewest = fill(&grid, grid_size, FillStrategy::FewestBricks, DEFAULT_MAX_BOX_SIZE).len();
+
+        assert!(fewest <= height_first);
+    }
+
+    #[test]
+    fn best_grow_order_beats_height_first() {
+        // A 3x3 floor with a 3 tall tower in one corner.
+        let grid_size = (3, 3, 3);
+        let mut grid = SparseGrid::new(grid_size);
+        for x in 0..3 {
+            for y in 0..3 {
+                grid.set((x, y, 0), Some(1));
+            }
+        }
+        grid.set((0, 0, 1), Some(1));
+        grid.set((0, 0, 2), Some(1));
+
+        let order = grid.best_grow_order(DEFAULT_MAX_BOX_SIZE);
+        let fill = |strategy| grid.fill_until(strategy, DEFAULT_MAX_BOX_SIZE, &|| false).unwrap().len();
+
+        assert_ne!(order, HEIGHT_FIRST);
+        assert_eq!(fill(FillStrategy::HeightFirst), 3);
+        assert_eq!(fill(FillStrategy::Order(order)), 2);
+
+        assert_eq!(SparseGrid::new(grid_size).best_grow_order(DEFAULT_MAX_BOX_SIZE), HEIGHT_FIRST);
+    }
+
+    #[test]
+    fn remove_interior_leaves_shell() {
+        let grid_size = (10, 10, 10);
+        let mut grid = vec![Some(1); 1000];
+
+        // Every cell but the 8x8x8 core is on the outside.
+        assert_eq!(hollow(&mut grid, grid_size, HollowMode::RemoveInterior), 8 * 8 * 8);
+        assert_eq!(grid.iter().filter(|cell| cell.is_some()).count(), 1000 - 8 * 8 * 8);
+
+        for_each_cell(grid_size, |(x, y, z)| {
+            let on_shell = [x, y, z].iter().any(|&c| c == 0 || c == 9);
+            assert_eq!(grid[get_index(grid_size, (x, y, z))].is_some(), on_shell);
+        });
+    }
+
+    #[test]
+    fn fill_interior_fills_enclosed_pockets() {
+        let grid_size = (10, 10, 10);
+        let mut hollow_cube = vec![Some(1); 1000];
+        remove_interior(&mut hollow_cube, grid_size);
+
+        // An open box with no lid has nothing enclosed.
+        let mut open_box = hollow_cube.clone();
+        for x in 1..9 {
+            for y in 1..9 {
+                open_box[get_index(grid_size, (x, y, 9))] = None;
+            }
+        }
+
+        assert_eq!(hollow(&mut open_box, grid_size, HollowMode::FillInterior), 0);
+        assert_eq!(hollow(&mut hollow_cube, grid_size, HollowMode::FillInterior), 8 * 8 * 8);
+        assert!(hollow_cube.iter().all(|cell| *cell == Some(1)));
+    }
+}
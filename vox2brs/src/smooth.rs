@@ -0,0 +1,156 @@
+//! Edge smoothing: wedges on single steps, leaving the rest of the build as bricks.
+//!
+//! A lighter alternative to rampifying. Only empty cells sitting in front of a step one cell
+//! tall get a wedge, so the grid itself is left alone and still simplified into bricks.
+
+use brickadia::save::{Brick, BrickColor, Direction, Rotation, Size};
+use crate::simplify::{Cell, GridPos};
+
+/// Horizontal directions a step can face, with the rotation of a wedge sloping down toward them.
+const SLOPES: [((i64, i64), Rotation); 4] = [
+    ((1, 0), Rotation::Deg0),
+    ((0, 1), Rotation::Deg90),
+    ((-1, 0), Rotation::Deg180),
+    ((0, -1), Rotation::Deg270),
+];
+
+fn get_index(grid_size: GridPos, pos: GridPos) -> usize {
+    pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
+}
+
+/// Color of the cell at `pos` moved by `offset`, or `None` if it's empty or outside the grid.
+fn cell_at(grid: &[Cell], grid_size: GridPos, pos: GridPos, offset: (i64, i64, i64)) -> Cell {
+    let moved = |p: usize, o: i64, size: usize| {
+        let p = p as i64 + o;
+        (0..size as i64).contains(&p).then(|| p as usize)
+    };
+
+    let pos = (
+        moved(pos.0, offset.0, grid_size.0)?,
+        moved(pos.1, offset.1, grid_size.1)?,
+        moved(pos.2, offset.2, grid_size.2)?,
+    );
+
+    grid[get_index(grid_size, pos)]
+}
+
+/// Finds empty cells in front of a single step, returning each with the rotation of the
+/// wedge that fills it and the color of the step.
+///
+/// A cell qualifies when it rests on a filled cell, has a filled cell behind it with nothing
+/// on top, and is open in front. Cells in front of steps in more than one direction, like
+/// inside corners, are left empty.
+pub fn find_steps(grid: &[Cell], grid_size: GridPos) -> Vec<(GridPos, Rotation, u16)> {
+    let mut steps = vec![];
+
+    for z in 1..grid_size.2 {
+        for y in 0..grid_size.1 {
+            for x in 0..grid_size.0 {
+                let pos = (x, y, z);
+
+                if grid[get_index(grid_size, pos)].is_some() || cell_at(grid, grid_size, pos, (0, 0, -1)).is_none() {
+                    continue;
+                }
+
+                let mut found = SLOPES.iter().filter_map(|((dx, dy), rotation)| {
+                    let behind = cell_at(grid, grid_size, pos, (-dx, -dy, 0))?;
+
+                    let single_step = cell_at(grid, grid_size, pos, (-dx, -dy, 1)).is_none();
+                    let open_front = cell_at(grid, grid_size, pos, (*dx, *dy, 0)).is_none();
+
+                    (single_step && open_front).then(|| (rotation.clone(), behind))
+                });
+
+                if let (Some((rotation, value)), None) = (found.next(), found.next()) {
+                    steps.push((pos, rotation, value));
+                }
+            }
+        }
+    }
+
+    steps
+}
+
+/// Makes wedges smoothing the single steps in `grid`, positioned like the simplifier's bricks.
+///
+/// `cell` is the half size of a grid cell along X, Y and Z.
+pub fn step_wedges(grid: &[Cell], grid_size: GridPos, cell: (i32, i32, i32), asset: u32) -> Vec<Brick> {
+    find_steps(grid, grid_size)
+        .into_iter()
+        .map(|((x, y, z), rotation, value)| {
+            // Sizes are along the wedge's own axes, which a quarter turn swaps.
+            let size = match rotation {
+                Rotation::Deg0 | Rotation::Deg180 => (cell.0, cell.1, cell.2),
+                Rotation::Deg90 | Rotation::Deg270 => (cell.1, cell.0, cell.2),
+            };
+
+            Brick {
+                asset_name_index: asset,
+                size: Size::Procedural(size.0 as u32, size.1 as u32, size.2 as u32),
+                position: (
+                    x as i32 * cell.0 * 2 + cell.0,
+                    y as i32 * cell.1 * 2 + cell.1,
+                    z as i32 * cell.2 * 2 + cell.2,
+                ),
+                direction: Direction::ZPositive,
+                rotation,
+                color: BrickColor::Index(value as u32),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A grid one cell deep along Y, with columns of the given heights along X.
+    fn columns(heights: &[usize], grid_height: usize) -> (Vec<Cell>, GridPos) {
+        let grid_size = (heights.len(), 1, grid_height);
+        let mut grid = vec![None; heights.len() * grid_height];
+
+        for (x, &height) in heights.iter().enumerate() {
+            for z in 0..height {
+                grid[get_index(grid_size, (x, 0, z))] = Some(x as u16);
+            }
+        }
+
+        (grid, grid_size)
+    }
+
+    #[test]
+    fn single_steps_get_wedges() {
+        let (grid, grid_size) = columns(&[2, 1, 1], 3);
+        assert_eq!(find_steps(&grid, grid_size), vec![((1, 0, 1), Rotation::Deg0, 0)]);
+
+        let (grid, grid_size) = columns(&[1, 1, 2], 3);
+        assert_eq!(find_steps(&grid, grid_size), vec![((1, 0, 1), Rotation::Deg180, 2)]);
+    }
+
+    #[test]
+    fn tall_steps_and_flat_ground_are_left_alone() {
+        let (grid, grid_size) = columns(&[3, 1, 1], 4);
+        assert!(find_steps(&grid, grid_size).is_empty());
+
+        let (grid, grid_size) = columns(&[1, 1, 1], 2);
+        assert!(find_steps(&grid, grid_size).is_empty());
+    }
+
+    #[test]
+    fn valleys_between_steps_are_left_alone() {
+        let (grid, grid_size) = columns(&[2, 1, 2], 3);
+        assert!(find_steps(&grid, grid_size).is_empty());
+    }
+
+    #[test]
+    fn wedges_turn_with_their_slope() {
+        let (grid, grid_size) = columns(&[2, 1], 2);
+        let wedges = step_wedges(&grid, grid_size, (5, 3, 2), 4);
+
+        assert_eq!(wedges.len(), 1);
+        assert_eq!(wedges[0].position, (15, 3, 6));
+        assert_eq!(wedges[0].size, Size::Procedural(5, 3, 2));
+        assert_eq!(wedges[0].asset_name_index, 4);
+    }
+}
@@ -0,0 +1,487 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use brickadia::save::Color;
+use create_vox::VoxFile;
+use crate::magica::{self, ModelInstance};
+use crate::qb::QbFile;
+use crate::schematic::SchematicFile;
+use crate::vxm::VxmFile;
+
+/// A single voxel inside a model.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourceVoxel {
+    pub position: (u32, u32, u32),
+
+    /// Index into the source's palette.
+    pub color_index: u32,
+}
+
+/// A model as read from a voxel file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceModel {
+    pub size: (u32, u32, u32),
+    pub voxels: Vec<SourceVoxel>,
+
+    /// Position of the model's center.
+    pub position: Option<(i32, i32, i32)>,
+
+    /// MagicaVoxel packed rotation byte.
+    pub rotation: Option<u8>,
+}
+
+/// Another placement of an existing model.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourceCopy {
+    /// Index into the source's models.
+    pub model_index: usize,
+    pub position: Option<(i32, i32, i32)>,
+    pub rotation: Option<u8>,
+}
+
+/// MagicaVoxel's material types.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaterialKind {
+    Diffuse,
+    Metal,
+    Glass,
+    Emit,
+    Blend,
+    Media,
+}
+
+/// Material settings of a palette entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMaterial {
+    /// Index into the source's palette.
+    pub color_index: u32,
+    pub kind: MaterialKind,
+
+    /// Emission strength, from 0 to 1.
+    pub emit: f32,
+
+    /// Emission power, from 0 to 4.
+    pub flux: f32,
+
+    /// Opacity, from 0 for invisible to 1 for opaque. Multiplies the palette color's alpha.
+    pub alpha: f32,
+}
+
+/// Anything vox2brs can read voxels from.
+pub trait VoxelSource {
+    fn palette(&self) -> Vec<Color>;
+    fn models(&self) -> Vec<SourceModel>;
+    fn copies(&self) -> Vec<SourceCopy>;
+
+    fn materials(&self) -> Vec<SourceMaterial> {
+        vec![]
+    }
+
+    /// Name or note stored in the file, if it has one.
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// Notes the author left in the file.
+    fn notes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Orientation of each of a model's voxels as a MagicaVoxel packed rotation byte, in the
+    /// same order as its voxels, for formats whose voxels face a direction.
+    ///
+    /// MagicaVoxel files only store orientations per model, so by default voxels face up.
+    fn voxel_rotations(&self, _model_index: usize) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl<T: VoxelSource + ?Sized> VoxelSource for Box<T> {
+    fn palette(&self) -> Vec<Color> {
+        (**self).palette()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        (**self).models()
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        (**self).copies()
+    }
+
+    fn materials(&self) -> Vec<SourceMaterial> {
+        (**self).materials()
+    }
+
+    fn name(&self) -> Option<String> {
+        (**self).name()
+    }
+
+    fn notes(&self) -> Vec<String> {
+        (**self).notes()
+    }
+
+    fn voxel_rotations(&self, model_index: usize) -> Option<Vec<u8>> {
+        (**self).voxel_rotations(model_index)
+    }
+}
+
+impl VoxelSource for VoxFile {
+    fn palette(&self) -> Vec<Color> {
+        self.palette
+            .iter()
+            .map(|c| Color { r: c.r, g: c.g, b: c.b, a: c.a })
+            .collect()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        self.models
+            .iter()
+            .map(|model| SourceModel {
+                size: (model.size.0 as u32, model.size.1 as u32, model.size.2 as u32),
+                voxels: model.voxels
+                    .iter()
+                    .map(|voxel| SourceVoxel {
+                        position: (voxel.position.0 as u32, voxel.position.1 as u32, voxel.position.2 as u32),
+                        // MagicaVoxel color indices start at 1.
+                        color_index: voxel.color_index as u32 - 1,
+                    })
+                    .collect(),
+                position: model.position,
+                rotation: model.rotation,
+            })
+            .collect()
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        self.copies
+            .iter()
+            .filter_map(|model_copy| {
+                let model = self.get_model_by_id(model_copy.model_id)?;
+                let model_index = self.models.iter().position(|m| std::ptr::eq(m, model))?;
+
+                Some(SourceCopy {
+                    model_index,
+                    position: model_copy.position,
+                    rotation: model_copy.rotation,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A MagicaVoxel file, read straight from its bytes.
+pub struct MagicaVoxelFile {
+    pub palette: Vec<Color>,
+
+    /// Models in file order, before they're placed in the scene.
+    pub models: Vec<SourceModel>,
+    pub materials: Vec<SourceMaterial>,
+
+    /// Every model placement in the scene graph, with parent transforms applied.
+    pub instances: Vec<ModelInstance>,
+
+    /// Names given to nodes in the scene graph.
+    pub names: Vec<String>,
+
+    /// Notes written beside palette rows.
+    pub notes: Vec<String>,
+}
+
+impl MagicaVoxelFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&fs::read(path)?)
+    }
+
+    /// Reads a `.vox` file from memory, for callers without a filesystem.
+    pub fn read(bytes: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            palette: magica::read_palette(bytes)?,
+            models: magica::read_models(bytes)?,
+            materials: magica::read_materials(bytes)?,
+            instances: magica::read_instances(bytes)?,
+            names: magica::read_names(bytes)?,
+            notes: magica::read_notes(bytes)?,
+        })
+    }
+}
+
+impl VoxelSource for MagicaVoxelFile {
+    fn palette(&self) -> Vec<Color> {
+        self.palette.clone()
+    }
+
+    /// Each model is placed at its first instance in the scene graph.
+    fn models(&self) -> Vec<SourceModel> {
+        let mut models = self.models.clone();
+
+        if self.instances.is_empty() {
+            return models;
+        }
+
+        for (model_index, model) in models.iter_mut().enumerate() {
+            let instance = self.instances.iter().find(|instance| instance.model_id == model_index);
+
+            model.position = instance.map(|instance| instance.position);
+            model.rotation = instance.map(|instance| instance.rotation);
+        }
+
+        models
+    }
+
+    /// Every instance after a model's first one becomes a copy.
+    fn copies(&self) -> Vec<SourceCopy> {
+        if self.instances.is_empty() {
+            return vec![];
+        }
+
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|(i, instance)| self.instances[..*i].iter().any(|previous| previous.model_id == instance.model_id))
+            .map(|(_, instance)| SourceCopy {
+                model_index: instance.model_id,
+                position: Some(instance.position),
+                rotation: Some(instance.rotation),
+            })
+            .collect()
+    }
+
+    fn materials(&self) -> Vec<SourceMaterial> {
+        self.materials.clone()
+    }
+
+    fn name(&self) -> Option<String> {
+        if self.names.is_empty() {
+            return None;
+        }
+        Some(self.names.join(", "))
+    }
+
+    fn notes(&self) -> Vec<String> {
+        self.notes.clone()
+    }
+}
+
+/// Several sources merged into one, each moved by an offset.
+///
+/// Palettes are appended in order, and color indices are remapped to match.
+#[derive(Default)]
+pub struct CombinedSource {
+    sources: Vec<(Box<dyn VoxelSource>, (i32, i32, i32))>,
+}
+
+impl CombinedSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source, moving its models by `offset` voxels.
+    pub fn push(&mut self, source: Box<dyn VoxelSource>, offset: (i32, i32, i32)) {
+        self.sources.push((source, offset));
+    }
+
+    /// Where each source's colors and models start in the combined palette and models.
+    fn starts(&self) -> Vec<(u32, usize)> {
+        let mut starts = vec![];
+        let mut color_start = 0;
+        let mut model_start = 0;
+
+        for (source, _) in &self.sources {
+            starts.push((color_start, model_start));
+            color_start += source.palette().len() as u32;
+            model_start += source.models().len();
+        }
+
+        starts
+    }
+}
+
+fn offset_position(position: Option<(i32, i32, i32)>, offset: (i32, i32, i32)) -> Option<(i32, i32, i32)> {
+    let (x, y, z) = position.unwrap_or((0, 0, 0));
+    Some((x + offset.0, y + offset.1, z + offset.2))
+}
+
+impl VoxelSource for CombinedSource {
+    fn palette(&self) -> Vec<Color> {
+        self.sources.iter().flat_map(|(source, _)| source.palette()).collect()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        let mut models = vec![];
+
+        for ((source, offset), (color_start, _)) in self.sources.iter().zip(self.starts()) {
+            for mut model in source.models() {
+                for voxel in &mut model.voxels {
+                    voxel.color_index += color_start;
+                }
+                model.position = offset_position(model.position, *offset);
+                models.push(model);
+            }
+        }
+
+        models
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        let mut copies = vec![];
+
+        for ((source, offset), (_, model_start)) in self.sources.iter().zip(self.starts()) {
+            for mut model_copy in source.copies() {
+                model_copy.model_index += model_start;
+                model_copy.position = offset_position(model_copy.position, *offset);
+                copies.push(model_copy);
+            }
+        }
+
+        copies
+    }
+
+    fn materials(&self) -> Vec<SourceMaterial> {
+        let mut materials = vec![];
+
+        for ((source, _), (color_start, _)) in self.sources.iter().zip(self.starts()) {
+            for mut material in source.materials() {
+                material.color_index += color_start;
+                materials.push(material);
+            }
+        }
+
+        materials
+    }
+
+    fn name(&self) -> Option<String> {
+        let names: Vec<String> = self.sources.iter().filter_map(|(source, _)| source.name()).collect();
+
+        if names.is_empty() {
+            return None;
+        }
+        Some(names.join(", "))
+    }
+
+    fn notes(&self) -> Vec<String> {
+        self.sources.iter().flat_map(|(source, _)| source.notes()).collect()
+    }
+
+    fn voxel_rotations(&self, model_index: usize) -> Option<Vec<u8>> {
+        let starts = self.starts();
+
+        // The last source whose models start at or before the index holds the model.
+        let (source, model_start) = self.sources
+            .iter()
+            .zip(starts)
+            .rev()
+            .find(|(_, (_, model_start))| *model_start <= model_index)
+            .map(|((source, _), (_, model_start))| (source, model_start))?;
+
+        source.voxel_rotations(model_index - model_start)
+    }
+}
+
+/// Describes a save converted from the voxel file at `path`, including the file's name and notes if it has them.
+pub fn default_description(path: &Path, source: &dyn VoxelSource) -> String {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    let description = match source.name() {
+        Some(name) => format!("Converted from {} ({}).", file_name, name),
+        None => format!("Converted from {}.", file_name),
+    };
+
+    let notes = source.notes();
+
+    if notes.is_empty() {
+        return description;
+    }
+    format!("{} Notes: {}.", description, notes.join("; "))
+}
+
+/// File extensions that `load_source` understands.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["vox", "qb", "vxm", "schematic"];
+
+/// Reads a voxel file from memory, picking the reader by the file's contents.
+///
+/// Gzipped data is read as a Minecraft schematic, and anything that doesn't start with
+/// MagicaVoxel's `VOX ` or VoxEdit's `VXM` header is read as a Qubicle file.
+pub fn read_source(bytes: &[u8]) -> io::Result<Box<dyn VoxelSource>> {
+    if bytes.starts_with(b"VOX ") {
+        Ok(Box::new(MagicaVoxelFile::read(bytes)?))
+    } else if bytes.starts_with(b"VXM") {
+        Ok(Box::new(VxmFile::read(&mut &bytes[..])?))
+    } else if bytes.starts_with(&[0x1F, 0x8B]) {
+        Ok(Box::new(SchematicFile::read(bytes)?))
+    } else {
+        Ok(Box::new(QbFile::read(&mut &bytes[..])?))
+    }
+}
+
+/// Loads a voxel file, picking the loader by the file's extension.
+pub fn load_source(path: &Path) -> io::Result<Box<dyn VoxelSource>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("vox") => Ok(Box::new(MagicaVoxelFile::load(path)?)),
+        Some("qb") => Ok(Box::new(QbFile::load(path)?)),
+        Some("vxm") => Ok(Box::new(VxmFile::load(path)?)),
+        Some("schematic") => Ok(Box::new(SchematicFile::load(path)?)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported voxel file format.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::save::BrickColor;
+
+    fn single_voxel_file(color: Color) -> QbFile {
+        QbFile {
+            palette: vec![color],
+            models: vec![SourceModel {
+                size: (1, 1, 1),
+                voxels: vec![SourceVoxel { position: (0, 0, 0), color_index: 0 }],
+                position: None,
+                rotation: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn combined_sources_remap_colors() {
+        let mut combined = CombinedSource::new();
+        combined.push(Box::new(single_voxel_file(Color { r: 255, g: 0, b: 0, a: 255 })), (0, 0, 0));
+        combined.push(Box::new(single_voxel_file(Color { r: 0, g: 0, b: 255, a: 255 })), (5, 0, 0));
+
+        let palette: Vec<_> = combined.palette().iter().map(|c| (c.r, c.g, c.b)).collect();
+        assert_eq!(palette, vec![(255, 0, 0), (0, 0, 255)]);
+
+        let models = combined.models();
+        assert_eq!(models.len(), 2);
+
+        // Each voxel still points at its own file's color.
+        let blue = &models[1];
+        assert_eq!(blue.voxels[0].color_index, 1);
+        assert_eq!(palette[blue.voxels[0].color_index as usize], (0, 0, 255));
+        assert_eq!(blue.position, Some((5, 0, 0)));
+
+        // And the second file's brick comes out blue, at its offset.
+        let options = crate::Vox2BrsOptions::default().mode(crate::BrickOutputMode::MicroBrick).width(1).height(1);
+        let save = crate::new_save(options.author.clone(), String::new());
+        let (save, _) = crate::vox2brs(combined, save, options).unwrap();
+
+        let mut bricks: Vec<_> = save.bricks
+            .iter()
+            .map(|brick| match brick.color {
+                BrickColor::Index(index) => (brick.position.0, index),
+                BrickColor::Unique(_) => panic!("expected a palette color"),
+            })
+            .collect();
+        bricks.sort();
+
+        // Bricks are centered on their cell, at twice the cell position.
+        assert_eq!(bricks, vec![(1, 0), (11, 1)]);
+        let blue = &save.header2.colors[1];
+        assert_eq!((blue.r, blue.g, blue.b), (0, 0, 255));
+    }
+}
@@ -0,0 +1,193 @@
+//! Small pictures of a save's bricks, for checking a conversion at a glance.
+//! Only built with the `thumbnail` feature, as it needs the `image` crate.
+
+use std::path::Path;
+use brickadia::save::{BrickColor, Color, SaveData, Size};
+use image::{ImageError, Rgba, RgbaImage};
+
+/// Width and height of each view in a thumbnail, in pixels.
+pub const VIEW_SIZE: u32 = 256;
+
+/// Empty pixels around the bricks in each view.
+const MARGIN: f32 = 8.0;
+
+/// A brick's corners in Brickadia units, and its color.
+struct BrickBox {
+    min: [f32; 3],
+    max: [f32; 3],
+    color: Color,
+}
+
+impl BrickBox {
+    fn center(&self, axis: usize) -> f32 {
+        (self.min[axis] + self.max[axis]) / 2.0
+    }
+}
+
+/// Boxes of the save's procedural bricks. Like `brick_bounds`, sizes are taken as they are,
+/// without turning them by the brick's direction.
+fn brick_boxes(save: &SaveData) -> Vec<BrickBox> {
+    save.bricks
+        .iter()
+        .filter_map(|brick| {
+            let (w, l, h) = match brick.size {
+                Size::Procedural(w, l, h) => (w as f32, l as f32, h as f32),
+                _ => return None,
+            };
+
+            let color = match &brick.color {
+                BrickColor::Index(index) => save.header2.colors.get(*index as usize)?.clone(),
+                BrickColor::Unique(color) => color.clone(),
+            };
+
+            let (x, y, z) = (brick.position.0 as f32, brick.position.1 as f32, brick.position.2 as f32);
+
+            Some(BrickBox { min: [x - w, y - l, z - h], max: [x + w, y + l, z + h], color })
+        })
+        .collect()
+}
+
+fn shade(color: &Color, light: f32) -> Rgba<u8> {
+    let channel = |value: u8| (value as f32 * light).round() as u8;
+    Rgba([channel(color.r), channel(color.g), channel(color.b), color.a])
+}
+
+/// Maps points onto a view `VIEW_SIZE` pixels wide starting at `left`, fitting `points` inside its margin.
+fn fit(points: impl Iterator<Item = (f32, f32)>, left: u32) -> impl Fn((f32, f32)) -> (f32, f32) {
+    let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+
+    for (u, v) in points {
+        min = (min.0.min(u), min.1.min(v));
+        max = (max.0.max(u), max.1.max(v));
+    }
+
+    let span = (max.0 - min.0).max(max.1 - min.1).max(1.0);
+    let scale = (VIEW_SIZE as f32 - MARGIN * 2.0) / span;
+    let center = ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0);
+    let half = VIEW_SIZE as f32 / 2.0;
+
+    move |(u, v)| (left as f32 + half + (u - center.0) * scale, half + (v - center.1) * scale)
+}
+
+/// Fills a convex quad with `color`, leaving out pixels past `left + VIEW_SIZE`.
+fn fill_quad(image: &mut RgbaImage, left: u32, corners: [(f32, f32); 4], color: Rgba<u8>) {
+    let min_x = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor().max(left as f32) as u32;
+    let max_x = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max).ceil().min((left + VIEW_SIZE) as f32) as u32;
+    let min_y = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+    let max_y = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max).ceil().min(image.height() as f32) as u32;
+
+    // Inside when the pixel's center is on the same side of every edge.
+    let side = |(x, y): (f32, f32), a: (f32, f32), b: (f32, f32)| (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0);
+
+    for py in min_y..max_y {
+        for px in min_x..max_x {
+            let center = (px as f32 + 0.5, py as f32 + 0.5);
+            let sides: Vec<f32> = (0..4).map(|i| side(center, corners[i], corners[(i + 1) % 4])).collect();
+
+            if sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0) {
+                image.put_pixel(px, py, color);
+            }
+        }
+    }
+}
+
+/// Draws the bricks as seen from the -Y side, X to the right and Z up.
+fn draw_front(image: &mut RgbaImage, boxes: &mut Vec<BrickBox>, left: u32) {
+    // Z is up in Brickadia but down in the image.
+    let to_view = fit(boxes.iter().flat_map(|b| [(b.min[0], -b.min[2]), (b.max[0], -b.max[2])]), left);
+
+    // Furthest first, so nearer bricks are painted over them.
+    boxes.sort_by(|a, b| b.center(1).total_cmp(&a.center(1)));
+
+    for b in boxes.iter() {
+        let (x0, y0) = to_view((b.min[0], -b.max[2]));
+        let (x1, y1) = to_view((b.max[0], -b.min[2]));
+        fill_quad(image, left, [(x0, y0), (x1, y0), (x1, y1), (x0, y1)], shade(&b.color, 0.9));
+    }
+}
+
+/// Draws the bricks in isometric projection, seen from the +X +Y corner above them.
+fn draw_iso(image: &mut RgbaImage, boxes: &mut Vec<BrickBox>, left: u32) {
+    let (cos, sin) = (30f32.to_radians().cos(), 30f32.to_radians().sin());
+    let project = |[x, y, z]: [f32; 3]| ((x - y) * cos, (x + y) * sin - z);
+
+    let corners = |b: &BrickBox| {
+        let mut corners = vec![];
+        for x in [b.min[0], b.max[0]] {
+            for y in [b.min[1], b.max[1]] {
+                for z in [b.min[2], b.max[2]] {
+                    corners.push(project([x, y, z]));
+                }
+            }
+        }
+        corners
+    };
+
+    let to_view = fit(boxes.iter().flat_map(corners), left);
+
+    // Further from the corner the bricks are seen from first.
+    boxes.sort_by(|a, b| {
+        let depth = |b: &BrickBox| b.center(0) + b.center(1) + b.center(2);
+        depth(a).total_cmp(&depth(b))
+    });
+
+    for b in boxes.iter() {
+        let ([x0, y0, z0], [x1, y1, z1]) = (b.min, b.max);
+        let quad = |points: [[f32; 3]; 4]| points.map(|point| to_view(project(point)));
+
+        fill_quad(image, left, quad([[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]]), shade(&b.color, 1.0));
+        fill_quad(image, left, quad([[x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1]]), shade(&b.color, 0.8));
+        fill_quad(image, left, quad([[x0, y1, z0], [x1, y1, z0], [x1, y1, z1], [x0, y1, z1]]), shade(&b.color, 0.65));
+    }
+}
+
+/// Renders a front view and an isometric view of the save's bricks side by side, each
+/// `VIEW_SIZE` pixels square, on a transparent background.
+pub fn render_thumbnail(save: &SaveData) -> RgbaImage {
+    let mut image = RgbaImage::new(VIEW_SIZE * 2, VIEW_SIZE);
+    let mut boxes = brick_boxes(save);
+
+    if boxes.is_empty() {
+        return image;
+    }
+
+    draw_front(&mut image, &mut boxes, 0);
+    draw_iso(&mut image, &mut boxes, VIEW_SIZE);
+
+    image
+}
+
+/// Renders the save's thumbnail and writes it to `path` as a PNG.
+pub fn write_thumbnail(save: &SaveData, path: &Path) -> Result<(), ImageError> {
+    render_thumbnail(save).save_with_format(path, image::ImageFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::save::Brick;
+
+    #[test]
+    fn thumbnail_shows_both_views() {
+        let mut save = SaveData::default();
+        let color = Color { r: 200, g: 100, b: 50, a: 255 };
+
+        save.bricks.push(Brick {
+            size: Size::Procedural(5, 5, 6),
+            position: (0, 0, 6),
+            color: BrickColor::Unique(color.clone()),
+            ..Brick::default()
+        });
+
+        let image = render_thumbnail(&save);
+        let middle = VIEW_SIZE / 2;
+
+        assert_eq!(image.dimensions(), (VIEW_SIZE * 2, VIEW_SIZE));
+        assert_eq!(*image.get_pixel(middle, middle), shade(&color, 0.9));
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+        assert_eq!(image.get_pixel(VIEW_SIZE + middle, middle)[3], 255);
+        assert_eq!(image.get_pixel(VIEW_SIZE, 0)[3], 0);
+
+        assert!(render_thumbnail(&SaveData::default()).pixels().all(|pixel| pixel[3] == 0));
+    }
+}
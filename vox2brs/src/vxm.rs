@@ -0,0 +1,297 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use brickadia::save::Color;
+use crate::source::{MaterialKind, SourceCopy, SourceMaterial, SourceModel, SourceVoxel, VoxelSource};
+
+/// Material index of empty runs of voxels.
+const EMPTY: u8 = 0xFF;
+
+/// A VoxEdit / Sandbox (`.vxm`) file, versions 4 to 12.
+///
+/// After the `VXM` magic and a version character, a file holds:
+/// - the model's size, Y-up;
+/// - its pivot from version 5, its surface bounds from version 9, and its LOD scale and pivot
+///   from version 8, all skipped;
+/// - baked LOD meshes, skipped;
+/// - up to 255 materials, each an RGBA color and an emissive flag;
+/// - from version 12, a count of layers, each with a name and a visibility flag;
+/// - the voxels of each layer, as runs of one material ending with a run of length 0.
+///
+/// Each visible layer becomes a model. Models are converted to MagicaVoxel's Z-up coordinates.
+#[derive(Debug, Clone)]
+pub struct VxmFile {
+    pub palette: Vec<Color>,
+
+    /// Whether each palette entry is emissive.
+    pub emissive: Vec<bool>,
+
+    pub models: Vec<SourceModel>,
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(len), &mut io::sink())?;
+
+    if skipped < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "File ended early."));
+    }
+
+    Ok(())
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let mut bytes = vec![];
+
+    loop {
+        match read_u8(reader)? {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl VxmFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic[..3] != b"VXM" {
+            return Err(invalid_data("Not a .vxm file."));
+        }
+
+        let version = match magic[3] {
+            c @ b'0'..=b'9' => c - b'0',
+            c @ b'A'..=b'C' => c - b'A' + 10,
+            _ => return Err(invalid_data("Unknown .vxm version.")),
+        };
+
+        if !(4..=12).contains(&version) {
+            return Err(invalid_data("Only .vxm versions 4 to 12 can be read."));
+        }
+
+        let size = (read_u32(reader)?, read_u32(reader)?, read_u32(reader)?);
+
+        let len = (size.0 as u64) * (size.1 as u64) * (size.2 as u64);
+        if len > u32::MAX as u64 {
+            return Err(invalid_data("Model is too large."));
+        }
+
+        // Pivot.
+        if version >= 5 {
+            skip(reader, 3 * 4)?;
+        }
+
+        // Surface bounds and normal.
+        if version >= 9 && read_u8(reader)? != 0 {
+            skip(reader, 7 * 4)?;
+        }
+
+        // LOD scale and pivot.
+        let lod_levels = if version >= 8 {
+            skip(reader, 4 * 4)?;
+            read_u32(reader)?
+        } else {
+            1
+        };
+
+        // Baked meshes: an RGBA texture, then quads for each of the 6 faces.
+        for _ in 0..lod_levels {
+            let texture = read_u32(reader)? as u64 * read_u32(reader)? as u64;
+            skip(reader, texture * 4)?;
+
+            for _ in 0..6 {
+                let quads = read_u32(reader)? as u64;
+                skip(reader, quads * 20)?;
+            }
+        }
+
+        let material_count = read_u8(reader)?;
+        let mut palette = vec![];
+        let mut emissive = vec![];
+
+        for _ in 0..material_count {
+            let mut rgba = [0; 4];
+            reader.read_exact(&mut rgba)?;
+
+            palette.push(Color { r: rgba[0], g: rgba[1], b: rgba[2], a: rgba[3] });
+            emissive.push(read_u8(reader)? != 0);
+        }
+
+        let layer_count = if version >= 12 { read_u8(reader)? } else { 1 };
+        let mut models = vec![];
+
+        for _ in 0..layer_count {
+            let visible = if version >= 12 {
+                let _name = read_string(reader)?;
+                read_u8(reader)? != 0
+            } else {
+                true
+            };
+
+            let mut voxels = vec![];
+            let mut index = 0u64;
+
+            loop {
+                let run = read_u8(reader)? as u64;
+
+                if run == 0 {
+                    break;
+                }
+
+                let material = read_u8(reader)?;
+
+                if index + run > len {
+                    return Err(invalid_data("Voxels run past the end of the model."));
+                }
+
+                if material != EMPTY {
+                    if material >= material_count {
+                        return Err(invalid_data("Voxel uses a material that doesn't exist."));
+                    }
+
+                    for i in index..index + run {
+                        let x = i / (size.1 as u64 * size.2 as u64);
+                        let y = (i / size.2 as u64) % size.1 as u64;
+                        let z = i % size.2 as u64;
+
+                        voxels.push(SourceVoxel {
+                            position: (x as u32, z as u32, y as u32),
+                            color_index: material as u32,
+                        });
+                    }
+                }
+
+                index += run;
+            }
+
+            if visible {
+                models.push(SourceModel {
+                    size: (size.0, size.2, size.1),
+                    voxels,
+                    position: None,
+                    rotation: None,
+                });
+            }
+        }
+
+        Ok(Self { palette, emissive, models })
+    }
+}
+
+impl VoxelSource for VxmFile {
+    fn palette(&self) -> Vec<Color> {
+        self.palette.clone()
+    }
+
+    fn models(&self) -> Vec<SourceModel> {
+        self.models.clone()
+    }
+
+    fn copies(&self) -> Vec<SourceCopy> {
+        vec![]
+    }
+
+    fn materials(&self) -> Vec<SourceMaterial> {
+        self.emissive
+            .iter()
+            .enumerate()
+            .filter(|(_, &emissive)| emissive)
+            .map(|(index, _)| SourceMaterial {
+                color_index: index as u32,
+                kind: MaterialKind::Emit,
+                emit: 1.0,
+                flux: 0.0,
+                alpha: 1.0,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// A version 5 file of a 2x1x2 model: red, then an empty run, then a glowing blue.
+    fn fixture() -> Vec<u8> {
+        let mut bytes = b"VXM5".to_vec();
+
+        for value in [2, 1, 2] {
+            push_u32(&mut bytes, value);
+        }
+
+        // Pivot.
+        for value in [0.5f32; 3] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // A 1x1 texture and no quads.
+        push_u32(&mut bytes, 1);
+        push_u32(&mut bytes, 1);
+        bytes.extend_from_slice(&[0; 4]);
+        for _ in 0..6 {
+            push_u32(&mut bytes, 0);
+        }
+
+        bytes.push(2);
+        bytes.extend_from_slice(&[255, 0, 0, 255, 0]);
+        bytes.extend_from_slice(&[0, 0, 255, 255, 1]);
+
+        bytes.extend_from_slice(&[1, 0, 2, EMPTY, 1, 1, 0]);
+
+        bytes
+    }
+
+    #[test]
+    fn reads_voxels_and_palette() {
+        let vxm = VxmFile::read(&mut fixture().as_slice()).unwrap();
+
+        let palette: Vec<_> = vxm.palette.iter().map(|c| (c.r, c.g, c.b, c.a)).collect();
+        assert_eq!(palette, vec![(255, 0, 0, 255), (0, 0, 255, 255)]);
+
+        assert_eq!(vxm.models.len(), 1);
+        assert_eq!(vxm.models[0].size, (2, 2, 1));
+        assert_eq!(vxm.models[0].voxels, vec![
+            SourceVoxel { position: (0, 0, 0), color_index: 0 },
+            SourceVoxel { position: (1, 1, 0), color_index: 1 },
+        ]);
+
+        assert_eq!(vxm.materials().len(), 1);
+        assert_eq!(vxm.materials()[0].color_index, 1);
+    }
+
+    #[test]
+    fn rejects_runs_past_the_model() {
+        let mut bytes = fixture();
+        let end = bytes.len();
+        bytes[end - 3] = 2;
+
+        assert!(VxmFile::read(&mut bytes.as_slice()).is_err());
+        assert!(VxmFile::read(&mut &b"VXMZ"[..]).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Converts a committed .vox file all the way to a .brs file on disk and reads it back.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use brickadia::read::SaveReader;
+use brickadia::save::{BrickColor, SaveData};
+use vox2brs::source::{load_source, VoxelSource};
+use vox2brs::{new_save, vox2brs, write_save, BrickOutputMode, Vox2BrsOptions};
+
+const MODES: [BrickOutputMode; 4] = [
+    BrickOutputMode::Brick,
+    BrickOutputMode::Plate,
+    BrickOutputMode::MicroBrick,
+    BrickOutputMode::Tile,
+];
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/stairs.vox")
+}
+
+/// RGB of every color bricks in `save` use, leaving alpha out since it's only kept with an alpha threshold.
+fn brick_colors(save: &SaveData) -> BTreeSet<(u8, u8, u8)> {
+    save.bricks
+        .iter()
+        .map(|brick| match &brick.color {
+            BrickColor::Index(index) => {
+                let color = &save.header2.colors[*index as usize];
+                (color.r, color.g, color.b)
+            }
+            BrickColor::Unique(color) => (color.r, color.g, color.b),
+        })
+        .collect()
+}
+
+/// RGB of every color the fixture's voxels use.
+fn voxel_colors(source: &dyn VoxelSource) -> BTreeSet<(u8, u8, u8)> {
+    let palette = source.palette();
+
+    source
+        .models()
+        .iter()
+        .flat_map(|model| model.voxels.iter())
+        .map(|voxel| {
+            let color = &palette[voxel.color_index as usize];
+            (color.r, color.g, color.b)
+        })
+        .collect()
+}
+
+#[test]
+fn vox_round_trips_through_brs() {
+    let source = load_source(&fixture()).unwrap();
+    let expected_colors = voxel_colors(&*source);
+    assert_eq!(expected_colors.len(), 3);
+
+    for mode in MODES {
+        for (simplify, rampify) in [(false, false), (true, false), (false, true), (true, true)] {
+            let options = Vox2BrsOptions::default().mode(mode).simplify(simplify).rampify(rampify);
+            let save = new_save(options.author.clone(), "round trip".into());
+
+            let (save, stats) = vox2brs(load_source(&fixture()).unwrap(), save, options).unwrap();
+
+            let output = std::env::temp_dir().join(format!(
+                "vox2brs_round_trip_{}_{:?}_{}_{}.brs",
+                std::process::id(),
+                mode,
+                simplify,
+                rampify,
+            ));
+            write_save(save, &output).unwrap();
+            let read = SaveReader::new(File::open(&output).unwrap()).unwrap().read_all();
+            let _ = std::fs::remove_file(&output);
+            let read = read.unwrap();
+
+            let case = format!("{:?}, simplify {}, rampify {}", mode, simplify, rampify);
+            assert!(!read.bricks.is_empty(), "{}", case);
+            assert_eq!(read.bricks.len(), stats.brick_count, "{}", case);
+            assert_eq!(brick_colors(&read), expected_colors, "{}", case);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Places a model nested two groups deep in a .vox scene graph.
+//!
+//! The fixture is laid out the way MagicaVoxel saves groups: the root transform and group, a
+//! group transform turned 90 degrees around Z and moved 10 along X, an inner group, and the
+//! model's transform moved (3, 0, 2). Its layer, palette, material, render and camera chunks are
+//! copied from test.vox, which MagicaVoxel saved. The model is 3x1x1 with one voxel at (2, 0, 0).
+
+use std::path::{Path, PathBuf};
+use vox2brs::source::load_source;
+use vox2brs::{new_save, vox2brs, BrickOutputMode, Vox2BrsOptions};
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nested_groups.vox")
+}
+
+#[test]
+fn nested_voxel_lands_in_place() {
+    let options = Vox2BrsOptions::default().mode(BrickOutputMode::MicroBrick).width(1).height(1);
+    let save = new_save(options.author.clone(), "scene graph".into());
+
+    let (save, _) = vox2brs(load_source(&fixture()).unwrap(), save, options).unwrap();
+
+    // The voxel is one cell along X from the model's center, which the turn points along Y,
+    // so it lands at (10, 4, 2). Bricks are centered on their cell, at twice the cell position, with Y flipped.
+    assert_eq!(save.bricks.len(), 1);
+    assert_eq!(save.bricks[0].position, (21, -7, 5));
+}